@@ -1,7 +1,43 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use wasm_bindgen::prelude::*;
-use web_sys::{console, Document, Element, HtmlInputElement, Node};
+// Without the DOM, the UI-only helpers below have no callers
+#![cfg_attr(not(feature = "ui"), allow(dead_code))]
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[cfg(feature = "ui")]
+mod ui;
+
+/// Scale factor of the Q64.96 fixed-point format (2^96).
+const Q96: f64 = 79_228_162_514_264_337_593_543_950_336.0;
+
+/// Errors from constructing a pool state with invalid parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CpmmError {
+    NonPositiveLiquidity,
+    NotFiniteLiquidity,
+    NonPositivePrice,
+    NotFinitePrice,
+    InvalidPriceRange,
+    InvalidAmplification,
+    InvalidWeights,
+}
+
+impl fmt::Display for CpmmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            CpmmError::NonPositiveLiquidity => "Liquidity must be positive",
+            CpmmError::NotFiniteLiquidity => "Liquidity must be finite",
+            CpmmError::NonPositivePrice => "Price must be positive",
+            CpmmError::NotFinitePrice => "Price must be finite",
+            CpmmError::InvalidPriceRange => "Price range must satisfy 0 <= lower < upper",
+            CpmmError::InvalidAmplification => "Amplification must be positive and finite",
+            CpmmError::InvalidWeights => "Weights must lie in (0, 1) and sum to 1",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for CpmmError {}
 
 /// CPMM state for a liquidity pool.
 /// Uses the constant product invariant: x * y = k = L^2
@@ -13,10 +49,34 @@ struct CpmmState {
 }
 
 impl CpmmState {
-    fn new(liquidity: f64, price: f64) -> Self {
-        assert!(liquidity > 0.0, "Liquidity must be positive");
-        assert!(price > 0.0, "Price must be positive");
-        Self { liquidity, price }
+    fn new(liquidity: f64, price: f64) -> Result<Self, CpmmError> {
+        if liquidity.is_nan() || liquidity == f64::INFINITY {
+            return Err(CpmmError::NotFiniteLiquidity);
+        }
+        if liquidity <= 0.0 {
+            return Err(CpmmError::NonPositiveLiquidity);
+        }
+        if price.is_nan() || price == f64::INFINITY {
+            return Err(CpmmError::NotFinitePrice);
+        }
+        if price <= 0.0 {
+            return Err(CpmmError::NonPositivePrice);
+        }
+        Ok(Self { liquidity, price })
+    }
+
+    /// Decodes a Uniswap v3 style Q64.96 sqrtPriceX96 into a pool state.
+    /// sqrt(P) = sqrtPriceX96 / 2^96
+    #[allow(dead_code)]
+    fn from_sqrt_price_x96(sqrt_price_x96: u128, liquidity: f64) -> Result<Self, CpmmError> {
+        let sqrt_price = sqrt_price_x96 as f64 / Q96;
+        Self::new(liquidity, sqrt_price * sqrt_price)
+    }
+
+    /// Encodes the price as a Q64.96 sqrtPriceX96, rounded to the nearest unit.
+    #[allow(dead_code)]
+    fn to_sqrt_price_x96(self) -> u128 {
+        (self.price.sqrt() * Q96).round() as u128
     }
 
     /// Base reserves: x = L / sqrt(P)
@@ -29,639 +89,6196 @@ impl CpmmState {
         self.liquidity * self.price.sqrt()
     }
 
-    /// Invariant k = L^2 = x * y
+    /// Swaps an exact base input for quote.
+    /// The fee is taken from the input; the remainder enters the pool with k held constant.
+    /// Returns the quote received and the resulting pool state.
+    #[allow(dead_code)]
+    fn swap_exact_base_in(&self, base_in: f64, fee_fraction: f64) -> (f64, CpmmState) {
+        let k = self.liquidity * self.liquidity;
+        let base_after = self.base_reserves() + base_in * (1.0 - fee_fraction);
+        let quote_after = k / base_after;
+        let quote_out = self.quote_reserves() - quote_after;
+        let next = Self {
+            liquidity: self.liquidity,
+            price: quote_after / base_after,
+        };
+        (quote_out, next)
+    }
+
+    /// Swaps an exact quote input for base.
+    /// Mirror of `swap_exact_base_in`; returns the base received and the resulting pool state.
+    #[allow(dead_code)]
+    fn swap_exact_quote_in(&self, quote_in: f64, fee_fraction: f64) -> (f64, CpmmState) {
+        let k = self.liquidity * self.liquidity;
+        let quote_after = self.quote_reserves() + quote_in * (1.0 - fee_fraction);
+        let base_after = k / quote_after;
+        let base_out = self.base_reserves() - base_after;
+        let next = Self {
+            liquidity: self.liquidity,
+            price: quote_after / base_after,
+        };
+        (base_out, next)
+    }
+
+    /// Solves for the quote input needed to receive an exact base output.
+    /// The fee is grossed up on the input: quote_in = (k / (x - base_out) - y) / (1 - fee).
+    /// Returns None when the pool cannot provide base_out (base_out >= x).
+    #[allow(dead_code)]
+    fn swap_base_out(&self, base_out: f64, fee_fraction: f64) -> Option<(f64, CpmmState)> {
+        let base = self.base_reserves();
+        if !(0.0..base).contains(&base_out) {
+            return None;
+        }
+        let k = self.liquidity * self.liquidity;
+        let base_after = base - base_out;
+        let quote_after = k / base_after;
+        let quote_in = (quote_after - self.quote_reserves()) / (1.0 - fee_fraction);
+        let next = Self {
+            liquidity: self.liquidity,
+            price: quote_after / base_after,
+        };
+        Some((quote_in, next))
+    }
+
+    /// Solves for the base input needed to receive an exact quote output.
+    /// Mirror of `swap_base_out`; returns None when quote_out >= y.
     #[allow(dead_code)]
+    fn swap_quote_out(&self, quote_out: f64, fee_fraction: f64) -> Option<(f64, CpmmState)> {
+        let quote = self.quote_reserves();
+        if !(0.0..quote).contains(&quote_out) {
+            return None;
+        }
+        let k = self.liquidity * self.liquidity;
+        let quote_after = quote - quote_out;
+        let base_after = k / quote_after;
+        let base_in = (base_after - self.base_reserves()) / (1.0 - fee_fraction);
+        let next = Self {
+            liquidity: self.liquidity,
+            price: quote_after / base_after,
+        };
+        Some((base_in, next))
+    }
+
+    /// Invariant k = L^2 = x * y
     fn invariant(&self) -> f64 {
         self.liquidity * self.liquidity
     }
 }
 
-/// Computes wallet deltas and fee collection for a trade.
-/// The trader moves the pool from initial_state to final_state.
-/// Wallet deltas are from the trader's perspective (positive = received).
-/// Fees are collected on the input side and sent to treasury.
-#[derive(Clone, Copy, Debug)]
-struct TradeResult {
-    price_delta: f64,
-    base_wallet_delta: f64,
-    quote_wallet_delta: f64,
-    base_fee_collected: f64,
-    quote_fee_collected: f64,
+/// Spot price and reserves of a pool, whatever its curve.
+/// Lets trade accounting work on any pool model.
+trait PoolState {
+    fn price(&self) -> f64;
+    fn base_reserves(&self) -> f64;
+    fn quote_reserves(&self) -> f64;
 }
 
-impl TradeResult {
-    fn compute(initial: CpmmState, final_state: CpmmState, fee_fraction: f64) -> Self {
-        assert!(
-            (0.0..1.0).contains(&fee_fraction),
-            "Fee must be in [0, 1)"
-        );
-
-        let price_delta = final_state.price - initial.price;
-
-        // Pool reserve changes
-        let base_pool_delta = final_state.base_reserves() - initial.base_reserves();
-        let quote_pool_delta = final_state.quote_reserves() - initial.quote_reserves();
+impl PoolState for CpmmState {
+    fn price(&self) -> f64 {
+        self.price
+    }
 
-        // Wallet deltas are opposite of pool deltas (what leaves pool enters wallet)
-        // Before fees, gross amounts
-        let base_gross = -base_pool_delta;
-        let quote_gross = -quote_pool_delta;
+    fn base_reserves(&self) -> f64 {
+        CpmmState::base_reserves(self)
+    }
 
-        // Fee is collected on the input side (negative wallet delta means trader pays)
-        // If trader pays base (base_gross < 0), fee is on base
-        // If trader pays quote (quote_gross < 0), fee is on quote
-        let (base_fee, quote_fee) = if base_gross < 0.0 {
-            // Trader is selling base (paying base, receiving quote)
-            let fee = (-base_gross) * fee_fraction;
-            (fee, 0.0)
-        } else if quote_gross < 0.0 {
-            // Trader is buying base (paying quote, receiving base)
-            let fee = (-quote_gross) * fee_fraction;
-            (0.0, fee)
-        } else {
-            // No trade or edge case
-            (0.0, 0.0)
-        };
+    fn quote_reserves(&self) -> f64 {
+        CpmmState::quote_reserves(self)
+    }
+}
 
-        // Net wallet deltas after fee deduction
-        // Fee is deducted from what trader would receive, conceptually
-        // But since fee is on input, the output is reduced by the fee's worth
-        // For simplicity, we show fee as separate collection
-        let base_wallet_delta = base_gross;
-        let quote_wallet_delta = quote_gross;
+/// Concentrated-liquidity position (Uniswap v3 style).
+/// Liquidity L is only active while the price is inside [p_lower, p_upper].
+/// Below the range the position is all base; above it, all quote.
+/// With p_lower = 0 and p_upper = infinity it is a full-range CpmmState.
+#[derive(Clone, Copy, Debug)]
+struct ConcentratedCpmmState {
+    liquidity: f64,
+    price: f64,
+    p_lower: f64,
+    p_upper: f64,
+}
 
-        Self {
-            price_delta,
-            base_wallet_delta,
-            quote_wallet_delta,
-            base_fee_collected: base_fee,
-            quote_fee_collected: quote_fee,
+impl ConcentratedCpmmState {
+    fn new(liquidity: f64, price: f64, p_lower: f64, p_upper: f64) -> Result<Self, CpmmError> {
+        CpmmState::new(liquidity, price)?;
+        if !(p_lower >= 0.0 && p_lower < p_upper) {
+            return Err(CpmmError::InvalidPriceRange);
         }
+        Ok(Self {
+            liquidity,
+            price,
+            p_lower,
+            p_upper,
+        })
     }
-}
 
-/// Converts a slider value in [0, 1] to a logarithmic price.
-/// Maps 0.5 to the center price, with exponential scaling.
-fn slider_to_price(slider_value: f64, center_price: f64, decades: f64) -> f64 {
-    let exponent = (slider_value - 0.5) * 2.0 * decades;
-    center_price * 10.0_f64.powf(exponent)
-}
+    /// Price clamped into the active range.
+    fn active_price(&self) -> f64 {
+        self.price.clamp(self.p_lower, self.p_upper)
+    }
 
-/// Converts a price to a slider value in [0, 1].
-fn price_to_slider(price: f64, center_price: f64, decades: f64) -> f64 {
-    if price <= 0.0 || center_price <= 0.0 {
-        return 0.5;
+    /// Base reserves: x = L * (1/sqrt(P) - 1/sqrt(p_upper)), with P clamped to the range.
+    /// Zero once the price reaches p_upper.
+    fn base_reserves(&self) -> f64 {
+        self.liquidity * (1.0 / self.active_price().sqrt() - 1.0 / self.p_upper.sqrt())
     }
-    let exponent = (price / center_price).log10();
-    0.5 + exponent / (2.0 * decades)
-}
 
-/// Formats a number with appropriate precision.
-fn format_number(value: f64) -> String {
-    if value.abs() < 0.0001 && value != 0.0 {
-        format!("{:.6e}", value)
-    } else if value.abs() >= 1_000_000.0 {
-        format!("{:.4e}", value)
-    } else {
-        format!("{:.6}", value)
+    /// Quote reserves: y = L * (sqrt(P) - sqrt(p_lower)), with P clamped to the range.
+    /// Zero once the price reaches p_lower.
+    fn quote_reserves(&self) -> f64 {
+        self.liquidity * (self.active_price().sqrt() - self.p_lower.sqrt())
     }
 }
 
-/// Shared application state.
-struct AppState {
-    initial_liquidity: f64,
-    initial_price: f64,
-    final_price: f64,
-    fee_percent: f64,
-    center_price: f64,
-    decades: f64,
+/// Sells an exact base input into a concentrated position.
+/// Within the range the position trades like a CPMM with liquidity L; once
+/// the price reaches p_lower it holds no quote and the rest of the input is
+/// returned unfilled. The fee is taken only from the filled part.
+/// Returns the resulting position, the quote received and the unfilled base.
+#[allow(dead_code)]
+fn swap_exact_base_in_concentrated(
+    pos: ConcentratedCpmmState,
+    base_in: f64,
+    fee_fraction: f64,
+) -> (ConcentratedCpmmState, f64, f64) {
+    let sqrt_start = pos.active_price().sqrt();
+    let sqrt_lower = pos.p_lower.sqrt();
+    // Base that moves the price from its start to p_lower, after fees
+    let capacity = pos.liquidity * (1.0 / sqrt_lower - 1.0 / sqrt_start);
+    let effective_in = base_in * (1.0 - fee_fraction);
+    let (sqrt_end, unfilled) = if effective_in < capacity {
+        let sqrt_end = pos.liquidity / (pos.liquidity / sqrt_start + effective_in);
+        (sqrt_end, 0.0)
+    } else {
+        (sqrt_lower, base_in - capacity / (1.0 - fee_fraction))
+    };
+    let next = ConcentratedCpmmState {
+        price: sqrt_end * sqrt_end,
+        ..pos
+    };
+    (next, pos.liquidity * (sqrt_start - sqrt_end), unfilled)
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self {
-            initial_liquidity: 1000.0,
-            initial_price: 1.0,
-            final_price: 1.1,
-            fee_percent: 0.3,
-            center_price: 1.0,
-            decades: 3.0,
+/// Sells an exact base input across several positions of one pool.
+/// All positions are taken to sit at the price of the first. The price walks
+/// down range by range: between consecutive bounds the active positions trade
+/// as one CPMM with their summed liquidity, each absorbing base in proportion
+/// to its own. Input beyond the lowest range is left unfilled.
+/// Returns the total quote received and the base (fee included) each
+/// position took.
+#[allow(dead_code)]
+fn swap_across_positions(
+    positions: &[ConcentratedCpmmState],
+    base_in: f64,
+    fee_fraction: f64,
+) -> (f64, Vec<f64>) {
+    let mut per_position = vec![0.0; positions.len()];
+    let Some(first) = positions.first() else {
+        return (0.0, per_position);
+    };
+    let mut price = first.price;
+    let mut remaining = base_in * (1.0 - fee_fraction);
+    let mut quote_out = 0.0;
+    while remaining > 0.0 {
+        let active: Vec<usize> = (0..positions.len())
+            .filter(|&i| positions[i].p_lower < price && price <= positions[i].p_upper)
+            .collect();
+        // The highest range bound below the price, where the active set changes
+        let next_bound = positions
+            .iter()
+            .flat_map(|pos| [pos.p_lower, pos.p_upper])
+            .filter(|&bound| bound < price)
+            .fold(None, |best: Option<f64>, bound| {
+                Some(best.map_or(bound, |b| b.max(bound)))
+            });
+        let liquidity: f64 = active.iter().map(|&i| positions[i].liquidity).sum();
+        if liquidity == 0.0 {
+            match next_bound {
+                Some(bound) => {
+                    price = bound;
+                    continue;
+                }
+                None => break,
+            }
+        }
+        let sqrt_price = price.sqrt();
+        let sqrt_bound = next_bound.unwrap_or(0.0).sqrt();
+        let capacity = liquidity * (1.0 / sqrt_bound - 1.0 / sqrt_price);
+        let (sqrt_end, end_price) = if remaining < capacity {
+            let sqrt_end = liquidity / (liquidity / sqrt_price + remaining);
+            (sqrt_end, sqrt_end * sqrt_end)
+        } else {
+            // Land exactly on the bound so it is not crossed again
+            (sqrt_bound, next_bound.unwrap_or(0.0))
+        };
+        for &i in &active {
+            let taken = positions[i].liquidity * (1.0 / sqrt_end - 1.0 / sqrt_price);
+            per_position[i] += taken / (1.0 - fee_fraction);
         }
+        quote_out += liquidity * (sqrt_price - sqrt_end);
+        remaining = (remaining - capacity).max(0.0);
+        price = end_price;
     }
+    (quote_out, per_position)
 }
 
-type SharedState = Rc<RefCell<AppState>>;
-
-/// Converts an Element to a Node reference for append operations.
-fn as_node(element: &Element) -> &Node {
-    element.as_ref()
+/// Full-range liquidity holding the same value as a concentrated position
+/// at its current price: a full-range pool is worth x * P + y = 2 * L * sqrt(P).
+/// At the geometric center of [a, b] this is L * (1 - (a / b)^(1/4)), which
+/// approaches L as the range widens.
+fn equivalent_full_range_liquidity(concentrated: &ConcentratedCpmmState) -> f64 {
+    let value = concentrated.base_reserves() * concentrated.price + concentrated.quote_reserves();
+    value / (2.0 * concentrated.price.sqrt())
 }
 
-/// Creates a labeled input row.
-fn create_input_row(
-    document: &Document,
-    label1: &str,
-    id1: &str,
-    value1: &str,
-    label2: Option<&str>,
-    id2: Option<&str>,
-    value2: Option<&str>,
-) -> Result<Element, JsValue> {
-    let row = document.create_element("div")?;
-    row.set_attribute("class", "cpmm-row")?;
-
-    let create_field = |label: &str, id: &str, value: &str| -> Result<Element, JsValue> {
-        let field = document.create_element("div")?;
-        field.set_attribute("class", "cpmm-field")?;
-
-        let lbl = document.create_element("label")?;
-        lbl.set_text_content(Some(label));
-        lbl.set_attribute("for", id)?;
+/// Price at or below which a position with this lower bound holds only base.
+#[allow(dead_code)]
+fn fully_base_price(lower: f64) -> f64 {
+    lower
+}
 
-        let input = document.create_element("input")?;
-        input.set_attribute("type", "text")?;
-        input.set_attribute("id", id)?;
-        input.set_attribute("value", value)?;
+/// Price at or above which a position with this upper bound holds only quote.
+#[allow(dead_code)]
+fn fully_quote_price(upper: f64) -> f64 {
+    upper
+}
 
-        field.append_child(as_node(&lbl))?;
-        field.append_child(as_node(&input))?;
-        Ok(field)
+/// Share of a concentrated position's value held in base and in quote,
+/// valued in quote at `price`. Returns (base_frac, quote_frac), summing to 1.
+/// The split is even at the geometric midpoint sqrt(lower * upper).
+#[allow(dead_code)]
+fn position_composition(price: f64, lower: f64, upper: f64) -> (f64, f64) {
+    if price <= fully_base_price(lower) {
+        return (1.0, 0.0);
+    }
+    if price >= fully_quote_price(upper) {
+        return (0.0, 1.0);
+    }
+    // Fractions do not depend on the amount of liquidity
+    let position = ConcentratedCpmmState {
+        liquidity: 1.0,
+        price,
+        p_lower: lower,
+        p_upper: upper,
     };
+    let base_value = position.base_reserves() * price;
+    let quote_value = position.quote_reserves();
+    let total = base_value + quote_value;
+    (base_value / total, quote_value / total)
+}
 
-    let field1 = create_field(label1, id1, value1)?;
-    row.append_child(as_node(&field1))?;
-
-    if let (Some(l2), Some(i2), Some(v2)) = (label2, id2, value2) {
-        let field2 = create_field(l2, i2, v2)?;
-        row.append_child(as_node(&field2))?;
+impl From<CpmmState> for ConcentratedCpmmState {
+    /// Full-range position: p_lower = 0, p_upper = infinity.
+    fn from(state: CpmmState) -> Self {
+        Self {
+            liquidity: state.liquidity,
+            price: state.price,
+            p_lower: 0.0,
+            p_upper: f64::INFINITY,
+        }
     }
-
-    Ok(row)
 }
 
-/// Creates a slider row.
-fn create_slider_row(document: &Document, id: &str, value: f64) -> Result<Element, JsValue> {
-    let row = document.create_element("div")?;
-    row.set_attribute("class", "cpmm-slider-row")?;
-
-    let label = document.create_element("label")?;
-    label.set_text_content(Some("Logarithmic Price Slider"));
+impl PoolState for ConcentratedCpmmState {
+    fn price(&self) -> f64 {
+        self.price
+    }
 
-    let slider = document.create_element("input")?;
-    slider.set_attribute("type", "range")?;
-    slider.set_attribute("id", id)?;
-    slider.set_attribute("min", "0")?;
-    slider.set_attribute("max", "1")?;
-    slider.set_attribute("step", "0.001")?;
-    slider.set_attribute("value", &value.to_string())?;
-    slider.set_attribute("class", "cpmm-slider")?;
+    fn base_reserves(&self) -> f64 {
+        ConcentratedCpmmState::base_reserves(self)
+    }
 
-    row.append_child(as_node(&label))?;
-    row.append_child(as_node(&slider))?;
-    Ok(row)
+    fn quote_reserves(&self) -> f64 {
+        ConcentratedCpmmState::quote_reserves(self)
+    }
 }
 
-/// Creates a section with a title.
-fn create_section(document: &Document, title: &str) -> Result<Element, JsValue> {
-    let section = document.create_element("div")?;
-    section.set_attribute("class", "cpmm-section")?;
+/// Iteration cap for the stableswap Newton solvers.
+/// Convergence normally takes a handful of steps; the cap only guards against
+/// oscillation in the last bit.
+const STABLESWAP_MAX_ITERATIONS: usize = 255;
 
-    let header = document.create_element("div")?;
-    header.set_attribute("class", "cpmm-section-header")?;
-    header.set_text_content(Some(title));
-
-    section.append_child(as_node(&header))?;
-    Ok(section)
-}
-
-/// Gets an input element by ID.
-fn get_input(document: &Document, id: &str) -> Option<HtmlInputElement> {
-    document
-        .get_element_by_id(id)
-        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+/// Stableswap invariant D for two reserves (Curve), solved by Newton iteration:
+///   A·n^n·(x + y) + D = A·D·n^n + D^(n+1) / (n^n·x·y), with n = 2.
+/// Starting from D = x + y, which is exact for balanced reserves, the
+/// iteration converges from above in a few steps.
+fn stableswap_d(amplification: f64, base: f64, quote: f64) -> f64 {
+    let ann = amplification * 4.0;
+    let sum = base + quote;
+    let mut d = sum;
+    for _ in 0..STABLESWAP_MAX_ITERATIONS {
+        let d_p = d * d * d / (4.0 * base * quote);
+        let previous = d;
+        d = (ann * sum + 2.0 * d_p) * d / ((ann - 1.0) * d + 3.0 * d_p);
+        if (d - previous).abs() <= d * f64::EPSILON {
+            break;
+        }
+    }
+    d
 }
 
-/// Sets the value of an input element.
-fn set_input_value(document: &Document, id: &str, value: &str) {
-    if let Some(input) = get_input(document, id) {
-        input.set_value(value);
+/// Reserve of the other token that keeps the stableswap invariant at D,
+/// given one reserve. Newton iteration on y^2 + (b - D)·y = c, where
+/// b = x + D / (A·n^n) and c = D^3 / (n^n·x·A·n^n).
+fn stableswap_y(amplification: f64, x: f64, d: f64) -> f64 {
+    let ann = amplification * 4.0;
+    let b = x + d / ann;
+    let c = d * d * d / (4.0 * x * ann);
+    let mut y = d;
+    for _ in 0..STABLESWAP_MAX_ITERATIONS {
+        let previous = y;
+        y = (y * y + c) / (2.0 * y + b - d);
+        if (y - previous).abs() <= y * f64::EPSILON {
+            break;
+        }
     }
+    y
 }
 
-/// Updates all computed fields based on current state.
-fn update_computed_fields(document: &Document, state: &AppState) {
-    let initial = CpmmState::new(state.initial_liquidity, state.initial_price);
-    let final_state = CpmmState::new(state.initial_liquidity, state.final_price);
-    let fee_fraction = state.fee_percent / 100.0;
-
-    // Initial reserves
-    set_input_value(
-        document,
-        "initial-base-reserves",
-        &format_number(initial.base_reserves()),
-    );
-    set_input_value(
-        document,
-        "initial-quote-reserves",
-        &format_number(initial.quote_reserves()),
-    );
-
-    // Final reserves
-    set_input_value(
-        document,
-        "final-base-reserves",
-        &format_number(final_state.base_reserves()),
-    );
-    set_input_value(
-        document,
-        "final-quote-reserves",
-        &format_number(final_state.quote_reserves()),
-    );
-
-    // Trade result
-    let result = TradeResult::compute(initial, final_state, fee_fraction);
+/// Spot price (quote per base) on the stableswap curve: the ratio of the
+/// invariant's partial derivatives, (A·n^n + D_P / x) / (A·n^n + D_P / y)
+/// with D_P = D^3 / (n^n·x·y). Exactly 1 for balanced reserves.
+fn stableswap_price(amplification: f64, base: f64, quote: f64, d: f64) -> f64 {
+    let ann = amplification * 4.0;
+    let d_p = d * d * d / (4.0 * base * quote);
+    (ann + d_p / base) / (ann + d_p / quote)
+}
 
-    set_input_value(
-        document,
-        "delta-price",
-        &format_number(result.price_delta),
-    );
-    set_input_value(
-        document,
-        "delta-base-reserves",
-        &format_number(result.base_wallet_delta),
-    );
-    set_input_value(
-        document,
-        "delta-quote-reserves",
-        &format_number(result.quote_wallet_delta),
-    );
-    set_input_value(
-        document,
-        "fee-base-collected",
-        &format_number(result.base_fee_collected),
-    );
-    set_input_value(
-        document,
-        "fee-quote-collected",
-        &format_number(result.quote_fee_collected),
-    );
+/// Two-asset stableswap pool (Curve).
+/// Near balanced reserves it behaves like a constant-sum pool (price close
+/// to 1), and like a constant-product pool far from balance. A larger
+/// amplification A widens the flat region.
+#[derive(Clone, Copy, Debug)]
+struct StableswapState {
+    amplification: f64,
+    base: f64,
+    quote: f64,
 }
 
-/// Attaches an input event listener to an element.
-fn attach_input_listener<F>(document: &Document, id: &str, callback: F)
-where
-    F: Fn(String) + 'static,
-{
-    if let Some(input) = get_input(document, id) {
-        let closure = Closure::wrap(Box::new(move |_event: web_sys::InputEvent| {
-            let input_clone = input.clone();
-            callback(input_clone.value());
-        }) as Box<dyn Fn(_)>);
-        let input_for_listener = get_input(document, id).unwrap();
-        input_for_listener
-            .add_event_listener_with_callback("input", closure.as_ref().unchecked_ref())
-            .unwrap();
-        closure.forget();
-    }
-}
-
-/// Main entry point for injecting the CPMM calculator UI.
-#[wasm_bindgen]
-pub fn inject_ui(anchor_id: &str) {
-    console::log_1(&"CPMM Calculator: Initializing...".into());
-
-    let window = match web_sys::window() {
-        Some(w) => w,
-        None => {
-            console::error_1(&"No window object found".into());
-            return;
+impl StableswapState {
+    /// Pool with invariant D whose spot price is `price`.
+    /// Solves for the base reserve by bisection on its logarithm, since the
+    /// price falls monotonically as base reserves grow along the curve. For
+    /// comparison with a CPMM, D = 2L puts both at the same reserves at price 1.
+    fn from_price(amplification: f64, d: f64, price: f64) -> Result<Self, CpmmError> {
+        if !(amplification.is_finite() && amplification > 0.0) {
+            return Err(CpmmError::InvalidAmplification);
         }
-    };
-
-    let document = match window.document() {
-        Some(d) => d,
-        None => {
-            console::error_1(&"No document object found".into());
-            return;
+        CpmmState::new(d, price)?;
+        let price_at = |base: f64| {
+            let quote = stableswap_y(amplification, base, d);
+            stableswap_price(amplification, base, quote, d)
+        };
+        let (mut low, mut high) = ((d * 1e-12).ln(), (d * 1e12).ln());
+        for _ in 0..STABLESWAP_MAX_ITERATIONS {
+            let mid = 0.5 * (low + high);
+            if price_at(mid.exp()) > price {
+                low = mid;
+            } else {
+                high = mid;
+            }
         }
-    };
+        let base = (0.5 * (low + high)).exp();
+        Ok(Self {
+            amplification,
+            base,
+            quote: stableswap_y(amplification, base, d),
+        })
+    }
 
-    let anchor = match document.get_element_by_id(anchor_id) {
-        Some(a) => a,
-        None => {
-            console::error_1(&format!("Anchor element '{}' not found", anchor_id).into());
-            return;
-        }
-    };
+    /// Invariant D of the current reserves.
+    fn invariant(&self) -> f64 {
+        stableswap_d(self.amplification, self.base, self.quote)
+    }
 
-    if let Err(e) = build_ui(&document, &anchor) {
-        console::error_1(&format!("Failed to build UI: {:?}", e).into());
+    /// Swaps an exact base input for quote, holding D constant.
+    /// The fee is taken from the input, as in `CpmmState::swap_exact_base_in`.
+    #[allow(dead_code)]
+    fn swap_exact_base_in(&self, base_in: f64, fee_fraction: f64) -> (f64, StableswapState) {
+        let d = self.invariant();
+        let base_after = self.base + base_in * (1.0 - fee_fraction);
+        let quote_after = stableswap_y(self.amplification, base_after, d);
+        let next = Self {
+            base: base_after,
+            quote: quote_after,
+            ..*self
+        };
+        (self.quote - quote_after, next)
     }
 }
 
-/// Builds the complete calculator UI.
-fn build_ui(document: &Document, anchor: &Element) -> Result<(), JsValue> {
-    let state: SharedState = Rc::new(RefCell::new(AppState::default()));
-
-    let container = document.create_element("div")?;
-    container.set_attribute("class", "cpmm-calculator")?;
+impl PoolState for StableswapState {
+    fn price(&self) -> f64 {
+        stableswap_price(self.amplification, self.base, self.quote, self.invariant())
+    }
 
-    // Initial Price Section
-    let initial_section = create_section(document, "Initial Price Section")?;
+    fn base_reserves(&self) -> f64 {
+        self.base
+    }
 
-    let initial_slider_value = {
-        let s = state.borrow();
-        price_to_slider(s.initial_price, s.center_price, s.decades)
-    };
+    fn quote_reserves(&self) -> f64 {
+        self.quote
+    }
+}
 
-    let row1 = create_input_row(
-        document,
-        "Liquidity:",
-        "initial-liquidity",
-        &format_number(state.borrow().initial_liquidity),
-        Some("Price:"),
-        Some("initial-price"),
-        Some(&format_number(state.borrow().initial_price)),
-    )?;
-    initial_section.append_child(as_node(&row1))?;
-
-    let slider1 = create_slider_row(document, "initial-price-slider", initial_slider_value)?;
-    initial_section.append_child(as_node(&slider1))?;
-
-    let row2 = create_input_row(
-        document,
-        "Base Reserves:",
-        "initial-base-reserves",
-        "",
-        Some("Quote Reserves:"),
-        Some("initial-quote-reserves"),
-        Some(""),
-    )?;
-    initial_section.append_child(as_node(&row2))?;
+/// How far pool weights may sum from 1.
+const WEIGHT_TOLERANCE: f64 = 1e-9;
 
-    container.append_child(as_node(&initial_section))?;
+/// Two-asset weighted pool (Balancer).
+/// Holds the invariant V = x^w_base * y^w_quote, with spot price
+/// P = (y / w_quote) / (x / w_base). Liquidity is V itself, so with 50/50
+/// weights V = sqrt(x * y) = L and the pool is a `CpmmState`.
+#[derive(Clone, Copy, Debug)]
+struct WeightedPoolState {
+    liquidity: f64,
+    price: f64,
+    weight_base: f64,
+    weight_quote: f64,
+}
 
-    // Final Price Section
-    let final_section = create_section(document, "Final Price Section")?;
+impl WeightedPoolState {
+    fn new(
+        liquidity: f64,
+        price: f64,
+        weight_base: f64,
+        weight_quote: f64,
+    ) -> Result<Self, CpmmError> {
+        CpmmState::new(liquidity, price)?;
+        let in_unit = |w: f64| w > 0.0 && w < 1.0;
+        if !(in_unit(weight_base)
+            && in_unit(weight_quote)
+            && (weight_base + weight_quote - 1.0).abs() <= WEIGHT_TOLERANCE)
+        {
+            return Err(CpmmError::InvalidWeights);
+        }
+        Ok(Self {
+            liquidity,
+            price,
+            weight_base,
+            weight_quote,
+        })
+    }
 
-    let final_slider_value = {
-        let s = state.borrow();
-        price_to_slider(s.final_price, s.center_price, s.decades)
-    };
+    /// Quote reserves per unit of base reserves: y / x = P * w_quote / w_base.
+    fn reserve_ratio(&self) -> f64 {
+        self.price * self.weight_quote / self.weight_base
+    }
 
-    let row3 = create_input_row(
-        document,
-        "Fee %:",
-        "fee-percent",
-        &format_number(state.borrow().fee_percent),
-        Some("Price:"),
-        Some("final-price"),
-        Some(&format_number(state.borrow().final_price)),
-    )?;
-    final_section.append_child(as_node(&row3))?;
-
-    let slider2 = create_slider_row(document, "final-price-slider", final_slider_value)?;
-    final_section.append_child(as_node(&slider2))?;
-
-    let row4 = create_input_row(
-        document,
-        "Base Reserves:",
-        "final-base-reserves",
-        "",
-        Some("Quote Reserves:"),
-        Some("final-quote-reserves"),
-        Some(""),
-    )?;
-    final_section.append_child(as_node(&row4))?;
+    /// Invariant V = x^w_base * y^w_quote, i.e. the liquidity.
+    fn invariant(&self) -> f64 {
+        self.liquidity
+    }
 
-    container.append_child(as_node(&final_section))?;
+    /// Swaps an exact base input for quote, holding V constant.
+    /// The fee is taken from the input, as in `CpmmState::swap_exact_base_in`.
+    #[allow(dead_code)]
+    fn swap_exact_base_in(&self, base_in: f64, fee_fraction: f64) -> (f64, WeightedPoolState) {
+        let base_after = self.base_reserves() + base_in * (1.0 - fee_fraction);
+        let quote_after =
+            (self.liquidity / base_after.powf(self.weight_base)).powf(1.0 / self.weight_quote);
+        let next = Self {
+            price: (quote_after / self.weight_quote) / (base_after / self.weight_base),
+            ..*self
+        };
+        (self.quote_reserves() - quote_after, next)
+    }
+}
 
-    // Delta Section
-    let delta_section = create_section(document, "Delta Section (Wallet Perspective)")?;
+impl PoolState for WeightedPoolState {
+    fn price(&self) -> f64 {
+        self.price
+    }
 
-    let row5 = create_input_row(
-        document,
-        "",
-        "delta-empty",
-        "",
-        Some("Price Delta:"),
-        Some("delta-price"),
-        Some(""),
-    )?;
-    delta_section.append_child(as_node(&row5))?;
+    /// x = V / (y / x)^w_quote, since V = x * (y / x)^w_quote when the weights sum to 1.
+    fn base_reserves(&self) -> f64 {
+        self.liquidity / self.reserve_ratio().powf(self.weight_quote)
+    }
 
-    let row6 = create_input_row(
-        document,
-        "Base Reserves Delta:",
-        "delta-base-reserves",
-        "",
-        Some("Quote Reserves Delta:"),
-        Some("delta-quote-reserves"),
-        Some(""),
-    )?;
-    delta_section.append_child(as_node(&row6))?;
+    fn quote_reserves(&self) -> f64 {
+        self.base_reserves() * self.reserve_ratio()
+    }
+}
 
-    let row7 = create_input_row(
-        document,
-        "Base Fee Collected:",
-        "fee-base-collected",
-        "",
-        Some("Quote Fee Collected:"),
-        Some("fee-quote-collected"),
-        Some(""),
-    )?;
-    delta_section.append_child(as_node(&row7))?;
+/// Which side of a trade pays the fee.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum FeeMode {
+    /// A fraction of what the trader pays in.
+    #[default]
+    OnInput,
+    /// A fraction of what the pool pays out, withheld from the trader.
+    OnOutput,
+}
 
-    container.append_child(as_node(&delta_section))?;
+impl FeeMode {
+    /// Options of the fee mode toggle, as (value, label).
+    const OPTIONS: &[(&str, &str)] = &[("onInput", "Input"), ("onOutput", "Output")];
 
-    // Insert container before anchor
-    if let Some(parent) = anchor.parent_node() {
-        parent.insert_before(&container, Some(anchor))?;
+    /// Value of this mode in the toggle.
+    fn as_str(self) -> &'static str {
+        match self {
+            FeeMode::OnInput => "onInput",
+            FeeMode::OnOutput => "onOutput",
+        }
     }
 
-    // Initial computation
-    update_computed_fields(document, &state.borrow());
-
-    // Attach event listeners
-    let doc = document.clone();
-    let state_clone = Rc::clone(&state);
-    attach_input_listener(document, "initial-liquidity", move |value| {
-        if let Ok(v) = value.parse::<f64>()
-            && v > 0.0
-        {
-            state_clone.borrow_mut().initial_liquidity = v;
-            update_computed_fields(&doc, &state_clone.borrow());
+    /// Parses a toggle value.
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "onInput" => Some(FeeMode::OnInput),
+            "onOutput" => Some(FeeMode::OnOutput),
+            _ => None,
         }
-    });
+    }
+}
 
-    let doc = document.clone();
-    let state_clone = Rc::clone(&state);
-    attach_input_listener(document, "initial-price", move |value| {
-        if let Ok(v) = value.parse::<f64>()
-            && v > 0.0
-        {
-            {
-                let mut s = state_clone.borrow_mut();
-                s.initial_price = v;
-            }
-            let s = state_clone.borrow();
-            let slider_val = price_to_slider(v, s.center_price, s.decades);
-            set_input_value(&doc, "initial-price-slider", &slider_val.to_string());
-            update_computed_fields(&doc, &s);
-        }
-    });
-
-    let doc = document.clone();
-    let state_clone = Rc::clone(&state);
-    attach_input_listener(document, "initial-price-slider", move |value| {
-        if let Ok(v) = value.parse::<f64>() {
-            let price = {
-                let s = state_clone.borrow();
-                slider_to_price(v, s.center_price, s.decades)
-            };
-            state_clone.borrow_mut().initial_price = price;
-            set_input_value(&doc, "initial-price", &format_number(price));
-            update_computed_fields(&doc, &state_clone.borrow());
-        }
-    });
+/// Computes wallet deltas and fee collection for a trade.
+/// The trader moves the pool from initial_state to final_state.
+/// Wallet deltas are from the trader's perspective (positive = received).
+/// Fees are collected on the input side, or on the output side in
+/// `FeeMode::OnOutput`, and sent to treasury.
+/// Gross deltas mirror the pool reserve changes; net deltas (`*_net`) also
+/// include the fee, so net = gross - fee on each side.
+/// Execution price is quote per base actually exchanged net of fees; price
+/// impact is its fractional difference from the initial spot price.
+/// Each side's fee is split between the protocol treasury (`*_protocol_fee`)
+/// and LPs (`*_lp_fee`); the LP portion is conceptually re-added to the
+/// reserves, growing k.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TradeResult {
+    price_delta: f64,
+    base_wallet_delta: f64,
+    quote_wallet_delta: f64,
+    base_wallet_delta_net: f64,
+    quote_wallet_delta_net: f64,
+    base_fee_collected: f64,
+    quote_fee_collected: f64,
+    base_lp_fee: f64,
+    quote_lp_fee: f64,
+    base_protocol_fee: f64,
+    quote_protocol_fee: f64,
+    execution_price: f64,
+    price_impact: f64,
+}
 
-    let doc = document.clone();
-    let state_clone = Rc::clone(&state);
-    attach_input_listener(document, "fee-percent", move |value| {
-        if let Ok(v) = value.parse::<f64>()
-            && (0.0..100.0).contains(&v)
-        {
-            state_clone.borrow_mut().fee_percent = v;
-            update_computed_fields(&doc, &state_clone.borrow());
+impl TradeResult {
+    /// Result of a trade that exchanges nothing: every delta and fee is exactly 0.
+    fn none() -> Self {
+        Self {
+            price_delta: 0.0,
+            base_wallet_delta: 0.0,
+            quote_wallet_delta: 0.0,
+            base_wallet_delta_net: 0.0,
+            quote_wallet_delta_net: 0.0,
+            base_fee_collected: 0.0,
+            quote_fee_collected: 0.0,
+            base_lp_fee: 0.0,
+            quote_lp_fee: 0.0,
+            base_protocol_fee: 0.0,
+            quote_protocol_fee: 0.0,
+            execution_price: 0.0,
+            price_impact: 0.0,
         }
-    });
+    }
 
-    let doc = document.clone();
-    let state_clone = Rc::clone(&state);
-    attach_input_listener(document, "final-price", move |value| {
-        if let Ok(v) = value.parse::<f64>()
-            && v > 0.0
-        {
-            {
-                let mut s = state_clone.borrow_mut();
-                s.final_price = v;
-            }
-            let s = state_clone.borrow();
-            let slider_val = price_to_slider(v, s.center_price, s.decades);
-            set_input_value(&doc, "final-price-slider", &slider_val.to_string());
-            update_computed_fields(&doc, &s);
-        }
-    });
-
-    let doc = document.clone();
-    let state_clone = Rc::clone(&state);
-    attach_input_listener(document, "final-price-slider", move |value| {
-        if let Ok(v) = value.parse::<f64>() {
-            let price = {
-                let s = state_clone.borrow();
-                slider_to_price(v, s.center_price, s.decades)
-            };
-            state_clone.borrow_mut().final_price = price;
-            set_input_value(&doc, "final-price", &format_number(price));
-            update_computed_fields(&doc, &state_clone.borrow());
-        }
-    });
+    /// Computes a trade whose whole fee, charged on the input, goes to the
+    /// protocol treasury.
+    fn compute<S: PoolState>(initial: S, final_state: S, fee_fraction: f64) -> Self {
+        Self::compute_split(initial, final_state, fee_fraction, 1.0, FeeMode::OnInput)
+    }
 
-    console::log_1(&"CPMM Calculator: UI initialized successfully".into());
-    Ok(())
-}
+    /// Computes a trade, charging the fee on the side `fee_mode` selects and
+    /// sending `protocol_fee_fraction` of it to the protocol treasury and the
+    /// rest to LPs.
+    fn compute_split<S: PoolState>(
+        initial: S,
+        final_state: S,
+        fee_fraction: f64,
+        protocol_fee_fraction: f64,
+        fee_mode: FeeMode,
+    ) -> Self {
+        assert!((0.0..1.0).contains(&fee_fraction), "Fee must be in [0, 1)");
+        assert!(
+            (0.0..=1.0).contains(&protocol_fee_fraction),
+            "Protocol fee share must be in [0, 1]"
+        );
 
-#[cfg(test)]
+        let price_delta = final_state.price() - initial.price();
+
+        // Pool reserve changes
+        let base_pool_delta = final_state.base_reserves() - initial.base_reserves();
+        let quote_pool_delta = final_state.quote_reserves() - initial.quote_reserves();
+
+        // Wallet deltas are opposite of pool deltas (what leaves pool enters wallet)
+        // Before fees, gross amounts
+        let base_gross = -base_pool_delta;
+        let quote_gross = -quote_pool_delta;
+
+        let (base_fee, quote_fee) = match fee_mode {
+            // Fee is collected on the input side (negative wallet delta means trader pays)
+            // If trader pays base (base_gross < 0), fee is on base
+            // If trader pays quote (quote_gross < 0), fee is on quote
+            FeeMode::OnInput => {
+                if base_gross < 0.0 {
+                    // Trader is selling base (paying base, receiving quote)
+                    ((-base_gross) * fee_fraction, 0.0)
+                } else if quote_gross < 0.0 {
+                    // Trader is buying base (paying quote, receiving base)
+                    (0.0, (-quote_gross) * fee_fraction)
+                } else {
+                    // No trade or edge case
+                    (0.0, 0.0)
+                }
+            }
+            // Fee is withheld from what the pool pays out (positive gross delta):
+            // the whole input reaches the pool and fee = pool_output * fee
+            FeeMode::OnOutput => {
+                if base_gross > 0.0 {
+                    // Trader is buying base
+                    (base_gross * fee_fraction, 0.0)
+                } else if quote_gross > 0.0 {
+                    // Trader is selling base
+                    (0.0, quote_gross * fee_fraction)
+                } else {
+                    (0.0, 0.0)
+                }
+            }
+        };
+
+        // Gross wallet deltas mirror the pool; net deltas also pay the fee
+        let base_wallet_delta = base_gross;
+        let quote_wallet_delta = quote_gross;
+        let base_wallet_delta_net = base_gross - base_fee;
+        let quote_wallet_delta_net = quote_gross - quote_fee;
+
+        // Slippage: zero when nothing is exchanged
+        let (execution_price, price_impact) = if base_wallet_delta_net != 0.0 {
+            let execution_price = (quote_wallet_delta_net / base_wallet_delta_net).abs();
+            (
+                execution_price,
+                (execution_price - initial.price()) / initial.price(),
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        // The protocol takes its share; LPs keep the rest
+        let base_protocol_fee = base_fee * protocol_fee_fraction;
+        let quote_protocol_fee = quote_fee * protocol_fee_fraction;
+
+        Self {
+            price_delta,
+            base_wallet_delta,
+            quote_wallet_delta,
+            base_wallet_delta_net,
+            quote_wallet_delta_net,
+            base_fee_collected: base_fee,
+            quote_fee_collected: quote_fee,
+            base_lp_fee: base_fee - base_protocol_fee,
+            quote_lp_fee: quote_fee - quote_protocol_fee,
+            base_protocol_fee,
+            quote_protocol_fee,
+            execution_price,
+            price_impact,
+        }
+    }
+}
+
+/// Wallet deltas between two pool states that differ in liquidity as well as price.
+/// The change is split into a trade that moves the price at the initial
+/// liquidity, followed by a deposit (or withdrawal) at the final price. Only the
+/// trade pays fees; the liquidity deltas are what the wallet pays into the pool
+/// (negative) or takes out of it (positive), in the pool's ratio at the final price.
+#[derive(Clone, Copy, Debug)]
+struct LiquidityChangeResult {
+    trade: TradeResult,
+    base_liquidity_delta: f64,
+    quote_liquidity_delta: f64,
+}
+
+impl LiquidityChangeResult {
+    fn compute(
+        initial: ConcentratedCpmmState,
+        final_state: ConcentratedCpmmState,
+        fee_fraction: f64,
+        protocol_fee_fraction: f64,
+        fee_mode: FeeMode,
+    ) -> Self {
+        // Pool after the price move, before any liquidity changes
+        let moved = ConcentratedCpmmState {
+            liquidity: initial.liquidity,
+            ..final_state
+        };
+        Self::between(
+            initial,
+            moved,
+            final_state,
+            fee_fraction,
+            protocol_fee_fraction,
+            fee_mode,
+        )
+    }
+
+    /// Splits the change given the intermediate pool `moved`: at the final
+    /// price with the initial liquidity. Works for any pool model.
+    fn between<S: PoolState>(
+        initial: S,
+        moved: S,
+        final_state: S,
+        fee_fraction: f64,
+        protocol_fee_fraction: f64,
+        fee_mode: FeeMode,
+    ) -> Self {
+        Self {
+            base_liquidity_delta: moved.base_reserves() - final_state.base_reserves(),
+            quote_liquidity_delta: moved.quote_reserves() - final_state.quote_reserves(),
+            trade: TradeResult::compute_split(
+                initial,
+                moved,
+                fee_fraction,
+                protocol_fee_fraction,
+                fee_mode,
+            ),
+        }
+    }
+}
+
+/// Worst-case amount of a quoted trade under a slippage tolerance fraction.
+/// Outputs shrink to the minimum received, amount * (1 - tolerance); inputs
+/// grow to the maximum sent, amount * (1 + tolerance). The tolerance is
+/// clamped to [0, 1], so a bad value never turns an output negative.
+fn apply_slippage_tolerance(amount: f64, tolerance: f64, is_output: bool) -> f64 {
+    let tolerance = tolerance.clamp(0.0, 1.0);
+    if is_output {
+        amount * (1.0 - tolerance)
+    } else {
+        amount * (1.0 + tolerance)
+    }
+}
+
+/// Relative price difference below which two prices count as the same,
+/// so a move that only differs by rounding is not shown as a trade.
+const NO_TRADE_TOLERANCE: f64 = 1e-12;
+
+/// Whether moving from `initial_price` to `final_price` trades nothing.
+fn is_no_trade(initial_price: f64, final_price: f64) -> bool {
+    (final_price - initial_price).abs() <= initial_price.abs() * NO_TRADE_TOLERANCE
+}
+
+/// Swaps an exact base input, then adds liquidity at the post-swap price.
+/// The deposit keeps the price where the swap left it and pays base and quote
+/// in the pool's ratio. The result reports the combined wallet deltas of both
+/// steps; fees, execution price and price impact come from the swap alone,
+/// since the deposit exchanges nothing. A negative add_liquidity withdraws.
+#[allow(dead_code)]
+fn swap_then_add(
+    initial: CpmmState,
+    base_in: f64,
+    add_liquidity: f64,
+    fee_fraction: f64,
+) -> Result<(CpmmState, TradeResult), CpmmError> {
+    let (_, swapped) = initial.swap_exact_base_in(base_in, fee_fraction);
+    let final_state = CpmmState::new(swapped.liquidity + add_liquidity, swapped.price)?;
+    let swap = TradeResult::compute(initial, swapped, fee_fraction);
+
+    // Deposit leaves the wallet: pool reserves grow at the unchanged price
+    let base_deposit = final_state.base_reserves() - swapped.base_reserves();
+    let quote_deposit = final_state.quote_reserves() - swapped.quote_reserves();
+
+    let combined = TradeResult {
+        base_wallet_delta: swap.base_wallet_delta - base_deposit,
+        quote_wallet_delta: swap.quote_wallet_delta - quote_deposit,
+        base_wallet_delta_net: swap.base_wallet_delta_net - base_deposit,
+        quote_wallet_delta_net: swap.quote_wallet_delta_net - quote_deposit,
+        ..swap
+    };
+    Ok((final_state, combined))
+}
+
+/// Pool after a supply rebase, which scales reserves without a trade.
+/// Base reserves are multiplied by `base_rebase_factor` and quote reserves by
+/// `quote_rebase_factor`; liquidity and price follow from the new reserves:
+/// L = sqrt(x * y), P = y / x. A base-only rebase by f therefore scales L by
+/// sqrt(f) and divides P by f.
+fn apply_rebase(
+    state: CpmmState,
+    base_rebase_factor: f64,
+    quote_rebase_factor: f64,
+) -> Result<CpmmState, CpmmError> {
+    let base = state.base_reserves() * base_rebase_factor;
+    let quote = state.quote_reserves() * quote_rebase_factor;
+    CpmmState::new((base * quote).sqrt(), quote / base)
+}
+
+/// A liquidity provider action on a CPMM pool.
+/// Both are proportional: they pay in or take out base and quote in the
+/// pool's reserve ratio, so neither moves the price.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LpAction {
+    /// Deposits this much liquidity L.
+    Add(f64),
+    /// Withdraws this much liquidity L.
+    Remove(f64),
+}
+
+/// Applies a sequence of proportional LP actions to a pool.
+/// Each action only rescales liquidity, so the price is carried over exactly.
+/// Fails if an action leaves no liquidity, or liquidity that is not finite.
+#[allow(dead_code)]
+fn apply_lp_actions(initial: CpmmState, actions: &[LpAction]) -> Result<CpmmState, CpmmError> {
+    actions.iter().try_fold(initial, |state, action| {
+        let liquidity = match action {
+            LpAction::Add(amount) => state.liquidity + amount,
+            LpAction::Remove(amount) => state.liquidity - amount,
+        };
+        CpmmState::new(liquidity, state.price)
+    })
+}
+
+/// Computes the trade that moves the pool to a target marginal (post-trade) price.
+/// The marginal price is the spot price after the trade, as opposed to the
+/// effective execution price. Liquidity is held constant across the move.
+fn trade_to_marginal_price(
+    initial: CpmmState,
+    target_marginal: f64,
+    fee_fraction: f64,
+) -> Result<TradeResult, CpmmError> {
+    let final_state = CpmmState::new(initial.liquidity, target_marginal)?;
+    Ok(TradeResult::compute(initial, final_state, fee_fraction))
+}
+
+/// Trade that pushes an oracle pool to a loan's liquidation price.
+/// This is the price-driven trade to that price; pair it with
+/// `manipulation_cost` to see what forcing the liquidation costs.
+#[allow(dead_code)]
+fn trade_to_liquidation(
+    initial: CpmmState,
+    liquidation_price: f64,
+    fee_fraction: f64,
+) -> Result<TradeResult, CpmmError> {
+    trade_to_marginal_price(initial, liquidation_price, fee_fraction)
+}
+
+/// Quote value a manipulator gives up on a trade, marked at the pre-trade
+/// price: -(net base received * P0 + net quote received). Slippage and fees
+/// make it positive for any non-trivial move, before any unwind.
+#[allow(dead_code)]
+fn manipulation_cost(result: &TradeResult, initial_price: f64) -> f64 {
+    -(result.base_wallet_delta_net * initial_price + result.quote_wallet_delta_net)
+}
+
+/// Limit that stopped a `constrained_trade`.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Constraint {
+    /// The pool reached the target price.
+    Price,
+    /// Price impact reached the slippage cap first.
+    Slippage,
+}
+
+/// Trades toward a target marginal price, stopping early if the price impact
+/// would exceed max_slippage. Reports which limit was binding.
+/// Impact is measured net of fees, so the execution price moves with the
+/// marginal price as:
+///   buying base:  exec / P0 = sqrt(P1 / P0) * (1 + fee)
+///   selling base: exec / P0 = sqrt(P1 / P0) / (1 + fee)
+/// A cap tighter than the fee alone allows no trade at all.
+#[allow(dead_code)]
+fn constrained_trade(
+    initial: CpmmState,
+    target_price: f64,
+    max_slippage: f64,
+    fee_fraction: f64,
+) -> Result<(TradeResult, Constraint), CpmmError> {
+    let (stop_price, constraint) = if target_price >= initial.price {
+        let ratio = (1.0 + max_slippage) / (1.0 + fee_fraction);
+        let limit = initial.price * ratio * ratio;
+        if target_price <= limit {
+            (target_price, Constraint::Price)
+        } else {
+            (limit.max(initial.price), Constraint::Slippage)
+        }
+    } else {
+        let ratio = ((1.0 - max_slippage) * (1.0 + fee_fraction)).max(0.0);
+        let limit = initial.price * ratio * ratio;
+        if target_price >= limit {
+            (target_price, Constraint::Price)
+        } else {
+            (limit.min(initial.price), Constraint::Slippage)
+        }
+    };
+    let result = trade_to_marginal_price(initial, stop_price, fee_fraction)?;
+    Ok((result, constraint))
+}
+
+/// Settles a batch of buys (quote in) and sells (base in) submitted together.
+/// Opposing flows are netted at the pool's initial price, so only the
+/// imbalance trades against the pool, paying the fee. The uniform clearing
+/// price for the batch is the pool's marginal price afterwards, where
+/// arbitrage leaves the batch price and the pool in agreement.
+#[allow(dead_code)]
+fn batch_clearing(
+    initial: CpmmState,
+    buys_quote: &[f64],
+    sells_base: &[f64],
+    fee_fraction: f64,
+) -> (CpmmState, f64) {
+    let quote_in: f64 = buys_quote.iter().sum();
+    let base_in: f64 = sells_base.iter().sum();
+    let net_quote = quote_in - base_in * initial.price;
+
+    let (_, final_state) = if net_quote > 0.0 {
+        initial.swap_exact_quote_in(net_quote, fee_fraction)
+    } else {
+        initial.swap_exact_base_in(-net_quote / initial.price, fee_fraction)
+    };
+    (final_state, final_state.price)
+}
+
+/// Base input needed to extract a fraction of the pool's quote reserves.
+/// The full quote reserve is only approached asymptotically, so the cost
+/// grows without bound as output_fraction approaches 1.
+/// The fee is charged on the gross input: x_in = x * f / (1 - f) / (1 - fee).
+#[allow(dead_code)]
+fn extraction_cost(state: CpmmState, output_fraction: f64, fee_fraction: f64) -> f64 {
+    if output_fraction <= 0.0 {
+        return 0.0;
+    }
+    if output_fraction >= 1.0 {
+        return f64::INFINITY;
+    }
+    let net_base_in = state.base_reserves() * output_fraction / (1.0 - output_fraction);
+    net_base_in / (1.0 - fee_fraction)
+}
+
+/// Computes the exit trade that realizes a target PnL (in quote) on a base position.
+/// The trader sells position_base into a pool with the given pool's liquidity;
+/// this solves for the pre-trade pool price (the exit price) at which the sale
+/// proceeds equal the cost basis plus target_pnl, with fees paid on the base input.
+/// Returns a no-trade result when the position or target proceeds are not positive.
+#[allow(dead_code)]
+fn exit_for_pnl(
+    entry_price: f64,
+    position_base: f64,
+    target_pnl: f64,
+    fee_fraction: f64,
+    pool: CpmmState,
+) -> Result<TradeResult, CpmmError> {
+    let proceeds = position_base * entry_price + target_pnl;
+    if position_base <= 0.0 || proceeds <= 0.0 {
+        return Ok(TradeResult::compute(pool, pool, fee_fraction));
+    }
+
+    // Base reaching the pool after the fee, which is collected on top of it
+    let base_in = position_base / (1.0 + fee_fraction);
+    let liquidity = pool.liquidity;
+
+    // Selling n base at sqrt price s yields L * n * s^2 / (L + n * s) quote.
+    // Solve L * n * s^2 - Q * n * s - Q * L = 0 for s.
+    let a = liquidity * base_in;
+    let b = -proceeds * base_in;
+    let c = -proceeds * liquidity;
+    let sqrt_price = (-b + (b * b - 4.0 * a * c).sqrt()) / (2.0 * a);
+
+    let exit = CpmmState::new(liquidity, sqrt_price * sqrt_price)?;
+    let final_base = exit.base_reserves() + base_in;
+    let final_state = CpmmState::new(liquidity, (liquidity / final_base).powi(2))?;
+    Ok(TradeResult::compute(exit, final_state, fee_fraction))
+}
+
+/// Target reserve ratio quote/base for a price, independent of liquidity.
+/// Since P = y / x, the ratio is the price itself.
+#[allow(dead_code)]
+fn reserve_ratio_at_price(price: f64) -> f64 {
+    price
+}
+
+/// Share of pool value held in the base token at a price.
+/// For a CPMM the value split is always 0.5: base value x * P = L * sqrt(P)
+/// equals quote value y = L * sqrt(P) at every price, so the pool rebalances
+/// to keep half its value in each token.
+#[allow(dead_code)]
+fn value_ratio_at_price(_price: f64) -> f64 {
+    0.5
+}
+
+/// Rows (less one) of the price sweep the Download CSV button exports.
+const SWEEP_CSV_STEPS: usize = 100;
+
+/// Header of the `sweep_csv` table.
+const SWEEP_CSV_HEADER: &str = "final_price,base_reserves,quote_reserves,\
+base_wallet_delta_net,quote_wallet_delta_net,base_fee,quote_fee";
+
+/// Table of trades from `initial` to final prices across the slider's range,
+/// as CSV for plotting elsewhere. Has a header and `steps + 1` rows evenly
+/// spaced in slider position from 0 to 1, so the middle row of an even
+/// `steps` is the center price. Values use full precision.
+fn sweep_csv(initial: CpmmState, center: f64, decades: f64, fee: f64, steps: usize) -> String {
+    let mut csv = String::from(SWEEP_CSV_HEADER);
+    csv.push('\n');
+    for i in 0..=steps {
+        let slider = if steps > 0 {
+            i as f64 / steps as f64
+        } else {
+            0.5
+        };
+        let final_state = CpmmState {
+            price: slider_to_price(slider, center, decades),
+            ..initial
+        };
+        let trade = TradeResult::compute(initial, final_state, fee);
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            final_state.price,
+            final_state.base_reserves(),
+            final_state.quote_reserves(),
+            trade.base_wallet_delta_net,
+            trade.quote_wallet_delta_net,
+            trade.base_fee_collected,
+            trade.quote_fee_collected,
+        ));
+    }
+    csv
+}
+
+/// Value of each token leg of a CPMM across the slider's price range, for plotting.
+/// Samples `steps` prices evenly in slider position (so logarithmically in
+/// price) and returns (price, base_value, quote_value), valued in quote.
+/// The legs sum to the pool's TVL, 2 * L * sqrt(P), at every price.
+fn composition_sweep(
+    liquidity: f64,
+    center: f64,
+    decades: f64,
+    steps: usize,
+) -> Vec<(f64, f64, f64)> {
+    (0..steps)
+        .map(|i| {
+            let slider = if steps > 1 {
+                i as f64 / (steps - 1) as f64
+            } else {
+                0.5
+            };
+            let state = CpmmState {
+                liquidity,
+                price: slider_to_price(slider, center, decades),
+            };
+            (
+                state.price,
+                state.base_reserves() * state.price,
+                state.quote_reserves(),
+            )
+        })
+        .collect()
+}
+
+/// Base depth a pool offers within ±pct of its price, order-book style:
+/// the base bought to push the price up to P * (1 + pct) plus the base sold to
+/// push it down to P * (1 - pct). With x = L / sqrt(P) this is
+/// x * (1 / sqrt(1 - pct) - 1 / sqrt(1 + pct)). pct is a fraction in (0, 1).
+#[allow(dead_code)]
+fn depth_within(state: CpmmState, pct: f64) -> f64 {
+    state.base_reserves() * depth_factor(pct)
+}
+
+/// Depth within ±pct per unit of base reserves.
+fn depth_factor(pct: f64) -> f64 {
+    1.0 / (1.0 - pct).sqrt() - 1.0 / (1.0 + pct).sqrt()
+}
+
+/// CPMM reserves whose depth within ±pct matches a target order-book depth.
+/// Inverts `depth_within`: depth is linear in the base reserves, so
+/// x = target / depth_factor(pct) and y = x * P. Returns (base, quote).
+#[allow(dead_code)]
+fn reserves_for_target_depth(target_depth_base: f64, pct: f64, price: f64) -> (f64, f64) {
+    let base = target_depth_base / depth_factor(pct);
+    (base, base * price)
+}
+
+/// Liquidity needed so a percentile trade from a size profile stays under an impact cap.
+/// The trade at `percentile` (in [0, 1], nearest rank) buys that much base; a
+/// buy of s base from reserves x executes at a (fee-free) impact of s / (x - s),
+/// so the cap requires x = s * (1 + max_impact) / max_impact.
+#[allow(dead_code)]
+fn liquidity_for_profile(trade_sizes: &[f64], percentile: f64, max_impact: f64, price: f64) -> f64 {
+    if trade_sizes.is_empty() {
+        return 0.0;
+    }
+    if max_impact <= 0.0 {
+        return f64::INFINITY;
+    }
+
+    let mut sorted = trade_sizes.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = (percentile.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+    let size = sorted[rank.saturating_sub(1)].abs();
+
+    let base_reserves = size * (1.0 + max_impact) / max_impact;
+    base_reserves * price.sqrt()
+}
+
+/// Price impact of selling each base size into the pool.
+/// Impact is the fractional shortfall of the execution price, quote received
+/// per base paid including the fee, below the spot price.
+#[allow(dead_code)]
+fn impact_curve(state: CpmmState, base_sizes: &[f64], fee_fraction: f64) -> Vec<f64> {
+    base_sizes
+        .iter()
+        .map(|&base_in| {
+            let (quote_out, _) = state.swap_exact_base_in(base_in, fee_fraction);
+            1.0 - quote_out / base_in / state.price
+        })
+        .collect()
+}
+
+/// CPMM liquidity implied by a quoted impact for a base sale of a given size.
+/// Inverts `impact_curve`: with e = base_in * (1 - fee) entering reserves x,
+/// impact = 1 - (1 - fee) * x / (x + e), so x = (1 - impact) * e / (impact - fee)
+/// and L = x * sqrt(P). An impact no larger than the fee implies infinite depth.
+fn implied_liquidity(base_in: f64, observed_impact: f64, price: f64, fee_fraction: f64) -> f64 {
+    if observed_impact <= fee_fraction {
+        return f64::INFINITY;
+    }
+    let effective_in = base_in * (1.0 - fee_fraction);
+    let base_reserves = (1.0 - observed_impact) * effective_in / (observed_impact - fee_fraction);
+    base_reserves.max(0.0) * price.sqrt()
+}
+
+/// Marginal bid and ask of a pool at spot price P.
+/// Selling base yields P * (1 - fee) per base and buying it costs
+/// P / (1 - fee), since the fee is taken from the input either way.
+#[allow(dead_code)]
+fn bid_ask(price: f64, fee_fraction: f64) -> (f64, f64) {
+    (price * (1.0 - fee_fraction), price / (1.0 - fee_fraction))
+}
+
+/// Mid price and fee implied by a venue's bid and ask.
+/// mid = sqrt(bid * ask) is exact for `bid_ask` quotes; the fee
+/// (ask / bid - 1) / 2 is correct to first order in the fee.
+/// Crossed quotes (bid > ask) give a negative fee.
+fn mid_and_fee_from_quotes(bid: f64, ask: f64) -> (f64, f64) {
+    ((bid * ask).sqrt(), (ask / bid - 1.0) / 2.0)
+}
+
+/// Bid and ask around a mid price, skewed by a market maker's inventory.
+/// `inventory_fraction` runs from -1 (all quote) through 0 (balanced) to 1
+/// (all base). Each side's half-spread is base_fee scaled by
+/// 1 ± skew_factor * inventory_fraction: a long-base maker widens the bid and
+/// narrows the ask, lowering both quotes to shed base.
+fn skewed_quotes(mid: f64, base_fee: f64, inventory_fraction: f64, skew_factor: f64) -> (f64, f64) {
+    let skew = skew_factor * inventory_fraction;
+    let bid = mid * (1.0 - base_fee * (1.0 + skew));
+    let ask = mid * (1.0 + base_fee * (1.0 - skew));
+    (bid, ask)
+}
+
+/// The same price move priced at two fees, for a side-by-side comparison.
+fn compare_fees<S: PoolState + Copy>(
+    initial: S,
+    final_state: S,
+    fee_a: f64,
+    fee_b: f64,
+) -> (TradeResult, TradeResult) {
+    (
+        TradeResult::compute(initial, final_state, fee_a),
+        TradeResult::compute(initial, final_state, fee_b),
+    )
+}
+
+/// One side of a trade as wallet deltas: the gross amount exchanged with the
+/// pool, the fee charged on it, and the net amount, gross - fee.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SideBreakdown {
+    gross: f64,
+    fee: f64,
+    net: f64,
+}
+
+/// A trade split into the side the trader pays and the side they receive.
+/// Only the side the fee mode charges has a fee; the other has net == gross.
+/// With nothing exchanged, quote counts as the paid side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Breakdown {
+    base_paid: bool,
+    paid: SideBreakdown,
+    received: SideBreakdown,
+}
+
+/// Gross, fee and net amounts of a trade by side.
+fn breakdown(result: &TradeResult) -> Breakdown {
+    let base = SideBreakdown {
+        gross: result.base_wallet_delta,
+        fee: result.base_fee_collected,
+        net: result.base_wallet_delta_net,
+    };
+    let quote = SideBreakdown {
+        gross: result.quote_wallet_delta,
+        fee: result.quote_fee_collected,
+        net: result.quote_wallet_delta_net,
+    };
+    let base_paid = result.base_wallet_delta < 0.0;
+    let (paid, received) = if base_paid {
+        (base, quote)
+    } else {
+        (quote, base)
+    };
+    Breakdown {
+        base_paid,
+        paid,
+        received,
+    }
+}
+
+/// Checks a fee percentage typed by the user: it must be in [0, 100).
+/// A 100% fee would leave nothing of the input to trade.
+fn validate_fee(percent: f64) -> Result<f64, String> {
+    if (0.0..100.0).contains(&percent) {
+        Ok(percent)
+    } else {
+        Err("Fee % must be at least 0 and below 100".to_string())
+    }
+}
+
+/// Computes a price-to-price trade from raw calculator inputs.
+/// Validates every input so that bad values surface as an error message
+/// instead of a panic.
+fn trade_from_inputs(
+    initial_liquidity: f64,
+    initial_price: f64,
+    final_price: f64,
+    fee_percent: f64,
+) -> Result<TradeResult, String> {
+    if !(0.0..100.0).contains(&fee_percent) {
+        return Err("Fee percent must be in [0, 100)".to_string());
+    }
+    let initial = CpmmState::new(initial_liquidity, initial_price).map_err(|e| e.to_string())?;
+    trade_to_marginal_price(initial, final_price, fee_percent / 100.0).map_err(|e| e.to_string())
+}
+
+/// Fraction of a price move that falls inside the range [lower, upper].
+/// The move may go in either direction. A zero-length move counts as
+/// fully inside when its price lies within the range.
+fn range_overlap_fraction(start_price: f64, end_price: f64, lower: f64, upper: f64) -> f64 {
+    let (low, high) = if start_price <= end_price {
+        (start_price, end_price)
+    } else {
+        (end_price, start_price)
+    };
+
+    if high == low {
+        return if (lower..=upper).contains(&low) {
+            1.0
+        } else {
+            0.0
+        };
+    }
+
+    let overlap = (high.min(upper) - low.max(lower)).max(0.0);
+    overlap / (high - low)
+}
+
+/// Fees accrued to a position range over a price move.
+/// Prorates total_fee by the overlap of the price interval with [lower, upper],
+/// as in concentrated liquidity fee accounting.
+#[allow(dead_code)]
+fn fees_in_range(start_price: f64, end_price: f64, lower: f64, upper: f64, total_fee: f64) -> f64 {
+    total_fee * range_overlap_fraction(start_price, end_price, lower, upper)
+}
+
+/// Fraction of a price move's fees captured by a position bounded by [lower, upper].
+/// A position narrower than the move captures only the overlapping part; one
+/// that covers the whole move captures everything.
+fn fee_capture_fraction(lower: f64, upper: f64, start_price: f64, end_price: f64) -> f64 {
+    range_overlap_fraction(start_price, end_price, lower, upper)
+}
+
+/// Small deterministic PRNG (SplitMix64) so simulations replay from a seed.
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform sample in [0, 1) with 53 bits of precision.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal sample (Box-Muller).
+    fn next_normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// Fees earned by the pool while arbitrage tracks a simulated GBM price path.
+/// The path spans one unit of time split into `steps`; vol and drift are per
+/// unit of time. Each step the external price moves by
+///   P' = P * exp((drift - vol^2 / 2) * dt + vol * sqrt(dt) * Z)
+/// and an arbitrageur trades the pool to P', paying the fee on the input.
+/// Fees are valued in quote at the price after each step. The same seed
+/// always produces the same path.
+#[allow(dead_code)]
+fn expected_fees_gbm(
+    initial: CpmmState,
+    vol: f64,
+    drift: f64,
+    steps: usize,
+    seed: u64,
+    fee_fraction: f64,
+) -> f64 {
+    let mut rng = SplitMix64::new(seed);
+    let dt = 1.0 / steps as f64;
+    let mut pool = initial;
+    let mut fees = 0.0;
+    for _ in 0..steps {
+        let shock = vol * dt.sqrt() * rng.next_normal();
+        let next = CpmmState {
+            liquidity: pool.liquidity,
+            price: pool.price * ((drift - vol * vol / 2.0) * dt + shock).exp(),
+        };
+        let trade = TradeResult::compute(pool, next, fee_fraction);
+        fees += trade.quote_fee_collected + trade.base_fee_collected * next.price;
+        pool = next;
+    }
+    fees
+}
+
+/// Log-normal volatility of one simulated random-walk step.
+const SIMULATION_STEP_VOL: f64 = 0.01;
+
+/// Largest number of trades a fee simulation replays.
+const MAX_SIMULATION_STEPS: usize = 100_000;
+
+/// Random-walk price multipliers: each step is exp(step_vol * Z).
+/// The same seed always produces the same moves.
+fn random_walk_moves(seed: u64, count: usize, step_vol: f64) -> Vec<f64> {
+    let mut rng = SplitMix64::new(seed);
+    (0..count)
+        .map(|_| (step_vol * rng.next_normal()).exp())
+        .collect()
+}
+
+/// Base and quote fees collected while the pool follows a sequence of moves.
+/// Each move multiplies the price at constant liquidity, and the trade between
+/// consecutive states pays its fee on the input side.
+fn simulate_fees(start: CpmmState, moves: &[f64], fee_fraction: f64) -> (f64, f64) {
+    let mut pool = start;
+    let mut base_fees = 0.0;
+    let mut quote_fees = 0.0;
+    for factor in moves {
+        let next = CpmmState {
+            liquidity: pool.liquidity,
+            price: pool.price * factor,
+        };
+        let trade = TradeResult::compute(pool, next, fee_fraction);
+        base_fees += trade.base_fee_collected;
+        quote_fees += trade.quote_fee_collected;
+        pool = next;
+    }
+    (base_fees, quote_fees)
+}
+
+/// Swaps a base input through two pools: base for the intermediate token in
+/// `pool_ab`, then the intermediate for quote in `pool_bc`. Each hop is an
+/// exact-input swap paying `fee_fraction` on its own input.
+/// Returns the quote received and both pools after the trade.
+fn route_two_hop(
+    pool_ab: CpmmState,
+    pool_bc: CpmmState,
+    base_in: f64,
+    fee_fraction: f64,
+) -> (f64, CpmmState, CpmmState) {
+    let (intermediate, next_ab) = pool_ab.swap_exact_base_in(base_in, fee_fraction);
+    let (quote_out, next_bc) = pool_bc.swap_exact_base_in(intermediate, fee_fraction);
+    (quote_out, next_ab, next_bc)
+}
+
+/// Impermanent loss of a full-range LP position between two pool states: the
+/// fractional value of the position versus holding the initial reserves.
+/// With price ratio r = P_final / P_initial,
+///   IL = 2 * sqrt(r) / (1 + r) - 1
+/// which is 0 when the price is unchanged and negative for any move.
+fn impermanent_loss(initial: CpmmState, final_state: CpmmState) -> f64 {
+    let ratio = final_state.price / initial.price;
+    2.0 * ratio.sqrt() / (1.0 + ratio) - 1.0
+}
+
+/// Daily fee revenue of a pool trading `volume` a day at `fee_fraction`, in
+/// the volume's unit. A pool without liquidity cannot trade, so it earns nothing.
+fn fee_revenue(volume: f64, fee_fraction: f64, liquidity: f64) -> f64 {
+    if liquidity <= 0.0 {
+        return 0.0;
+    }
+    volume * fee_fraction
+}
+
+/// Simple (non-compounding) APR of a daily revenue on a position:
+/// daily * 365 / position value. 0 for a worthless position.
+fn simple_apr(daily_revenue: f64, position_value: f64) -> f64 {
+    if position_value <= 0.0 {
+        return 0.0;
+    }
+    daily_revenue * 365.0 / position_value
+}
+
+/// Days an LP must hold a position for fee income to offset impermanent loss.
+/// Uses simple (non-compounding) accrual: il_fraction / (fee_apr / 365).
+/// Returns infinity when the fee APR is not positive.
+fn breakeven_days(fee_apr: f64, il_fraction: f64) -> f64 {
+    if fee_apr <= 0.0 {
+        return f64::INFINITY;
+    }
+    il_fraction.abs() / (fee_apr / 365.0)
+}
+
+/// Steps until arbitrage closes half the gap between the pool and an anchor price.
+/// Each step closes `close_fraction_per_step` of the remaining gap, so the gap
+/// decays geometrically and the half-life does not depend on its size. The result
+/// is fractional. A pool already at the anchor, or pressure that closes the whole
+/// gap at once, gives 0; no pressure never closes the gap.
+fn price_halflife_steps(pool_price: f64, anchor_price: f64, close_fraction_per_step: f64) -> f64 {
+    if pool_price == anchor_price || close_fraction_per_step >= 1.0 {
+        return 0.0;
+    }
+    if close_fraction_per_step <= 0.0 {
+        return f64::INFINITY;
+    }
+    0.5f64.ln() / (1.0 - close_fraction_per_step).ln()
+}
+
+/// Annualized effect of a price move on a passive holder of the base token.
+/// The move's return in quote, final / initial - 1, is scaled to a year
+/// without compounding, like an APR: positive is a boost, negative a drag.
+/// A move over no time at all annualizes to an infinite effect.
+fn annualized_price_effect(initial_price: f64, final_price: f64, horizon_days: f64) -> f64 {
+    let price_return = final_price / initial_price - 1.0;
+    if price_return == 0.0 {
+        return 0.0;
+    }
+    if horizon_days <= 0.0 {
+        return price_return.signum() * f64::INFINITY;
+    }
+    price_return * 365.0 / horizon_days
+}
+
+/// Width of a range (limit) order as a price ratio: one 1 bp tick.
+const LIMIT_ORDER_TICK: f64 = 1.0001;
+
+/// Base filled on a limit order modeled as a one-tick range position.
+/// The order occupies [order_price, order_price * LIMIT_ORDER_TICK] and fills
+/// in proportion to how much of that band the price move crosses.
+#[allow(dead_code)]
+fn limit_fill(order_price: f64, order_size_base: f64, start_price: f64, end_price: f64) -> f64 {
+    let lower = order_price;
+    let upper = order_price * LIMIT_ORDER_TICK;
+    let low = start_price.min(end_price);
+    let high = start_price.max(end_price);
+    let crossed = (high.min(upper) - low.max(lower)).max(0.0);
+    order_size_base * crossed / (upper - lower)
+}
+
+/// Converts a slider value in [0, 1] to a logarithmic price.
+/// Maps 0.5 to the center price, with exponential scaling.
+fn slider_to_price(slider_value: f64, center_price: f64, decades: f64) -> f64 {
+    let exponent = (slider_value - 0.5) * 2.0 * decades;
+    center_price * 10.0_f64.powf(exponent)
+}
+
+/// Converts a price to a slider value in [0, 1].
+fn price_to_slider(price: f64, center_price: f64, decades: f64) -> f64 {
+    if price <= 0.0 || center_price <= 0.0 {
+        return 0.5;
+    }
+    let exponent = (price / center_price).log10();
+    0.5 + exponent / (2.0 * decades)
+}
+
+/// Decades either side of the center that the price sliders span by default.
+const DEFAULT_SLIDER_DECADES: f64 = 3.0;
+
+/// Decades left beyond the final price by an automatic slider range.
+const AUTO_SLIDER_MARGIN_DECADES: f64 = 1.0;
+
+/// Whether a slider with this center and width can show the price.
+fn slider_covers(price: f64, center_price: f64, decades: f64) -> bool {
+    (0.0..=1.0).contains(&price_to_slider(price, center_price, decades))
+}
+
+/// Slider range for a pair of prices, as (center, decades): centered on the
+/// initial price and wide enough to reach the final price with a decade to
+/// spare, but never narrower than the default.
+fn auto_slider_range(initial: f64, final_price: f64) -> (f64, f64) {
+    let span = (final_price / initial).log10().abs();
+    let decades = (span + AUTO_SLIDER_MARGIN_DECADES)
+        .ceil()
+        .max(DEFAULT_SLIDER_DECADES);
+    (initial, decades)
+}
+
+/// Decades of price one step of a price slider moves (a factor of about
+/// 1.4%); at the default 3 decades this is a step of 0.001.
+const SLIDER_STEP_DECADES: f64 = 0.006;
+
+/// Bounds of the slider step, so a tiny or huge range stays draggable.
+const MIN_SLIDER_STEP: f64 = 1e-6;
+const MAX_SLIDER_STEP: f64 = 0.01;
+
+/// Slider step giving the same multiplicative resolution at any range.
+/// The slider spans 2 * decades decades, so a step of
+///   SLIDER_STEP_DECADES / (2 * decades)
+/// always moves the price by a factor of 10^SLIDER_STEP_DECADES.
+fn slider_step_for_decades(decades: f64) -> f64 {
+    (SLIDER_STEP_DECADES / (2.0 * decades)).clamp(MIN_SLIDER_STEP, MAX_SLIDER_STEP)
+}
+
+/// Ratio between adjacent ticks of a tick-based AMM: price = TICK_BASE^tick.
+const TICK_BASE: f64 = 1.0001;
+
+/// Largest tick magnitude (as in Uniswap v3), and the widest tick spacing.
+const MAX_TICK: i32 = 887_272;
+const MAX_TICK_SPACING: u32 = 16_384;
+
+/// Nearest tick to a price. Prices beyond the tick range clamp to its ends.
+fn price_to_tick(price: f64) -> i32 {
+    let tick = (price.ln() / TICK_BASE.ln()).round() as i32;
+    tick.clamp(-MAX_TICK, MAX_TICK)
+}
+
+/// Price at a tick: 1.0001^tick.
+fn tick_to_price(tick: i32) -> f64 {
+    TICK_BASE.powf(tick as f64)
+}
+
+/// Nearest tick to a price that is a multiple of `tick_spacing`.
+/// Rounds the fractional tick once, so a price just past the midpoint of two
+/// valid ticks goes to the nearer one rather than through an extra rounding.
+fn nearest_valid_tick(price: f64, tick_spacing: u32) -> i32 {
+    let spacing = tick_spacing.max(1) as f64;
+    let steps = (price.ln() / TICK_BASE.ln() / spacing).round();
+    let max_steps = (MAX_TICK as f64 / spacing).floor();
+    (steps.clamp(-max_steps, max_steps) * spacing) as i32
+}
+
+/// Relative step of an arrow-key nudge, and with Shift held.
+const NUDGE_STEP: f64 = 0.01;
+const NUDGE_SHIFT_STEP: f64 = 0.1;
+
+/// Value after an arrow-key nudge in `direction` (+1 up, -1 down).
+/// The step is relative to the value (1%, or 10% with Shift) so it works the
+/// same across decades. A zero value has no magnitude to scale, so it moves
+/// by the step itself.
+fn nudge_value(current: f64, direction: i32, shift: bool) -> f64 {
+    let step = if shift { NUDGE_SHIFT_STEP } else { NUDGE_STEP };
+    if current == 0.0 {
+        return direction as f64 * step;
+    }
+    current + direction as f64 * step * current.abs()
+}
+
+/// Quotes a price from the other side of the pair: quote per base becomes base per quote.
+/// Inverting twice returns the original price.
+fn invert_price(price: f64) -> f64 {
+    1.0 / price
+}
+
+/// Collateral ratio of a position valued at the pool price after a trade.
+/// ratio = collateral_base * price / debt_quote
+/// Returns infinity when there is no debt.
+fn collateral_ratio_after(trade_final_price: f64, collateral_base: f64, debt_quote: f64) -> f64 {
+    if debt_quote <= 0.0 {
+        return f64::INFINITY;
+    }
+    collateral_base * trade_final_price / debt_quote
+}
+
+/// Blends the pool price with an external (oracle) price.
+/// weight = 0 gives the pool price, weight = 1 the external price.
+fn blended_price(pool_price: f64, external_price: f64, weight: f64) -> f64 {
+    let weight = weight.clamp(0.0, 1.0);
+    (1.0 - weight) * pool_price + weight * external_price
+}
+
+/// Upper bound on the fee fraction considered by revenue modeling.
+const MAX_FEE_FRACTION: f64 = 1.0;
+
+/// Protocol revenue at a fee when volume decays with the fee.
+/// volume(fee) = base_volume * exp(-elasticity * fee)
+fn elastic_fee_revenue(base_volume: f64, elasticity: f64, fee_fraction: f64) -> f64 {
+    fee_fraction * base_volume * (-elasticity * fee_fraction).exp()
+}
+
+/// Fee fraction that maximizes `elastic_fee_revenue`.
+/// The revenue curve peaks at fee = 1 / elasticity, capped at MAX_FEE_FRACTION.
+/// Returns 0 when there is no volume to earn on.
+fn revenue_maximizing_fee(base_volume: f64, elasticity: f64) -> f64 {
+    if base_volume <= 0.0 {
+        return 0.0;
+    }
+    if elasticity <= 0.0 {
+        return MAX_FEE_FRACTION;
+    }
+    (1.0 / elasticity).min(MAX_FEE_FRACTION)
+}
+
+/// Fee cost of a marginal round trip (sell, then buy back) as a fraction of
+/// the amount traded. Each leg keeps 1 - fee, so the cost is 1 - (1 - fee)^2.
+/// Price impact vanishes for a marginal trade, leaving only the fees.
+#[allow(dead_code)]
+fn round_trip(fee_fraction: f64) -> f64 {
+    1.0 - (1.0 - fee_fraction) * (1.0 - fee_fraction)
+}
+
+/// Largest single-leg fee whose round trip costs at most `budget_fraction`.
+/// Inverts `round_trip`: fee = 1 - sqrt(1 - budget), which is about budget / 2
+/// for small budgets. Budgets outside [0, 1] are clamped.
+#[allow(dead_code)]
+fn max_fee_for_roundtrip_budget(budget_fraction: f64) -> f64 {
+    1.0 - (1.0 - budget_fraction.clamp(0.0, 1.0)).sqrt()
+}
+
+/// Fee left after the volume-tier rebate a trader qualifies for.
+/// Each tier is (volume threshold, rebate), with the rebate in the same units as
+/// base_fee. The tier with the highest threshold at or below cumulative_volume
+/// applies, so tiers may be listed in any order. A rebate larger than the base
+/// fee gives a negative result: the trader is paid to trade.
+fn effective_fee_after_rebate(base_fee: f64, cumulative_volume: f64, tiers: &[(f64, f64)]) -> f64 {
+    let rebate = tiers
+        .iter()
+        .filter(|(threshold, _)| *threshold <= cumulative_volume)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map_or(0.0, |(_, rebate)| *rebate);
+    base_fee - rebate
+}
+
+/// Parses rebate tiers written as `threshold:rebate` pairs separated by commas.
+/// Each half is read like any typed number; the rebate is in fee %, so a
+/// trailing `%` is optional. An empty string means no tiers. Returns None if
+/// any pair is malformed.
+fn parse_rebate_tiers(text: &str) -> Option<Vec<(f64, f64)>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (threshold, rebate) = pair.split_once(':')?;
+            Some((parse_user_number(threshold)?, parse_user_percent(rebate)?))
+        })
+        .collect()
+}
+
+/// Formats rebate tiers in the form accepted by `parse_rebate_tiers`.
+fn format_rebate_tiers(tiers: &[(f64, f64)]) -> String {
+    tiers
+        .iter()
+        .map(|(threshold, rebate)| format!("{}:{}", threshold, rebate))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Log return of a price move: ln(final / initial).
+/// Log returns of sequential moves add up to the return of the whole move.
+fn log_return(initial_price: f64, final_price: f64) -> f64 {
+    (final_price / initial_price).ln()
+}
+
+/// Log-space price change of a trade: ln(final / initial).
+/// Log drifts of sequential trades add up to the drift of the whole session.
+fn log_drift(initial_price: f64, final_price: f64) -> f64 {
+    log_return(initial_price, final_price)
+}
+
+/// Checks whether a slider moved far enough to warrant a recompute.
+/// Changes smaller than min_delta are treated as jitter and ignored.
+fn slider_delta_exceeds_min(previous: f64, next: f64, min_delta: f64) -> bool {
+    (next - previous).abs() >= min_delta
+}
+
+/// Digits shown by default, and the most the precision input accepts.
+const DEFAULT_DISPLAY_PRECISION: usize = 6;
+const MAX_DISPLAY_PRECISION: usize = 17;
+
+/// Formats a number with `precision` digits after the decimal point.
+/// Very small and very large magnitudes switch to scientific notation; large
+/// ones show two fewer mantissa digits.
+fn format_number(value: f64, precision: usize) -> String {
+    if value.abs() < 0.0001 && value != 0.0 {
+        format!("{:.*e}", precision, value)
+    } else if value.abs() >= 1_000_000.0 {
+        format!("{:.*e}", precision.saturating_sub(2), value)
+    } else {
+        format!("{:.*}", precision, value)
+    }
+}
+
+/// Formats a fraction as a percentage with `precision` digits after the
+/// decimal point, e.g. 0.003 as `0.300%` at precision 3.
+fn format_percent(fraction: f64, precision: usize) -> String {
+    format!("{}%", format_number(fraction * 100.0, precision))
+}
+
+/// Magnitude from which grouped numbers fall back to scientific notation.
+/// Grouping keeps millions and billions readable, so fixed notation runs
+/// further than in `format_number`.
+const GROUPED_SCIENTIFIC_THRESHOLD: f64 = 1e15;
+
+/// Inserts comma separators into the integer part of a formatted number.
+/// Scientific notation is returned unchanged.
+fn group_thousands(text: &str) -> String {
+    if text.contains(['e', 'E']) {
+        return text.to_string();
+    }
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let (integer, fraction) = match unsigned.find('.') {
+        Some(dot) => unsigned.split_at(dot),
+        None => (unsigned, ""),
+    };
+    let mut grouped = String::with_capacity(text.len() + integer.len() / 3);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!("{}{}{}", sign, grouped, fraction)
+}
+
+/// Like `format_number`, but with thousands separators.
+/// Values up to GROUPED_SCIENTIFIC_THRESHOLD stay in fixed notation.
+fn format_number_grouped(value: f64, precision: usize) -> String {
+    let magnitude = value.abs();
+    if (1_000_000.0..GROUPED_SCIENTIFIC_THRESHOLD).contains(&magnitude) {
+        group_thousands(&format!("{:.*}", precision, value))
+    } else {
+        group_thousands(&format_number(value, precision))
+    }
+}
+
+/// Magnitude suffixes of abbreviated numbers, largest first.
+const ABBREVIATIONS: &[(f64, &str)] = &[(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "K")];
+
+/// Formats a number with a magnitude suffix, e.g. 1200 as `1.2K` at
+/// precision 1. Magnitudes below a thousand or from a thousand trillion up,
+/// and non-finite values, fall back to `format_number`. When rounding carries
+/// the mantissa to 1000 the next suffix is used, so 999,999 at precision 1 is
+/// `1.0M` rather than `1000.0K`.
+fn format_abbreviated(value: f64, precision: usize) -> String {
+    let magnitude = value.abs();
+    if magnitude.is_nan() || magnitude >= 1e15 {
+        return format_number(value, precision);
+    }
+    // Largest suffix at or below the magnitude; None below a thousand
+    let mut index = ABBREVIATIONS
+        .iter()
+        .position(|(scale, _)| magnitude >= *scale);
+    let scale = index.map_or(1.0, |i| ABBREVIATIONS[i].0);
+    let rounded: f64 = format!("{:.*}", precision, magnitude / scale)
+        .parse()
+        .unwrap_or(0.0);
+    if rounded >= 1000.0 {
+        index = match index {
+            None => Some(ABBREVIATIONS.len() - 1),
+            Some(i) => Some(i.saturating_sub(1)),
+        };
+    }
+    match index {
+        Some(i) => {
+            let (scale, suffix) = ABBREVIATIONS[i];
+            format!("{:.*}{}", precision, value / scale, suffix)
+        }
+        None => format_number(value, precision),
+    }
+}
+
+/// Parses a number typed into a field. Surrounding whitespace and grouping
+/// commas are ignored, scientific notation is accepted, and a trailing `%`
+/// divides by 100. Returns None for anything else, including NaN and
+/// infinity, so listeners leave the state unchanged.
+fn parse_user_number(s: &str) -> Option<f64> {
+    let text = s.trim();
+    let (text, divisor) = match text.strip_suffix('%') {
+        Some(rest) => (rest.trim_end(), 100.0),
+        None => (text, 1.0),
+    };
+    let value: f64 = text.replace(',', "").parse().ok()?;
+    value.is_finite().then_some(value / divisor)
+}
+
+/// Parses a field whose value is already in percent, so `0.3` and `0.3%`
+/// both mean 0.3%.
+fn parse_user_percent(s: &str) -> Option<f64> {
+    let text = s.trim();
+    parse_user_number(text.strip_suffix('%').unwrap_or(text))
+}
+
+/// Parses a non-negative whole number typed into a field, as leniently as
+/// `parse_user_number`. Exact up to 2^53.
+fn parse_user_count(s: &str) -> Option<u64> {
+    let value = parse_user_number(s)?;
+    (value >= 0.0 && value.fract() == 0.0 && value <= u64::MAX as f64).then_some(value as u64)
+}
+
+/// AMM invariant the calculator prices trades with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum AmmModel {
+    /// Full-range constant product (x·y = k).
+    #[default]
+    ConstantProduct,
+    /// Curve stableswap with amplification A.
+    Stableswap,
+    /// Constant product concentrated into [price_lower, price_upper].
+    Concentrated,
+    /// Balancer weighted product with weights w_base and w_quote.
+    Weighted,
+}
+
+impl AmmModel {
+    /// Options of the model selector, as (value, label).
+    const OPTIONS: &[(&str, &str)] = &[
+        ("constantProduct", "Constant Product"),
+        ("stableswap", "Stableswap"),
+        ("concentrated", "Concentrated Liquidity"),
+        ("weighted", "Weighted (Balancer)"),
+    ];
+
+    /// Value of this model in the selector.
+    fn as_str(self) -> &'static str {
+        match self {
+            AmmModel::ConstantProduct => "constantProduct",
+            AmmModel::Stableswap => "stableswap",
+            AmmModel::Concentrated => "concentrated",
+            AmmModel::Weighted => "weighted",
+        }
+    }
+
+    /// Parses a selector value.
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "constantProduct" => Some(AmmModel::ConstantProduct),
+            "stableswap" => Some(AmmModel::Stableswap),
+            "concentrated" => Some(AmmModel::Concentrated),
+            "weighted" => Some(AmmModel::Weighted),
+            _ => None,
+        }
+    }
+}
+
+/// Color theme of the calculator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Theme {
+    /// Follow the system's `prefers-color-scheme`.
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// Theme the toggle switches to next: Auto, Light, Dark, then Auto again.
+    fn next(self) -> Self {
+        match self {
+            Theme::Auto => Theme::Light,
+            Theme::Light => Theme::Dark,
+            Theme::Dark => Theme::Auto,
+        }
+    }
+
+    /// Text of the theme toggle.
+    fn label(self) -> &'static str {
+        match self {
+            Theme::Auto => "Theme: Auto",
+            Theme::Light => "Theme: Light",
+            Theme::Dark => "Theme: Dark",
+        }
+    }
+}
+
+/// Whether a theme renders dark, given the system preference.
+fn resolve_theme(theme: Theme, system_dark: bool) -> bool {
+    match theme {
+        Theme::Auto => system_dark,
+        Theme::Light => false,
+        Theme::Dark => true,
+    }
+}
+
+/// How computed, read-only values are written out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum DisplayMode {
+    /// Every digit, as in the editable inputs.
+    #[default]
+    Full,
+    /// Thousands and up shortened with a K, M, B or T suffix.
+    Abbreviated,
+}
+
+impl DisplayMode {
+    /// Options of the display mode toggle, as (value, label).
+    const OPTIONS: &[(&str, &str)] = &[("full", "Full"), ("abbreviated", "Abbreviated")];
+
+    /// Value of this mode in the toggle.
+    fn as_str(self) -> &'static str {
+        match self {
+            DisplayMode::Full => "full",
+            DisplayMode::Abbreviated => "abbreviated",
+        }
+    }
+
+    /// Parses a toggle value.
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "full" => Some(DisplayMode::Full),
+            "abbreviated" => Some(DisplayMode::Abbreviated),
+            _ => None,
+        }
+    }
+}
+
+/// How the final price is entered: typed directly, or solved from a trade amount.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum EntryMode {
+    #[default]
+    Price,
+    /// An exact base input; the price falls.
+    BaseIn,
+    /// An exact quote input; the price rises.
+    QuoteIn,
+}
+
+impl EntryMode {
+    /// Options of the entry mode selector, as (value, label).
+    const OPTIONS: &[(&str, &str)] = &[
+        ("price", "Final Price"),
+        ("baseIn", "Base In"),
+        ("quoteIn", "Quote In"),
+    ];
+
+    /// Value of this mode in the selector.
+    fn as_str(self) -> &'static str {
+        match self {
+            EntryMode::Price => "price",
+            EntryMode::BaseIn => "baseIn",
+            EntryMode::QuoteIn => "quoteIn",
+        }
+    }
+
+    /// Parses a selector value.
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "price" => Some(EntryMode::Price),
+            "baseIn" => Some(EntryMode::BaseIn),
+            "quoteIn" => Some(EntryMode::QuoteIn),
+            _ => None,
+        }
+    }
+
+    /// The mode in internal token terms when base and quote are displayed swapped.
+    fn oriented(self, inverted: bool) -> Self {
+        match (self, inverted) {
+            (EntryMode::BaseIn, true) => EntryMode::QuoteIn,
+            (EntryMode::QuoteIn, true) => EntryMode::BaseIn,
+            (mode, _) => mode,
+        }
+    }
+}
+
+/// Final price after paying an exact input amount, fee included, into the pool.
+/// As in `TradeResult`, the fee is charged on top of what reaches the pool, so
+/// amount / (1 + fee) is swapped. Returns None in price mode, where there is
+/// no amount to solve from.
+fn final_price_for_amount(
+    initial: CpmmState,
+    mode: EntryMode,
+    amount: f64,
+    fee_fraction: f64,
+) -> Option<f64> {
+    let pool_in = amount / (1.0 + fee_fraction);
+    match mode {
+        EntryMode::Price => None,
+        EntryMode::BaseIn => Some(initial.swap_exact_base_in(pool_in, 0.0).1.price),
+        EntryMode::QuoteIn => Some(initial.swap_exact_quote_in(pool_in, 0.0).1.price),
+    }
+}
+
+/// Largest base input, fee included, whose trade has a price impact of at most
+/// `max_impact` (a fraction; selling base gives a negative impact, so this
+/// bounds its magnitude). With fee f on the input, b / (1 + f) of a base input
+/// b reaches the pool and the execution price falls to
+/// x / ((x + b / (1 + f))(1 + f)) of the pool price, which solves to
+/// b = x (m / (1 - m) - f). With the fee on the output the whole input reaches
+/// the pool, the price falls to (1 - f) · x / (x + b) and b = x (m - f) / (1 - m).
+/// Returns 0 when the fee alone exceeds the impact, and infinity from 100%,
+/// which no input reaches.
+fn max_base_in_for_impact(
+    state: CpmmState,
+    max_impact: f64,
+    fee_fraction: f64,
+    fee_mode: FeeMode,
+) -> f64 {
+    if max_impact >= 1.0 {
+        return f64::INFINITY;
+    }
+    let base_in = match fee_mode {
+        FeeMode::OnInput => {
+            state.base_reserves() * (max_impact / (1.0 - max_impact) - fee_fraction)
+        }
+        FeeMode::OnOutput => {
+            state.base_reserves() * (max_impact - fee_fraction) / (1.0 - max_impact)
+        }
+    };
+    base_in.max(0.0)
+}
+
+/// Largest quote input, fee included, whose trade has a price impact of at
+/// most `max_impact`. With fee f on the input, a quote input q raises the
+/// execution price to (1 + f)(y + q / (1 + f)) / y of the pool price, which
+/// solves to q = y (m - f). With the fee on the output it rises to
+/// (y + q) / ((1 - f) · y) and q = y ((1 + m)(1 - f) - 1). The base received
+/// stays below the base reserves however large the impact. Returns 0 when the
+/// fee alone exceeds the impact.
+fn max_quote_in_for_impact(
+    state: CpmmState,
+    max_impact: f64,
+    fee_fraction: f64,
+    fee_mode: FeeMode,
+) -> f64 {
+    let quote_in = match fee_mode {
+        FeeMode::OnInput => state.quote_reserves() * (max_impact - fee_fraction),
+        FeeMode::OnOutput => {
+            state.quote_reserves() * ((1.0 + max_impact) * (1.0 - fee_fraction) - 1.0)
+        }
+    };
+    quote_in.max(0.0)
+}
+
+/// Exact input amount, fee included, that moves the pool to `final_price`.
+/// Inverse of `final_price_for_amount`. Returns None in price mode or when the
+/// move goes the other way (a base input cannot raise the price).
+fn amount_for_final_price(
+    initial: CpmmState,
+    mode: EntryMode,
+    final_price: f64,
+    fee_fraction: f64,
+) -> Option<f64> {
+    let final_state = CpmmState {
+        price: final_price,
+        ..initial
+    };
+    let trade = TradeResult::compute(initial, final_state, fee_fraction);
+    let amount = match mode {
+        EntryMode::Price => return None,
+        EntryMode::BaseIn => -trade.base_wallet_delta_net,
+        EntryMode::QuoteIn => -trade.quote_wallet_delta_net,
+    };
+    (amount > 0.0).then_some(amount)
+}
+
+/// Running totals of a calculator session: fees accrued by executed trades,
+/// their cumulative log drift and the fees of the last simulation. They are
+/// results rather than inputs, so they are never saved, exported, imported or
+/// validated, and start at zero on every load.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SessionTotals {
+    cumulative_log_drift: f64,
+    accrued_base_lp_fees: f64,
+    accrued_quote_lp_fees: f64,
+    accrued_base_protocol_fees: f64,
+    accrued_quote_protocol_fees: f64,
+    sim_base_fees: f64,
+    sim_quote_fees: f64,
+}
+
+/// Shared application state.
+/// Deserializes from a camelCase config object; missing fields take defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct AppState {
+    initial_liquidity: f64,
+    final_liquidity: f64,
+    initial_price: f64,
+    final_price: f64,
+    fee_percent: f64,
+    fee_mode: FeeMode,
+    protocol_fee_percent: f64,
+    center_price: f64,
+    decades: f64,
+    fee_apr_percent: f64,
+    il_percent: f64,
+    horizon_days: f64,
+    min_slider_delta: f64,
+    collateral_base: f64,
+    debt_quote: f64,
+    external_price: f64,
+    oracle_weight: f64,
+    arb_close_percent: f64,
+    base_volume: f64,
+    volume_elasticity: f64,
+    cumulative_volume: f64,
+    rebate_tiers: Vec<(f64, f64)>,
+    price_lower: f64,
+    price_upper: f64,
+    inverted: bool,
+    display_precision: usize,
+    grouped: bool,
+    display_mode: DisplayMode,
+    model: AmmModel,
+    theme: Theme,
+    entry_mode: EntryMode,
+    trade_amount: f64,
+    slippage_tolerance_percent: f64,
+    max_impact_percent: f64,
+    route_liquidity: f64,
+    route_price: f64,
+    route_amount: f64,
+    daily_volume: f64,
+    alt_fee_percent: f64,
+    tick_mode: bool,
+    tick_spacing: u32,
+    amplification: f64,
+    weight_base: f64,
+    weight_quote: f64,
+    base_rebase_factor: f64,
+    quote_rebase_factor: f64,
+    implied_trade_size: f64,
+    implied_impact_percent: f64,
+    quoted_bid: f64,
+    quoted_ask: f64,
+    inventory_fraction: f64,
+    skew_factor: f64,
+    warn_threshold: f64,
+    sim_seed: u64,
+    sim_steps: usize,
+    #[serde(skip)]
+    session: SessionTotals,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            initial_liquidity: 1000.0,
+            final_liquidity: 1000.0,
+            initial_price: 1.0,
+            final_price: 1.1,
+            fee_percent: 0.3,
+            fee_mode: FeeMode::OnInput,
+            protocol_fee_percent: 100.0,
+            center_price: 1.0,
+            decades: DEFAULT_SLIDER_DECADES,
+            fee_apr_percent: 20.0,
+            il_percent: 5.0,
+            horizon_days: 30.0,
+            min_slider_delta: 0.0005,
+            collateral_base: 100.0,
+            debt_quote: 50.0,
+            external_price: 1.0,
+            oracle_weight: 0.5,
+            arb_close_percent: 10.0,
+            base_volume: 100_000.0,
+            volume_elasticity: 50.0,
+            cumulative_volume: 50_000.0,
+            rebate_tiers: vec![(10_000.0, 0.05), (100_000.0, 0.1)],
+            price_lower: 0.5,
+            price_upper: 2.0,
+            inverted: false,
+            display_precision: DEFAULT_DISPLAY_PRECISION,
+            grouped: false,
+            display_mode: DisplayMode::Full,
+            model: AmmModel::ConstantProduct,
+            theme: Theme::Auto,
+            entry_mode: EntryMode::Price,
+            trade_amount: 10.0,
+            slippage_tolerance_percent: 0.5,
+            max_impact_percent: 1.0,
+            route_liquidity: 1000.0,
+            route_price: 1.0,
+            route_amount: 10.0,
+            daily_volume: 10_000.0,
+            alt_fee_percent: 0.0,
+            tick_mode: false,
+            tick_spacing: 60,
+            amplification: 100.0,
+            weight_base: 0.5,
+            weight_quote: 0.5,
+            base_rebase_factor: 1.0,
+            quote_rebase_factor: 1.0,
+            implied_trade_size: 10.0,
+            implied_impact_percent: 1.0,
+            quoted_bid: 0.997,
+            quoted_ask: 1.003,
+            inventory_fraction: 0.0,
+            skew_factor: 0.5,
+            warn_threshold: 0.05,
+            sim_seed: 1,
+            sim_steps: 100,
+            session: SessionTotals::default(),
+        }
+    }
+}
+
+impl AppState {
+    /// Formats a value for display with the chosen precision and grouping.
+    fn display(&self, value: f64) -> String {
+        if self.grouped {
+            format_number_grouped(value, self.display_precision)
+        } else {
+            format_number(value, self.display_precision)
+        }
+    }
+
+    /// Formats a computed, read-only value, abbreviated in that display mode.
+    /// Editable inputs keep `display` so what they show parses back exactly.
+    fn display_output(&self, value: f64) -> String {
+        match self.display_mode {
+            DisplayMode::Full => self.display(value),
+            DisplayMode::Abbreviated => format_abbreviated(value, self.display_precision),
+        }
+    }
+
+    /// Price snapped to the nearest valid tick in tick mode; unchanged otherwise.
+    fn snap_to_tick(&self, price: f64) -> f64 {
+        if self.tick_mode {
+            tick_to_price(nearest_valid_tick(price, self.tick_spacing))
+        } else {
+            price
+        }
+    }
+
+    /// Moves the price sliders to `auto_slider_range` when the current range
+    /// cannot show both prices, e.g. for a token priced around 1e-9.
+    fn fit_slider_range(&mut self) {
+        let covered = [self.initial_price, self.final_price]
+            .iter()
+            .all(|&price| slider_covers(price, self.center_price, self.decades));
+        if !covered {
+            (self.center_price, self.decades) =
+                auto_slider_range(self.initial_price, self.final_price);
+        }
+    }
+
+    /// Snaps the initial and final prices to valid ticks (in tick mode).
+    fn snap_prices_to_ticks(&mut self) {
+        self.initial_price = self.snap_to_tick(self.initial_price);
+        self.final_price = self.snap_to_tick(self.final_price);
+    }
+
+    /// Pool the trade amount is swapped into: the initial constant-product pool.
+    fn amount_pool(&self) -> Result<CpmmState, CpmmError> {
+        CpmmState::new(self.initial_liquidity, self.initial_price)
+    }
+
+    /// Fee fraction charged on a trader's input; none when the fee is taken
+    /// from the output instead.
+    fn input_fee_fraction(&self) -> f64 {
+        match self.fee_mode {
+            FeeMode::OnInput => self.fee_percent / 100.0,
+            FeeMode::OnOutput => 0.0,
+        }
+    }
+
+    /// Largest base and quote inputs on the amount pool whose price impact
+    /// stays within `max_impact_percent`, in pool orientation.
+    fn max_trades_for_impact(&self) -> Result<(f64, f64), CpmmError> {
+        let pool = self.amount_pool()?;
+        let max_impact = self.max_impact_percent / 100.0;
+        let fee = self.fee_percent / 100.0;
+        Ok((
+            max_base_in_for_impact(pool, max_impact, fee, self.fee_mode),
+            max_quote_in_for_impact(pool, max_impact, fee, self.fee_mode),
+        ))
+    }
+
+    /// Final price the trade amount solves to, or None in price mode.
+    /// The amount is of the displayed token, so the mode follows the orientation.
+    fn final_price_for_trade_amount(&self) -> Option<f64> {
+        final_price_for_amount(
+            self.amount_pool().ok()?,
+            self.entry_mode.oriented(self.inverted),
+            self.trade_amount,
+            self.input_fee_fraction(),
+        )
+    }
+
+    /// Trade amount that reproduces the current final price, if the mode has one.
+    /// Used to back-fill the amount so switching modes never moves the price.
+    fn trade_amount_for_final_price(&self) -> Option<f64> {
+        amount_for_final_price(
+            self.amount_pool().ok()?,
+            self.entry_mode.oriented(self.inverted),
+            self.final_price,
+            self.input_fee_fraction(),
+        )
+    }
+
+    /// Formats a fraction as a percentage at the display precision.
+    fn display_percent(&self, fraction: f64) -> String {
+        format_percent(fraction, self.display_precision)
+    }
+
+    /// Serializes the state as JSON for storage.
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Restores a state saved with `to_json`.
+    /// Returns None if the JSON is corrupt or describes an invalid state.
+    fn from_json(json: &str) -> Option<Self> {
+        let state: Self = serde_json::from_str(json).ok()?;
+        state.validate().is_empty().then_some(state)
+    }
+
+    /// Converts a price between the pool orientation and the displayed one.
+    /// Inversion is its own inverse, so this works in both directions.
+    fn oriented_price(&self, price: f64) -> f64 {
+        if self.inverted {
+            invert_price(price)
+        } else {
+            price
+        }
+    }
+
+    /// Smallest slider move that triggers a recompute: `min_slider_delta`,
+    /// capped at half a slider step so one step registers at any range.
+    fn slider_jitter_threshold(&self) -> f64 {
+        self.min_slider_delta
+            .min(slider_step_for_decades(self.decades) / 2.0)
+    }
+
+    /// Slider position that displays a pool-oriented price.
+    /// The center price inverts along with the price.
+    fn slider_for_price(&self, price: f64) -> f64 {
+        price_to_slider(
+            self.oriented_price(price),
+            self.oriented_price(self.center_price),
+            self.decades,
+        )
+    }
+
+    /// Pool-oriented price at a slider position.
+    fn price_for_slider(&self, slider_value: f64) -> f64 {
+        let center = self.oriented_price(self.center_price);
+        self.oriented_price(slider_to_price(slider_value, center, self.decades))
+    }
+
+    /// Range bounds as displayed. Inverting swaps which bound is lower.
+    fn displayed_range(&self) -> (f64, f64) {
+        if self.inverted {
+            (
+                invert_price(self.price_upper),
+                invert_price(self.price_lower),
+            )
+        } else {
+            (self.price_lower, self.price_upper)
+        }
+    }
+
+    /// Sets the range from displayed bounds.
+    fn set_displayed_range(&mut self, lower: f64, upper: f64) {
+        if self.inverted {
+            self.price_lower = invert_price(upper);
+            self.price_upper = invert_price(lower);
+        } else {
+            self.price_lower = lower;
+            self.price_upper = upper;
+        }
+    }
+
+    /// Checks every field and returns a description of each problem found.
+    /// An empty list means the state is valid.
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let positive = [
+            ("initialLiquidity", self.initial_liquidity),
+            ("finalLiquidity", self.final_liquidity),
+            ("initialPrice", self.initial_price),
+            ("finalPrice", self.final_price),
+            ("centerPrice", self.center_price),
+            ("decades", self.decades),
+            ("externalPrice", self.external_price),
+            ("amplification", self.amplification),
+            ("baseRebaseFactor", self.base_rebase_factor),
+            ("quoteRebaseFactor", self.quote_rebase_factor),
+            ("impliedTradeSize", self.implied_trade_size),
+            ("quotedBid", self.quoted_bid),
+            ("quotedAsk", self.quoted_ask),
+            ("tradeAmount", self.trade_amount),
+            ("routeLiquidity", self.route_liquidity),
+            ("routePrice", self.route_price),
+            ("routeAmount", self.route_amount),
+        ];
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                errors.push(format!("{} must be a positive finite number", name));
+            }
+        }
+        if !(0.0..100.0).contains(&self.fee_percent) {
+            errors.push("feePercent must be in [0, 100)".to_string());
+        }
+        if !(0.0..100.0).contains(&self.alt_fee_percent) {
+            errors.push("altFeePercent must be in [0, 100)".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.oracle_weight) {
+            errors.push("oracleWeight must be in [0, 1]".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.protocol_fee_percent) {
+            errors.push("protocolFeePercent must be in [0, 100]".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.slippage_tolerance_percent) {
+            errors.push("slippageTolerancePercent must be in [0, 100]".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.arb_close_percent) {
+            errors.push("arbClosePercent must be in [0, 100]".to_string());
+        }
+        let weights_valid = [self.weight_base, self.weight_quote]
+            .iter()
+            .all(|w| *w > 0.0 && *w < 1.0)
+            && (self.weight_base + self.weight_quote - 1.0).abs() <= WEIGHT_TOLERANCE;
+        if !weights_valid {
+            errors.push("weightBase and weightQuote must lie in (0, 1) and sum to 1".to_string());
+        }
+        if !(-1.0..=1.0).contains(&self.inventory_fraction) {
+            errors.push("inventoryFraction must be in [-1, 1]".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.implied_impact_percent) {
+            errors.push("impliedImpactPercent must be in [0, 100]".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.max_impact_percent) {
+            errors.push("maxImpactPercent must be in [0, 100]".to_string());
+        }
+        let non_negative = [
+            ("feeAprPercent", self.fee_apr_percent),
+            ("ilPercent", self.il_percent),
+            ("horizonDays", self.horizon_days),
+            ("minSliderDelta", self.min_slider_delta),
+            ("collateralBase", self.collateral_base),
+            ("debtQuote", self.debt_quote),
+            ("baseVolume", self.base_volume),
+            ("volumeElasticity", self.volume_elasticity),
+            ("cumulativeVolume", self.cumulative_volume),
+            ("priceLower", self.price_lower),
+            ("skewFactor", self.skew_factor),
+            ("warnThreshold", self.warn_threshold),
+            ("dailyVolume", self.daily_volume),
+        ];
+        for (name, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                errors.push(format!("{} must be a non-negative finite number", name));
+            }
+        }
+        if self.price_lower >= self.price_upper || self.price_upper.is_nan() {
+            errors.push("priceLower must be less than priceUpper".to_string());
+        }
+        if !(1..=MAX_TICK_SPACING).contains(&self.tick_spacing) {
+            errors.push(format!("tickSpacing must be in [1, {}]", MAX_TICK_SPACING));
+        }
+        if self.sim_steps > MAX_SIMULATION_STEPS {
+            errors.push(format!("simSteps must be at most {}", MAX_SIMULATION_STEPS));
+        }
+        if self.display_precision > MAX_DISPLAY_PRECISION {
+            errors.push(format!(
+                "displayPrecision must be at most {}",
+                MAX_DISPLAY_PRECISION
+            ));
+        }
+        let tiers_valid = self.rebate_tiers.iter().all(|(threshold, rebate)| {
+            threshold.is_finite() && *threshold >= 0.0 && rebate.is_finite()
+        });
+        if !tiers_valid {
+            errors.push("rebateTiers must be finite with non-negative thresholds".to_string());
+        }
+        errors
+    }
+}
+
+/// Adds an executed trade's fees to the session totals, split as they were
+/// at execution.
+fn accrue_fees(state: &mut AppState, result: &TradeResult) {
+    state.session.accrued_base_lp_fees += result.base_lp_fee;
+    state.session.accrued_quote_lp_fees += result.quote_lp_fee;
+    state.session.accrued_base_protocol_fees += result.base_protocol_fee;
+    state.session.accrued_quote_protocol_fees += result.quote_protocol_fee;
+}
+
+/// Switches the protocol's share of fees for trades executed from now on.
+/// Fees already accrued keep the split they were earned under.
+fn toggle_protocol_fee(state: &mut AppState, new_protocol_fraction: f64) {
+    state.protocol_fee_percent = new_protocol_fraction * 100.0;
+}
+
+/// URL query parameter of one calculator's field: `{prefix}.{key}`, so
+/// several calculators can share the page URL without clobbering each other.
+fn query_key(prefix: &str, key: &str) -> String {
+    format!("{}.{}", prefix, key)
+}
+
+/// Shareable parameters of a state as unprefixed (key, value) query pairs.
+/// Keys: l (liquidity), ip/fp (initial/final price), fee (percent),
+/// c (slider center price), d (slider decades).
+fn query_pairs(state: &AppState) -> [(&'static str, f64); 6] {
+    [
+        ("l", state.initial_liquidity),
+        ("ip", state.initial_price),
+        ("fp", state.final_price),
+        ("fee", state.fee_percent),
+        ("c", state.center_price),
+        ("d", state.decades),
+    ]
+}
+
+/// Sets the field behind an unprefixed query key from its value.
+/// Unknown keys and invalid values are ignored.
+fn apply_query_value(state: &mut AppState, key: &str, value: &str) {
+    let positive = |v: f64| v.is_finite() && v > 0.0;
+    let Ok(v) = value.parse::<f64>() else {
+        return;
+    };
+    match key {
+        "l" if positive(v) => state.initial_liquidity = v,
+        "ip" if positive(v) => state.initial_price = v,
+        "fp" if positive(v) => state.final_price = v,
+        "fee" if (0.0..100.0).contains(&v) => state.fee_percent = v,
+        "c" if positive(v) => state.center_price = v,
+        "d" if positive(v) => state.decades = v,
+        _ => {}
+    }
+}
+
+/// Reads one calculator's parameters from a URL query string such as
+/// `?calc.l=1000&calc.ip=1&calc.fp=1.1&calc.fee=0.3`, starting from the
+/// defaults. Each missing or invalid parameter keeps its default; the rest
+/// still apply. Parameters of other calculators and of the page are ignored.
+#[allow(dead_code)]
+fn parse_state_from_query(query: &str, prefix: &str) -> AppState {
+    let mut state = AppState::default();
+    for pair in query.trim_start_matches('?').split('&') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        if let Some((owner, key)) = key.rsplit_once('.')
+            && owner == prefix
+        {
+            apply_query_value(&mut state, key, value);
+        }
+    }
+    state
+}
+
+/// Formats the shareable parameters of a state as a query string (without
+/// `?`). Inverse of `parse_state_from_query`.
+#[allow(dead_code)]
+fn state_to_query(state: &AppState, prefix: &str) -> String {
+    query_pairs(state)
+        .iter()
+        .map(|(key, value)| format!("{}={}", query_key(prefix, key), value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Maximum number of undo steps kept per calculator.
+const HISTORY_DEPTH: usize = 50;
+
+/// Undo/redo history of committed values.
+/// `present` is the latest committed value; `past` and `future` hold the
+/// snapshots before and after it, newest last.
+#[derive(Debug)]
+struct History<T> {
+    past: Vec<T>,
+    present: T,
+    future: Vec<T>,
+    depth: usize,
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    fn new(present: T, depth: usize) -> Self {
+        Self {
+            past: Vec::new(),
+            present,
+            future: Vec::new(),
+            depth,
+        }
+    }
+
+    /// Commits a new value. The previous one becomes undoable and the redo
+    /// steps are dropped; committing the current value again does nothing.
+    /// The oldest snapshot is dropped once `depth` is exceeded.
+    fn push(&mut self, value: T) {
+        if value == self.present {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.present, value);
+        self.past.push(previous);
+        if self.past.len() > self.depth {
+            self.past.remove(0);
+        }
+        self.future.clear();
+    }
+
+    /// Steps back to the previous value, or returns None if there is none.
+    fn undo(&mut self) -> Option<&T> {
+        let previous = self.past.pop()?;
+        let current = std::mem::replace(&mut self.present, previous);
+        self.future.push(current);
+        Some(&self.present)
+    }
+
+    /// Steps forward to the value last undone, or returns None if there is none.
+    fn redo(&mut self) -> Option<&T> {
+        let next = self.future.pop()?;
+        let current = std::mem::replace(&mut self.present, next);
+        self.past.push(current);
+        Some(&self.present)
+    }
+
+    fn can_undo(&self) -> bool {
+        !self.past.is_empty()
+    }
+
+    fn can_redo(&self) -> bool {
+        !self.future.is_empty()
+    }
+}
+
+/// Viewport width in CSS pixels below which rows stack their fields.
+const NARROW_BREAKPOINT: f64 = 600.0;
+
+/// Whether a viewport this wide gets the single-column layout.
+fn is_narrow(width: f64) -> bool {
+    width < NARROW_BREAKPOINT
+}
+
+/// Number of prices sampled for the composition chart.
+const COMPOSITION_SWEEP_STEPS: usize = 64;
+
+/// Size of the composition chart in SVG user units.
+const CHART_WIDTH: f64 = 400.0;
+const CHART_HEIGHT: f64 = 150.0;
+
+/// SVG polygon points for a composition sweep drawn as a stacked area:
+/// base value on the bottom, quote value stacked on top. Samples are spaced
+/// evenly across the width and the tallest total fills the height.
+/// Returns (base points, quote points).
+fn stacked_area_points(sweep: &[(f64, f64, f64)], width: f64, height: f64) -> (String, String) {
+    let max_total = sweep
+        .iter()
+        .map(|(_, base, quote)| base + quote)
+        .fold(0.0, f64::max);
+    if sweep.is_empty() || max_total <= 0.0 {
+        return (String::new(), String::new());
+    }
+    let x = |i: usize| {
+        if sweep.len() > 1 {
+            width * i as f64 / (sweep.len() - 1) as f64
+        } else {
+            width / 2.0
+        }
+    };
+    let y = |value: f64| height - value / max_total * height;
+    let point = |i: usize, value: f64| format!("{:.2},{:.2}", x(i), y(value));
+
+    let base_top = sweep
+        .iter()
+        .enumerate()
+        .map(|(i, (_, base, _))| point(i, *base));
+    let baseline = [point(sweep.len() - 1, 0.0), point(0, 0.0)];
+    let base_points: Vec<String> = base_top.chain(baseline).collect();
+
+    let total_top = sweep
+        .iter()
+        .enumerate()
+        .map(|(i, (_, base, quote))| point(i, base + quote));
+    let base_edge = sweep
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, (_, base, _))| point(i, *base));
+    let quote_points: Vec<String> = total_top.chain(base_edge).collect();
+
+    (base_points.join(" "), quote_points.join(" "))
+}
+
+/// Samples of the curve x * y = k at n evenly spaced x in [x_min, x_max].
+fn curve_points(k: f64, x_min: f64, x_max: f64, n: usize) -> Vec<(f64, f64)> {
+    (0..n)
+        .map(|i| {
+            let t = if n > 1 {
+                i as f64 / (n - 1) as f64
+            } else {
+                0.0
+            };
+            let x = x_min + (x_max - x_min) * t;
+            (x, k / x)
+        })
+        .collect()
+}
+
+/// Number of samples along the invariant curve.
+const CURVE_STEPS: usize = 64;
+
+/// Formatted output field values, as (element ID, text) in display order.
+#[derive(Debug, PartialEq)]
+struct DisplayValues {
+    values: Vec<(&'static str, String)>,
+}
+
+impl DisplayValues {
+    /// Text for an output field, if it is computed.
+    #[allow(dead_code)]
+    fn get(&self, id: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(field, _)| *field == id)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Price range of the position. Without a range it spans (0, infinity),
+/// i.e. a plain CPMM.
+fn position_range(state: &AppState) -> (f64, f64) {
+    if state.model == AmmModel::Concentrated {
+        (state.price_lower, state.price_upper)
+    } else {
+        (0.0, f64::INFINITY)
+    }
+}
+
+/// Pools of the selected model before and after, and the change between them.
+struct ModelOutcome {
+    initial_reserves: (f64, f64),
+    final_reserves: (f64, f64),
+    invariants: (f64, f64),
+    change: LiquidityChangeResult,
+    /// Trade part of the change; exactly zero when the prices match.
+    trade: TradeResult,
+}
+
+/// Evaluates the selected AMM model for the state's initial and final pools.
+fn model_outcome(state: &AppState) -> Result<ModelOutcome, CpmmError> {
+    let (p_lower, p_upper) = position_range(state);
+    let fee_fraction = state.fee_percent / 100.0;
+    let protocol_fee_fraction = state.protocol_fee_percent / 100.0;
+    let (initial_reserves, final_reserves, invariants, change) = match state.model {
+        // The range only bounds the position; k = L^2 is that of the full curve
+        AmmModel::ConstantProduct | AmmModel::Concentrated => {
+            let initial = ConcentratedCpmmState::new(
+                state.initial_liquidity,
+                state.initial_price,
+                p_lower,
+                p_upper,
+            )?;
+            let final_state = ConcentratedCpmmState::new(
+                state.final_liquidity,
+                state.final_price,
+                p_lower,
+                p_upper,
+            )?;
+            (
+                (initial.base_reserves(), initial.quote_reserves()),
+                (final_state.base_reserves(), final_state.quote_reserves()),
+                (
+                    CpmmState::new(state.initial_liquidity, state.initial_price)?.invariant(),
+                    CpmmState::new(state.final_liquidity, state.final_price)?.invariant(),
+                ),
+                LiquidityChangeResult::compute(
+                    initial,
+                    final_state,
+                    fee_fraction,
+                    protocol_fee_fraction,
+                    state.fee_mode,
+                ),
+            )
+        }
+        // Liquidity L maps to D = 2L, matching the CPMM reserves at price 1
+        AmmModel::Stableswap => {
+            let a = state.amplification;
+            let initial_d = 2.0 * state.initial_liquidity;
+            let initial = StableswapState::from_price(a, initial_d, state.initial_price)?;
+            let moved = StableswapState::from_price(a, initial_d, state.final_price)?;
+            let final_state =
+                StableswapState::from_price(a, 2.0 * state.final_liquidity, state.final_price)?;
+            (
+                (initial.base_reserves(), initial.quote_reserves()),
+                (final_state.base_reserves(), final_state.quote_reserves()),
+                (initial.invariant(), final_state.invariant()),
+                LiquidityChangeResult::between(
+                    initial,
+                    moved,
+                    final_state,
+                    fee_fraction,
+                    protocol_fee_fraction,
+                    state.fee_mode,
+                ),
+            )
+        }
+        // V plays the role of L, so 50/50 weights match the constant product
+        AmmModel::Weighted => {
+            let (w_base, w_quote) = (state.weight_base, state.weight_quote);
+            let pool = |liquidity, price| WeightedPoolState::new(liquidity, price, w_base, w_quote);
+            let initial = pool(state.initial_liquidity, state.initial_price)?;
+            let moved = pool(state.initial_liquidity, state.final_price)?;
+            let final_state = pool(state.final_liquidity, state.final_price)?;
+            (
+                (initial.base_reserves(), initial.quote_reserves()),
+                (final_state.base_reserves(), final_state.quote_reserves()),
+                (initial.invariant(), final_state.invariant()),
+                LiquidityChangeResult::between(
+                    initial,
+                    moved,
+                    final_state,
+                    fee_fraction,
+                    protocol_fee_fraction,
+                    state.fee_mode,
+                ),
+            )
+        }
+    };
+    // Equal prices trade nothing, so report clean zeros rather than rounding noise
+    let trade = if is_no_trade(state.initial_price, state.final_price) {
+        TradeResult::none()
+    } else {
+        change.trade
+    };
+    Ok(ModelOutcome {
+        initial_reserves,
+        final_reserves,
+        invariants,
+        change,
+        trade,
+    })
+}
+
+/// Plain-text summary of a trade for pasting elsewhere: prices in the
+/// displayed orientation, then reserves, wallet deltas and fees.
+/// Reserves are left out when the pools are invalid.
+fn format_results_text(state: &AppState, result: &TradeResult) -> String {
+    let (base, quote) = if state.inverted {
+        ("Quote", "Base")
+    } else {
+        ("Base", "Quote")
+    };
+    let mut lines = vec![
+        (
+            "Initial Price".to_string(),
+            state.oriented_price(state.initial_price),
+        ),
+        (
+            "Final Price".to_string(),
+            state.oriented_price(state.final_price),
+        ),
+    ];
+    if let Ok(outcome) = model_outcome(state) {
+        lines.extend([
+            (
+                format!("Initial {} Reserves", base),
+                outcome.initial_reserves.0,
+            ),
+            (
+                format!("Initial {} Reserves", quote),
+                outcome.initial_reserves.1,
+            ),
+            (format!("Final {} Reserves", base), outcome.final_reserves.0),
+            (
+                format!("Final {} Reserves", quote),
+                outcome.final_reserves.1,
+            ),
+        ]);
+    }
+    lines.extend([
+        (format!("{} Reserves Delta", base), result.base_wallet_delta),
+        (
+            format!("{} Reserves Delta", quote),
+            result.quote_wallet_delta,
+        ),
+        (format!("{} Net Delta", base), result.base_wallet_delta_net),
+        (
+            format!("{} Net Delta", quote),
+            result.quote_wallet_delta_net,
+        ),
+        (format!("{} Fee Collected", base), result.base_fee_collected),
+        (
+            format!("{} Fee Collected", quote),
+            result.quote_fee_collected,
+        ),
+        (format!("{} LP Fee", base), result.base_lp_fee),
+        (format!("{} LP Fee", quote), result.quote_lp_fee),
+        (format!("{} Protocol Fee", base), result.base_protocol_fee),
+        (format!("{} Protocol Fee", quote), result.quote_protocol_fee),
+    ]);
+    lines
+        .iter()
+        .map(|(label, value)| format!("{}: {}\n", label, state.display(*value)))
+        .collect()
+}
+
+/// Execution price and price impact of a trade in the displayed orientation.
+/// Both are 0 when nothing is exchanged.
+fn displayed_execution(state: &AppState, result: &TradeResult) -> (f64, f64) {
+    if result.execution_price == 0.0 {
+        return (0.0, 0.0);
+    }
+    let displayed_initial = state.oriented_price(state.initial_price);
+    let execution_price = state.oriented_price(result.execution_price);
+    (
+        execution_price,
+        (execution_price - displayed_initial) / displayed_initial,
+    )
+}
+
+/// Shown in place of a value that is undefined.
+const UNDEFINED_DISPLAY: &str = "—";
+
+/// Execution price of a trade as displayed, or `UNDEFINED_DISPLAY` when nothing
+/// is exchanged: at the initial price the price has no trade to average over.
+fn execution_price_text(state: &AppState, result: &TradeResult) -> String {
+    let (execution_price, _) = displayed_execution(state, result);
+    if is_no_trade(state.initial_price, state.final_price) || execution_price == 0.0 {
+        UNDEFINED_DISPLAY.to_string()
+    } else {
+        state.display(execution_price)
+    }
+}
+
+/// Whether a price impact is large enough to warn about: its magnitude
+/// strictly exceeds the threshold.
+fn impact_is_high(impact: f64, threshold: f64) -> bool {
+    impact.abs() > threshold
+}
+
+/// Computes the text of every output field from the state.
+fn compute_display_values(state: &AppState) -> Result<DisplayValues, CpmmError> {
+    let (p_lower, p_upper) = position_range(state);
+    let ModelOutcome {
+        initial_reserves,
+        final_reserves,
+        invariants,
+        change,
+        trade: result,
+    } = model_outcome(state)?;
+    let fee_fraction = state.fee_percent / 100.0;
+
+    // Initial and final reserves
+    let mut values = vec![
+        (
+            "initial-base-reserves",
+            state.display_output(initial_reserves.0),
+        ),
+        (
+            "initial-quote-reserves",
+            state.display_output(initial_reserves.1),
+        ),
+        (
+            "final-base-reserves",
+            state.display_output(final_reserves.0),
+        ),
+        (
+            "final-quote-reserves",
+            state.display_output(final_reserves.1),
+        ),
+        ("initial-invariant", state.display_output(invariants.0)),
+        ("final-invariant", state.display_output(invariants.1)),
+    ];
+
+    let no_trade = is_no_trade(state.initial_price, state.final_price);
+
+    // Prices are shown in the displayed orientation; amounts keep their fields
+    // and only their labels swap
+    let displayed_initial = state.oriented_price(state.initial_price);
+    let displayed_final = if no_trade {
+        displayed_initial
+    } else {
+        state.oriented_price(state.final_price)
+    };
+    let (_, price_impact) = displayed_execution(state, &result);
+
+    values.push((
+        "delta-price",
+        state.display_output(displayed_final - displayed_initial),
+    ));
+    values.push(("initial-tick", price_to_tick(displayed_initial).to_string()));
+    values.push(("final-tick", price_to_tick(displayed_final).to_string()));
+    values.push((
+        "delta-base-reserves",
+        state.display_output(result.base_wallet_delta),
+    ));
+    values.push((
+        "delta-quote-reserves",
+        state.display_output(result.quote_wallet_delta),
+    ));
+    values.push((
+        "delta-base-net",
+        state.display_output(result.base_wallet_delta_net),
+    ));
+    values.push((
+        "delta-quote-net",
+        state.display_output(result.quote_wallet_delta_net),
+    ));
+    values.push((
+        "fee-base-collected",
+        state.display_output(result.base_fee_collected),
+    ));
+    values.push((
+        "fee-quote-collected",
+        state.display_output(result.quote_fee_collected),
+    ));
+    values.push(("fee-base-lp", state.display_output(result.base_lp_fee)));
+    values.push(("fee-quote-lp", state.display_output(result.quote_lp_fee)));
+    values.push((
+        "fee-base-protocol",
+        state.display_output(result.base_protocol_fee),
+    ));
+    values.push((
+        "fee-quote-protocol",
+        state.display_output(result.quote_protocol_fee),
+    ));
+
+    // Gross, fee and net on the side the trader pays and the side they receive
+    let parts = breakdown(&result);
+    let sides = [
+        ("breakdown-paid-gross", parts.paid.gross),
+        ("breakdown-received-gross", parts.received.gross),
+        ("breakdown-paid-fee", parts.paid.fee),
+        ("breakdown-received-fee", parts.received.fee),
+        ("breakdown-paid-net", parts.paid.net),
+        ("breakdown-received-net", parts.received.net),
+    ];
+    for (id, value) in sides {
+        values.push((id, state.display_output(value)));
+    }
+
+    // The move at the initial liquidity, at the current and the alternate fee
+    let (at_fee, at_alt_fee) = compare_fees(
+        CpmmState::new(state.initial_liquidity, state.initial_price)?,
+        CpmmState::new(state.initial_liquidity, state.final_price)?,
+        fee_fraction,
+        state.alt_fee_percent / 100.0,
+    );
+    let comparison = [
+        ("compare-base-net", at_fee.base_wallet_delta_net),
+        ("compare-base-net-alt", at_alt_fee.base_wallet_delta_net),
+        ("compare-quote-net", at_fee.quote_wallet_delta_net),
+        ("compare-quote-net-alt", at_alt_fee.quote_wallet_delta_net),
+        ("compare-base-fee", at_fee.base_fee_collected),
+        ("compare-base-fee-alt", at_alt_fee.base_fee_collected),
+        ("compare-quote-fee", at_fee.quote_fee_collected),
+        ("compare-quote-fee-alt", at_alt_fee.quote_fee_collected),
+    ];
+    for (id, value) in comparison {
+        values.push((id, state.display_output(value)));
+    }
+
+    // Session totals of executed trades
+    values.push((
+        "accrued-base-lp",
+        state.display_output(state.session.accrued_base_lp_fees),
+    ));
+    values.push((
+        "accrued-quote-lp",
+        state.display_output(state.session.accrued_quote_lp_fees),
+    ));
+    values.push((
+        "accrued-base-protocol",
+        state.display_output(state.session.accrued_base_protocol_fees),
+    ));
+    values.push((
+        "accrued-quote-protocol",
+        state.display_output(state.session.accrued_quote_protocol_fees),
+    ));
+
+    values.push((
+        "delta-base-liquidity",
+        state.display_output(change.base_liquidity_delta),
+    ));
+    values.push((
+        "delta-quote-liquidity",
+        state.display_output(change.quote_liquidity_delta),
+    ));
+
+    values.push((
+        "delta-execution-price",
+        execution_price_text(state, &result),
+    ));
+    values.push(("delta-price-impact", state.display_percent(price_impact)));
+
+    // Worst case under the slippage tolerance: the positive net delta is
+    // received, the negative one sent
+    let tolerance = state.slippage_tolerance_percent / 100.0;
+    let received = result
+        .base_wallet_delta_net
+        .max(result.quote_wallet_delta_net)
+        .max(0.0);
+    let sent = (-result
+        .base_wallet_delta_net
+        .min(result.quote_wallet_delta_net))
+    .max(0.0);
+    values.push((
+        "delta-min-received",
+        state.display_output(apply_slippage_tolerance(received, tolerance, true)),
+    ));
+    values.push((
+        "delta-max-sent",
+        state.display_output(apply_slippage_tolerance(sent, tolerance, false)),
+    ));
+
+    // Price change as a percentage and as a log return
+    values.push((
+        "delta-price-change",
+        state.display_output((displayed_final / displayed_initial - 1.0) * 100.0),
+    ));
+    values.push((
+        "delta-log-return",
+        state.display_output(log_return(displayed_initial, displayed_final)),
+    ));
+
+    // Log drift of this trade and of the session so far
+    values.push((
+        "delta-log-drift",
+        state.display_output(log_drift(displayed_initial, displayed_final)),
+    ));
+    // Inverting negates a log drift; skip zero so it never shows as -0
+    let cumulative_drift = if state.inverted && state.session.cumulative_log_drift != 0.0 {
+        -state.session.cumulative_log_drift
+    } else {
+        state.session.cumulative_log_drift
+    };
+    values.push((
+        "cumulative-log-drift",
+        state.display_output(cumulative_drift),
+    ));
+
+    // Fee capture of the position range over this move
+    values.push((
+        "range-fee-capture",
+        state.display_output(
+            fee_capture_fraction(p_lower, p_upper, state.initial_price, state.final_price) * 100.0,
+        ),
+    ));
+
+    // Full-range liquidity worth as much as the initial position
+    let position = ConcentratedCpmmState::new(
+        state.initial_liquidity,
+        state.initial_price,
+        p_lower,
+        p_upper,
+    )?;
+    values.push((
+        "range-equivalent-liquidity",
+        state.display_output(equivalent_full_range_liquidity(&position)),
+    ));
+
+    // Impermanent loss of the initial position at the final price
+    let il = impermanent_loss(
+        CpmmState::new(state.initial_liquidity, state.initial_price)?,
+        CpmmState::new(state.initial_liquidity, state.final_price)?,
+    );
+    values.push(("lp-impermanent-loss", state.display_output(il * 100.0)));
+
+    // Fee income of the initial pool from the daily quote volume
+    let pool = CpmmState::new(state.initial_liquidity, state.initial_price)?;
+    let daily_revenue = fee_revenue(state.daily_volume, fee_fraction, pool.liquidity);
+    let pool_value = pool.base_reserves() * pool.price + pool.quote_reserves();
+    values.push(("lp-daily-revenue", state.display_output(daily_revenue)));
+    values.push((
+        "lp-fee-apr",
+        state.display_percent(simple_apr(daily_revenue, pool_value)),
+    ));
+
+    // Routing the base input through the initial pool and a second pool,
+    // against a single pool of the initial liquidity at the combined price
+    let first_hop = CpmmState::new(state.initial_liquidity, state.initial_price)?;
+    let second_hop = CpmmState::new(state.route_liquidity, state.route_price)?;
+    let direct = CpmmState::new(
+        state.initial_liquidity,
+        state.initial_price * state.route_price,
+    )?;
+    let (route_out, _, _) = route_two_hop(first_hop, second_hop, state.route_amount, fee_fraction);
+    let (direct_out, _) = direct.swap_exact_base_in(state.route_amount, fee_fraction);
+    values.push(("route-output", state.display_output(route_out)));
+    values.push((
+        "route-rate",
+        state.display_output(route_out / state.route_amount),
+    ));
+    values.push((
+        "route-direct-rate",
+        state.display_output(direct_out / state.route_amount),
+    ));
+
+    // LP break-even
+    let days = breakeven_days(state.fee_apr_percent / 100.0, state.il_percent / 100.0);
+    values.push(("breakeven-days", state.display_output(days)));
+
+    // Annualized effect on a holder of the displayed base token
+    values.push((
+        "holder-annualized-effect",
+        state.display_output(
+            annualized_price_effect(displayed_initial, displayed_final, state.horizon_days) * 100.0,
+        ),
+    ));
+
+    // Collateral ratio
+    values.push((
+        "collateral-ratio-before",
+        state.display_output(collateral_ratio_after(
+            state.initial_price,
+            state.collateral_base,
+            state.debt_quote,
+        )),
+    ));
+    values.push((
+        "collateral-ratio-after",
+        state.display_output(collateral_ratio_after(
+            state.final_price,
+            state.collateral_base,
+            state.debt_quote,
+        )),
+    ));
+
+    // Oracle blend
+    values.push((
+        "blended-price",
+        state.display_output(state.oriented_price(blended_price(
+            state.final_price,
+            state.external_price,
+            state.oracle_weight,
+        ))),
+    ));
+
+    // Arbitrage half-life of the gap between the final and external prices
+    values.push((
+        "halflife-steps",
+        state.display_output(price_halflife_steps(
+            state.final_price,
+            state.external_price,
+            state.arb_close_percent / 100.0,
+        )),
+    ));
+
+    // Revenue-maximizing fee
+    let optimal_fee = revenue_maximizing_fee(state.base_volume, state.volume_elasticity);
+    values.push((
+        "revenue-optimal-fee",
+        state.display_output(optimal_fee * 100.0),
+    ));
+    values.push((
+        "revenue-max",
+        state.display_output(elastic_fee_revenue(
+            state.base_volume,
+            state.volume_elasticity,
+            optimal_fee,
+        )),
+    ));
+
+    // Volume rebate
+    values.push((
+        "rebate-effective-fee",
+        state.display_output(effective_fee_after_rebate(
+            state.fee_percent,
+            state.cumulative_volume,
+            &state.rebate_tiers,
+        )),
+    ));
+
+    // Supply rebase of the initial pool
+    let rebased = apply_rebase(
+        CpmmState::new(state.initial_liquidity, state.initial_price)?,
+        state.base_rebase_factor,
+        state.quote_rebase_factor,
+    )?;
+    values.push((
+        "rebase-price",
+        state.display_output(state.oriented_price(rebased.price)),
+    ));
+    values.push(("rebase-liquidity", state.display_output(rebased.liquidity)));
+
+    // Liquidity implied by a quoted impact at the initial price
+    values.push((
+        "implied-liquidity",
+        state.display_output(implied_liquidity(
+            state.implied_trade_size,
+            state.implied_impact_percent / 100.0,
+            state.initial_price,
+            fee_fraction,
+        )),
+    ));
+
+    // Mid and fee implied by venue quotes, in the orientation they were typed
+    let (quoted_mid, quoted_fee) = mid_and_fee_from_quotes(state.quoted_bid, state.quoted_ask);
+    values.push(("quotes-mid", state.display_output(quoted_mid)));
+    values.push((
+        "quotes-fee-percent",
+        state.display_output(quoted_fee * 100.0),
+    ));
+
+    // Inventory-skewed market-maker quotes around the initial price
+    let (skew_bid, skew_ask) = skewed_quotes(
+        state.oriented_price(state.initial_price),
+        fee_fraction,
+        state.inventory_fraction,
+        state.skew_factor,
+    );
+    values.push(("skew-bid", state.display_output(skew_bid)));
+    values.push(("skew-ask", state.display_output(skew_ask)));
+
+    // Largest trades within the impact limit
+    let (max_base_in, max_quote_in) = state.max_trades_for_impact()?;
+    values.push(("max-impact-base-in", state.display_output(max_base_in)));
+    values.push(("max-impact-quote-in", state.display_output(max_quote_in)));
+
+    // Totals of the last fee simulation
+    values.push((
+        "sim-base-fees",
+        state.display_output(state.session.sim_base_fees),
+    ));
+    values.push((
+        "sim-quote-fees",
+        state.display_output(state.session.sim_quote_fees),
+    ));
+
+    Ok(DisplayValues { values })
+}
+
+/// Cargo features compiled into this build.
+#[cfg(feature = "ui")]
+const ENABLED_FEATURES: &[&str] = &["ui"];
+#[cfg(not(feature = "ui"))]
+const ENABLED_FEATURES: &[&str] = &[];
+
+/// Build diagnostics reported to JS.
+#[derive(Serialize)]
+struct BuildInfo {
+    version: &'static str,
+    features: &'static [&'static str],
+}
+
+impl BuildInfo {
+    fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features: ENABLED_FEATURES,
+        }
+    }
+}
+
+/// Version of the exported scenario layout; bumped on incompatible changes.
+const SCENARIO_VERSION: u32 = 1;
+
+/// A scenario as exported for archiving and sharing: the calculator state
+/// and the trade it describes. The trade is null when the state is invalid.
+#[derive(Serialize)]
+struct Scenario<'a> {
+    version: u32,
+    state: &'a AppState,
+    trade: Option<TradeResult>,
+}
+
+impl<'a> Scenario<'a> {
+    fn new(state: &'a AppState) -> Self {
+        Self {
+            version: SCENARIO_VERSION,
+            state,
+            trade: model_outcome(state).ok().map(|outcome| outcome.trade),
+        }
+    }
+}
+
+/// Serializes a state and its computed trade as pretty-printed JSON.
+fn scenario_json(state: &AppState) -> String {
+    serde_json::to_string_pretty(&Scenario::new(state)).unwrap_or_default()
+}
+
+/// Reads a state from an exported scenario or a bare state object.
+/// Missing fields take their defaults; returns None if the JSON is malformed
+/// or describes an invalid state.
+fn parse_scenario(json: &str) -> Option<AppState> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    let state_value = match value.get("state") {
+        Some(state) => state.clone(),
+        None => value,
+    };
+    let state: AppState = serde_json::from_value(state_value).ok()?;
+    state.validate().is_empty().then_some(state)
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
-    const EPSILON: f64 = 1e-10;
+    const EPSILON: f64 = 1e-10;
+
+    fn approx_eq(a: f64, b: f64) -> bool {
+        (a - b).abs() < EPSILON
+    }
+
+    #[test]
+    fn test_cpmm_state_reserves() {
+        // L = 100, P = 4
+        // x = L / sqrt(P) = 100 / 2 = 50
+        // y = L * sqrt(P) = 100 * 2 = 200
+        let state = CpmmState::new(100.0, 4.0).unwrap();
+        assert!(approx_eq(state.base_reserves(), 50.0));
+        assert!(approx_eq(state.quote_reserves(), 200.0));
+    }
+
+    #[test]
+    fn test_cpmm_invariant() {
+        // k = L^2 = x * y
+        let state = CpmmState::new(100.0, 4.0).unwrap();
+        let k = state.invariant();
+        let xy = state.base_reserves() * state.quote_reserves();
+        assert!(approx_eq(k, xy));
+        assert!(approx_eq(k, 10000.0));
+    }
+
+    #[test]
+    fn test_price_from_reserves() {
+        // P = y / x
+        let state = CpmmState::new(100.0, 4.0).unwrap();
+        let computed_price = state.quote_reserves() / state.base_reserves();
+        assert!(approx_eq(computed_price, state.price));
+    }
+
+    #[test]
+    fn test_trade_result_buy_base() {
+        // Initial: L=1000, P=1.0 => x=1000, y=1000
+        // Final: L=1000, P=1.21 => x=909.09, y=1100
+        // Trader buys base: receives ~90.91 base, pays ~100 quote
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let final_state = CpmmState::new(1000.0, 1.21).unwrap();
+        let result = TradeResult::compute(initial, final_state, 0.003);
+
+        assert!(result.base_wallet_delta > 0.0); // Trader receives base
+        assert!(result.quote_wallet_delta < 0.0); // Trader pays quote
+        assert!(result.quote_fee_collected > 0.0); // Fee on quote input
+        assert!(approx_eq(result.base_fee_collected, 0.0)); // No fee on base
+
+        // Net = gross - fee on both sides
+        assert!(approx_eq(
+            result.quote_wallet_delta_net,
+            result.quote_wallet_delta - result.quote_fee_collected
+        ));
+        assert!(approx_eq(
+            result.base_wallet_delta_net,
+            result.base_wallet_delta - result.base_fee_collected
+        ));
+        assert!(result.quote_wallet_delta_net < result.quote_wallet_delta); // Pays more
+    }
+
+    #[test]
+    fn test_trade_result_sell_base() {
+        // Price decreases: trader sells base for quote
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let final_state = CpmmState::new(1000.0, 0.81).unwrap();
+        let result = TradeResult::compute(initial, final_state, 0.003);
+
+        assert!(result.base_wallet_delta < 0.0); // Trader pays base
+        assert!(result.quote_wallet_delta > 0.0); // Trader receives quote
+        assert!(result.base_fee_collected > 0.0); // Fee on base input
+        assert!(approx_eq(result.quote_fee_collected, 0.0)); // No fee on quote
+
+        // Net = gross - fee on both sides
+        assert!(approx_eq(
+            result.base_wallet_delta_net,
+            result.base_wallet_delta - result.base_fee_collected
+        ));
+        assert!(approx_eq(
+            result.quote_wallet_delta_net,
+            result.quote_wallet_delta - result.quote_fee_collected
+        ));
+        assert!(result.base_wallet_delta_net < result.base_wallet_delta); // Pays more
+    }
+
+    #[test]
+    fn test_slider_price_conversion_roundtrip() {
+        let center = 1.0;
+        let decades = 3.0;
+        let prices = [0.001, 0.1, 1.0, 10.0, 100.0, 1000.0];
+
+        for &price in &prices {
+            let slider = price_to_slider(price, center, decades);
+            let recovered = slider_to_price(slider, center, decades);
+            assert!(
+                (price - recovered).abs() / price < 0.001,
+                "Roundtrip failed for price {}",
+                price
+            );
+        }
+    }
+
+    #[test]
+    fn test_slider_center() {
+        let center = 10.0;
+        let decades = 2.0;
+
+        // Slider at 0.5 should give center price
+        let price = slider_to_price(0.5, center, decades);
+        assert!(approx_eq(price, center));
+    }
+
+    #[test]
+    fn test_fees_in_range_fully_inside() {
+        // Move 1.0 -> 1.2 lies entirely within [0.5, 2.0]
+        let fees = fees_in_range(1.0, 1.2, 0.5, 2.0, 10.0);
+        assert!(approx_eq(fees, 10.0));
+    }
+
+    #[test]
+    fn test_fees_in_range_partial_overlap() {
+        // Move 1.0 -> 2.0 overlaps [1.5, 3.0] for half the interval
+        assert!(approx_eq(fees_in_range(1.0, 2.0, 1.5, 3.0, 10.0), 5.0));
+        // Direction of the move does not matter
+        assert!(approx_eq(fees_in_range(2.0, 1.0, 1.5, 3.0, 10.0), 5.0));
+    }
+
+    #[test]
+    fn test_fees_in_range_disjoint() {
+        assert!(approx_eq(fees_in_range(1.0, 1.2, 2.0, 3.0, 10.0), 0.0));
+        assert!(approx_eq(fees_in_range(1.0, 1.0, 2.0, 3.0, 10.0), 0.0));
+    }
+
+    #[test]
+    fn test_breakeven_days() {
+        // 5% IL at 36.5% APR accrues 0.1% per day => 50 days
+        assert!(approx_eq(breakeven_days(0.365, 0.05), 50.0));
+        // Higher APR shortens the holding period
+        assert!(breakeven_days(0.5, 0.05) < breakeven_days(0.2, 0.05));
+        // No fee income never breaks even
+        assert!(breakeven_days(0.0, 0.05).is_infinite());
+    }
+
+    #[test]
+    fn test_trade_to_marginal_price_reaches_target() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        for &target in &[0.25, 0.81, 1.0, 1.21, 4.0] {
+            let result = trade_to_marginal_price(initial, target, 0.003).unwrap();
+            assert!(approx_eq(initial.price + result.price_delta, target));
+        }
+    }
+
+    #[test]
+    fn test_trade_to_marginal_price_matches_compute() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let final_state = CpmmState::new(1000.0, 1.21).unwrap();
+        let direct = TradeResult::compute(initial, final_state, 0.003);
+        let targeted = trade_to_marginal_price(initial, 1.21, 0.003).unwrap();
+        assert!(approx_eq(
+            direct.base_wallet_delta,
+            targeted.base_wallet_delta
+        ));
+        assert!(approx_eq(
+            direct.quote_wallet_delta,
+            targeted.quote_wallet_delta
+        ));
+        assert!(approx_eq(
+            direct.quote_fee_collected,
+            targeted.quote_fee_collected
+        ));
+    }
+
+    #[test]
+    fn test_slider_delta_below_min_is_ignored() {
+        assert!(!slider_delta_exceeds_min(0.5, 0.5002, 0.0005));
+        assert!(!slider_delta_exceeds_min(0.5, 0.4998, 0.0005));
+    }
+
+    #[test]
+    fn test_slider_delta_above_min_is_accepted() {
+        assert!(slider_delta_exceeds_min(0.5, 0.501, 0.0005));
+        assert!(slider_delta_exceeds_min(0.5, 0.499, 0.0005));
+        // A zero threshold accepts every change
+        assert!(slider_delta_exceeds_min(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn test_sqrt_price_x96_known_values() {
+        // 2^96 encodes sqrt(P) = 1
+        let state =
+            CpmmState::from_sqrt_price_x96(79_228_162_514_264_337_593_543_950_336, 100.0).unwrap();
+        assert!(approx_eq(state.price, 1.0));
+        // 2 * 2^96 encodes sqrt(P) = 2
+        let state =
+            CpmmState::from_sqrt_price_x96(158_456_325_028_528_675_187_087_900_672, 100.0).unwrap();
+        assert!(approx_eq(state.price, 4.0));
+    }
+
+    #[test]
+    fn test_sqrt_price_x96_roundtrip() {
+        let encoded: u128 = 1_771_595_571_142_957_166_518_320_255_467_520;
+        let state = CpmmState::from_sqrt_price_x96(encoded, 1000.0).unwrap();
+        let recovered = state.to_sqrt_price_x96();
+        // f64 carries ~53 bits of mantissa, so compare relative to the encoded value
+        let relative_error = (recovered as f64 - encoded as f64).abs() / encoded as f64;
+        assert!(relative_error < 1e-12);
+    }
+
+    #[test]
+    fn test_extraction_cost_half() {
+        // Draining half the quote requires doubling the base reserves
+        let state = CpmmState::new(1000.0, 1.0).unwrap();
+        assert!(approx_eq(extraction_cost(state, 0.5, 0.0), 1000.0));
+        assert!(approx_eq(
+            extraction_cost(state, 0.5, 0.003),
+            1000.0 / 0.997
+        ));
+    }
+
+    #[test]
+    fn test_extraction_cost_ninety_nine_percent() {
+        let state = CpmmState::new(1000.0, 4.0).unwrap();
+        let base_in = extraction_cost(state, 0.99, 0.0);
+        assert!((base_in - 99.0 * state.base_reserves()).abs() < 1e-6);
+
+        // The pool is left holding 1% of its quote reserves
+        let k = state.base_reserves() * state.quote_reserves();
+        let quote_left = k / (state.base_reserves() + base_in);
+        assert!((quote_left - 0.01 * state.quote_reserves()).abs() < 1e-9);
+
+        assert!(extraction_cost(state, 1.0, 0.0).is_infinite());
+    }
+
+    #[test]
+    fn test_app_state_default_is_valid() {
+        assert!(AppState::default().validate().is_empty());
+    }
+
+    #[test]
+    fn test_app_state_validate_reports_each_problem() {
+        let state = AppState {
+            initial_liquidity: 0.0,
+            final_price: f64::NAN,
+            fee_percent: 100.0,
+            ..AppState::default()
+        };
+        let errors = state.validate();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("initialLiquidity"));
+    }
+
+    #[test]
+    fn test_collateral_ratio_after() {
+        // 100 base at price 1.0 against 50 quote of debt
+        assert!(approx_eq(collateral_ratio_after(1.0, 100.0, 50.0), 2.0));
+        // A price drop lowers the ratio
+        assert!(
+            collateral_ratio_after(0.8, 100.0, 50.0) < collateral_ratio_after(1.0, 100.0, 50.0)
+        );
+        assert!(collateral_ratio_after(1.0, 100.0, 0.0).is_infinite());
+    }
+
+    #[test]
+    fn test_blended_price_extremes() {
+        assert!(approx_eq(blended_price(1.0, 2.0, 0.0), 1.0));
+        assert!(approx_eq(blended_price(1.0, 2.0, 1.0), 2.0));
+    }
+
+    #[test]
+    fn test_blended_price_midpoint() {
+        assert!(approx_eq(blended_price(1.0, 2.0, 0.5), 1.5));
+    }
+
+    #[test]
+    fn test_exit_for_pnl_zero_target_is_breakeven() {
+        let pool = CpmmState::new(1000.0, 1.0).unwrap();
+        let result = exit_for_pnl(2.0, 10.0, 0.0, 0.003, pool).unwrap();
+
+        // Proceeds cover the cost basis exactly
+        assert!((result.quote_wallet_delta - 20.0).abs() < 1e-9);
+        // The whole position is sold, including the fee
+        let base_paid = -result.base_wallet_delta + result.base_fee_collected;
+        assert!((base_paid - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_exit_for_pnl_profit_target() {
+        let pool = CpmmState::new(1000.0, 1.0).unwrap();
+        let breakeven = exit_for_pnl(2.0, 10.0, 0.0, 0.003, pool).unwrap();
+        let profit = exit_for_pnl(2.0, 10.0, 5.0, 0.003, pool).unwrap();
+        assert!((profit.quote_wallet_delta - 25.0).abs() < 1e-9);
+        assert!(profit.quote_wallet_delta > breakeven.quote_wallet_delta);
+        // Selling base pushes the pool price down
+        assert!(profit.price_delta < 0.0);
+    }
+
+    #[test]
+    fn test_cpmm_state_rejects_zero_and_negative() {
+        assert_eq!(
+            CpmmState::new(0.0, 1.0).unwrap_err(),
+            CpmmError::NonPositiveLiquidity
+        );
+        assert_eq!(
+            CpmmState::new(-5.0, 1.0).unwrap_err(),
+            CpmmError::NonPositiveLiquidity
+        );
+        assert_eq!(
+            CpmmState::new(100.0, 0.0).unwrap_err(),
+            CpmmError::NonPositivePrice
+        );
+        assert_eq!(
+            CpmmState::new(100.0, -1.0).unwrap_err(),
+            CpmmError::NonPositivePrice
+        );
+        assert_eq!(
+            CpmmState::new(100.0, f64::NEG_INFINITY).unwrap_err(),
+            CpmmError::NonPositivePrice
+        );
+    }
+
+    #[test]
+    fn test_cpmm_state_rejects_nan_and_infinity() {
+        assert_eq!(
+            CpmmState::new(f64::NAN, 1.0).unwrap_err(),
+            CpmmError::NotFiniteLiquidity
+        );
+        assert_eq!(
+            CpmmState::new(f64::INFINITY, 1.0).unwrap_err(),
+            CpmmError::NotFiniteLiquidity
+        );
+        assert_eq!(
+            CpmmState::new(100.0, f64::NAN).unwrap_err(),
+            CpmmError::NotFinitePrice
+        );
+        assert_eq!(
+            CpmmState::new(100.0, f64::INFINITY).unwrap_err(),
+            CpmmError::NotFinitePrice
+        );
+    }
+
+    #[test]
+    fn test_build_info_version() {
+        let info = BuildInfo::current();
+        assert!(!info.version.is_empty());
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_swap_exact_base_in_updates_price() {
+        let state = CpmmState::new(1000.0, 1.0).unwrap();
+        let (quote_out, next) = state.swap_exact_base_in(100.0, 0.003);
+
+        assert!(quote_out > 0.0);
+        assert!(next.price < state.price);
+        assert!(approx_eq(
+            next.quote_reserves() / next.base_reserves(),
+            next.price
+        ));
+        assert!(approx_eq(next.invariant(), state.invariant()));
+        assert!(approx_eq(
+            state.quote_reserves() - next.quote_reserves(),
+            quote_out
+        ));
+    }
+
+    #[test]
+    fn test_swap_exact_quote_in_updates_price() {
+        let state = CpmmState::new(1000.0, 4.0).unwrap();
+        let (base_out, next) = state.swap_exact_quote_in(100.0, 0.003);
+
+        assert!(base_out > 0.0);
+        assert!(next.price > state.price);
+        assert!(approx_eq(
+            next.quote_reserves() / next.base_reserves(),
+            next.price
+        ));
+        assert!(approx_eq(
+            state.base_reserves() - next.base_reserves(),
+            base_out
+        ));
+    }
+
+    #[test]
+    fn test_swap_tiny_roundtrip_zero_fee() {
+        let state = CpmmState::new(1000.0, 1.0).unwrap();
+        let (quote_out, mid) = state.swap_exact_base_in(0.001, 0.0);
+        let (base_back, end) = mid.swap_exact_quote_in(quote_out, 0.0);
+
+        assert!((base_back - 0.001).abs() < 1e-12);
+        assert!((end.price - state.price).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_revenue_maximizing_fee_zero_elasticity() {
+        // Volume does not react to the fee, so revenue keeps rising to the cap
+        assert!(approx_eq(
+            revenue_maximizing_fee(1000.0, 0.0),
+            MAX_FEE_FRACTION
+        ));
+    }
+
+    #[test]
+    fn test_revenue_maximizing_fee_drops_with_elasticity() {
+        let low = revenue_maximizing_fee(1000.0, 10.0);
+        let high = revenue_maximizing_fee(1000.0, 200.0);
+        assert!(approx_eq(high, 0.005));
+        assert!(high < low);
+
+        // The optimum beats nearby fees
+        let best = elastic_fee_revenue(1000.0, 200.0, high);
+        assert!(best > elastic_fee_revenue(1000.0, 200.0, high * 0.9));
+        assert!(best > elastic_fee_revenue(1000.0, 200.0, high * 1.1));
+    }
+
+    #[test]
+    fn test_swap_base_out_matches_exact_input() {
+        let state = CpmmState::new(1000.0, 1.0).unwrap();
+        let (quote_in, next) = state.swap_base_out(50.0, 0.003).unwrap();
+        let (base_out, forward) = state.swap_exact_quote_in(quote_in, 0.003);
+
+        assert!((base_out - 50.0).abs() < 1e-9);
+        assert!((forward.price - next.price).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_swap_quote_out_matches_exact_input() {
+        let state = CpmmState::new(1000.0, 4.0).unwrap();
+        let (base_in, next) = state.swap_quote_out(100.0, 0.003).unwrap();
+        let (quote_out, forward) = state.swap_exact_base_in(base_in, 0.003);
+
+        assert!((quote_out - 100.0).abs() < 1e-9);
+        assert!((forward.price - next.price).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_swap_exact_output_exceeding_reserves() {
+        let state = CpmmState::new(1000.0, 4.0).unwrap();
+        // Exactly the reserves would divide by zero
+        assert!(state.swap_base_out(state.base_reserves(), 0.003).is_none());
+        assert!(
+            state
+                .swap_quote_out(state.quote_reserves(), 0.003)
+                .is_none()
+        );
+        assert!(
+            state
+                .swap_base_out(state.base_reserves() * 2.0, 0.003)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_log_drift_adds_across_trades() {
+        let first = log_drift(1.0, 1.5);
+        let second = log_drift(1.5, 0.9);
+        let third = log_drift(0.9, 2.0);
+        assert!(approx_eq(first + second + third, log_drift(1.0, 2.0)));
+        assert!(approx_eq(log_drift(2.0, 2.0), 0.0));
+    }
+
+    #[test]
+    fn test_price_impact_grows_with_move() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let small = trade_to_marginal_price(initial, 1.05, 0.003).unwrap();
+        let large = trade_to_marginal_price(initial, 1.5, 0.003).unwrap();
+
+        assert!(small.price_impact > 0.0);
+        assert!(large.price_impact > small.price_impact);
+
+        // Selling base executes below spot
+        let sell = trade_to_marginal_price(initial, 0.5, 0.003).unwrap();
+        assert!(sell.execution_price < initial.price);
+        assert!(sell.price_impact < 0.0);
+    }
+
+    #[test]
+    fn test_price_impact_no_trade() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let result = TradeResult::compute(initial, initial, 0.003);
+        assert_eq!(result.execution_price, 0.0);
+        assert_eq!(result.price_impact, 0.0);
+    }
+
+    #[test]
+    fn test_reserve_ratio_at_price() {
+        for &price in &[0.01, 1.0, 4.0, 250.0] {
+            let state = CpmmState::new(1000.0, price).unwrap();
+            let ratio = state.quote_reserves() / state.base_reserves();
+            assert!((reserve_ratio_at_price(price) - ratio).abs() / ratio < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_value_ratio_at_price_is_constant() {
+        for &price in &[0.01, 1.0, 4.0, 250.0] {
+            let state = CpmmState::new(1000.0, price).unwrap();
+            let base_value = state.base_reserves() * price;
+            let total_value = base_value + state.quote_reserves();
+            assert!(approx_eq(
+                value_ratio_at_price(price),
+                base_value / total_value
+            ));
+        }
+    }
+
+    #[test]
+    fn test_trade_result_net_includes_input_fee() {
+        // 100 base reaches the pool; the fee is 0.3% of that, paid on top
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let (quote_out, final_state) = initial.swap_exact_base_in(100.0, 0.0);
+        let result = TradeResult::compute(initial, final_state, 0.003);
+        assert!((result.base_fee_collected - 0.3).abs() < 1e-9);
+        assert!((result.base_wallet_delta_net + 100.3).abs() < 1e-9);
+        // The fee is on the input, so the output is the pool's
+        assert!((result.quote_wallet_delta_net - quote_out).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_limit_fill_no_cross() {
+        assert!(approx_eq(limit_fill(100.0, 5.0, 90.0, 99.0), 0.0));
+        assert!(approx_eq(limit_fill(100.0, 5.0, 101.0, 110.0), 0.0));
+    }
+
+    #[test]
+    fn test_limit_fill_partial_cross() {
+        // Price stops halfway through the order's band
+        let midpoint = 100.0 * (1.0 + LIMIT_ORDER_TICK) / 2.0;
+        assert!((limit_fill(100.0, 5.0, 90.0, midpoint) - 2.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_limit_fill_full_cross() {
+        assert!((limit_fill(100.0, 5.0, 90.0, 110.0) - 5.0).abs() < 1e-9);
+        // Either direction fills
+        assert!((limit_fill(100.0, 5.0, 110.0, 90.0) - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_liquidity_for_profile_meets_cap() {
+        let sizes = [5.0, 1.0, 20.0, 10.0, 2.0];
+        let liquidity = liquidity_for_profile(&sizes, 0.8, 0.01, 4.0);
+
+        // The 80th percentile trade (10 base) executes at exactly 1% impact
+        let state = CpmmState::new(liquidity, 4.0).unwrap();
+        let (quote_in, _) = state.swap_base_out(10.0, 0.0).unwrap();
+        let impact = (quote_in / 10.0) / state.price - 1.0;
+        assert!((impact - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_liquidity_for_profile_monotonic() {
+        let sizes = [5.0, 1.0, 20.0, 10.0, 2.0];
+        let base = liquidity_for_profile(&sizes, 0.5, 0.01, 1.0);
+        assert!(liquidity_for_profile(&sizes, 0.9, 0.01, 1.0) > base);
+        assert!(liquidity_for_profile(&sizes, 0.5, 0.005, 1.0) > base);
+    }
+
+    #[test]
+    fn test_trade_from_inputs() {
+        let result = trade_from_inputs(1000.0, 1.0, 1.21, 0.3).unwrap();
+        assert!((result.base_wallet_delta - 1000.0 / 11.0).abs() < 1e-9);
+        assert!((result.quote_wallet_delta + 100.0).abs() < 1e-9);
+
+        assert!(trade_from_inputs(-1.0, 1.0, 1.21, 0.3).is_err());
+        assert!(trade_from_inputs(1000.0, 1.0, 0.0, 0.3).is_err());
+        assert!(trade_from_inputs(1000.0, 1.0, 1.21, 100.0).is_err());
+    }
+
+    #[test]
+    fn test_effective_fee_crossing_rebate_tiers() {
+        let tiers = [(10_000.0, 0.05), (100_000.0, 0.1)];
+        // Below the first tier pays the full fee
+        assert!(approx_eq(
+            effective_fee_after_rebate(0.3, 5_000.0, &tiers),
+            0.3
+        ));
+        // Reaching a threshold unlocks that tier
+        assert!(approx_eq(
+            effective_fee_after_rebate(0.3, 10_000.0, &tiers),
+            0.25
+        ));
+        assert!(approx_eq(
+            effective_fee_after_rebate(0.3, 50_000.0, &tiers),
+            0.25
+        ));
+        // Crossing into the top tier applies only the larger rebate
+        assert!(approx_eq(
+            effective_fee_after_rebate(0.3, 250_000.0, &tiers),
+            0.2
+        ));
+    }
+
+    #[test]
+    fn test_effective_fee_unordered_tiers() {
+        let tiers = [(100_000.0, 0.1), (10_000.0, 0.05)];
+        assert!(approx_eq(
+            effective_fee_after_rebate(0.3, 150_000.0, &tiers),
+            0.2
+        ));
+        assert!(approx_eq(effective_fee_after_rebate(0.3, 0.0, &[]), 0.3));
+    }
+
+    #[test]
+    fn test_effective_fee_net_rebate() {
+        // A maker rebate above the base fee pays the trader
+        let tiers = [(0.0, 0.4)];
+        assert!(approx_eq(
+            effective_fee_after_rebate(0.3, 1.0, &tiers),
+            -0.1
+        ));
+    }
+
+    #[test]
+    fn test_parse_rebate_tiers() {
+        let tiers = parse_rebate_tiers("10000:0.05, 100000 : 0.1").unwrap();
+        assert_eq!(tiers, vec![(10_000.0, 0.05), (100_000.0, 0.1)]);
+        assert_eq!(parse_rebate_tiers(" ").unwrap(), vec![]);
+        assert!(parse_rebate_tiers("10000").is_none());
+        assert!(parse_rebate_tiers("10000:x").is_none());
+        // Halves parse like typed numbers, with an optional % on the rebate
+        assert_eq!(
+            parse_rebate_tiers("1e4:0.05%, 100000: 0.1 %").unwrap(),
+            tiers
+        );
+        assert_eq!(
+            parse_rebate_tiers(&format_rebate_tiers(&tiers)).unwrap(),
+            tiers
+        );
+    }
+
+    #[test]
+    fn test_swap_then_add_decomposes() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let (final_state, combined) = swap_then_add(initial, 50.0, 200.0, 0.003).unwrap();
+
+        // Same as swapping, then adding liquidity at the new price
+        let (_, swapped) = initial.swap_exact_base_in(50.0, 0.003);
+        let added = CpmmState::new(swapped.liquidity + 200.0, swapped.price).unwrap();
+        assert!(approx_eq(final_state.liquidity, 1200.0));
+        assert!(approx_eq(final_state.price, swapped.price));
+
+        let swap = TradeResult::compute(initial, swapped, 0.003);
+        let base_deposit = added.base_reserves() - swapped.base_reserves();
+        let quote_deposit = added.quote_reserves() - swapped.quote_reserves();
+        assert!(approx_eq(
+            combined.base_wallet_delta_net,
+            swap.base_wallet_delta_net - base_deposit
+        ));
+        assert!(approx_eq(
+            combined.quote_wallet_delta_net,
+            swap.quote_wallet_delta_net - quote_deposit
+        ));
+
+        // Combined wallet deltas mirror the total pool reserve change
+        assert!(approx_eq(
+            combined.base_wallet_delta,
+            initial.base_reserves() - final_state.base_reserves()
+        ));
+        assert!(approx_eq(
+            combined.quote_wallet_delta,
+            initial.quote_reserves() - final_state.quote_reserves()
+        ));
+
+        // Fees and slippage belong to the swap leg only
+        assert!(approx_eq(
+            combined.base_fee_collected,
+            swap.base_fee_collected
+        ));
+        assert!(approx_eq(combined.execution_price, swap.execution_price));
+    }
+
+    #[test]
+    fn test_swap_then_add_rejects_draining_withdrawal() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        assert_eq!(
+            swap_then_add(initial, 10.0, -1000.0, 0.003).unwrap_err(),
+            CpmmError::NonPositiveLiquidity
+        );
+    }
+
+    #[test]
+    fn test_concentrated_reserves_single_asset_at_bounds() {
+        // L = 100, range [1, 4]
+        let at_lower = ConcentratedCpmmState::new(100.0, 1.0, 1.0, 4.0).unwrap();
+        // x = L * (1/sqrt(1) - 1/sqrt(4)) = 50, y = 0
+        assert!(approx_eq(at_lower.base_reserves(), 50.0));
+        assert!(approx_eq(at_lower.quote_reserves(), 0.0));
+
+        let at_upper = ConcentratedCpmmState::new(100.0, 4.0, 1.0, 4.0).unwrap();
+        // x = 0, y = L * (sqrt(4) - sqrt(1)) = 100
+        assert!(approx_eq(at_upper.base_reserves(), 0.0));
+        assert!(approx_eq(at_upper.quote_reserves(), 100.0));
+    }
+
+    #[test]
+    fn test_concentrated_reserves_clamp_outside_range() {
+        let below = ConcentratedCpmmState::new(100.0, 0.25, 1.0, 4.0).unwrap();
+        assert!(approx_eq(below.base_reserves(), 50.0));
+        assert!(approx_eq(below.quote_reserves(), 0.0));
+
+        let above = ConcentratedCpmmState::new(100.0, 9.0, 1.0, 4.0).unwrap();
+        assert!(approx_eq(above.base_reserves(), 0.0));
+        assert!(approx_eq(above.quote_reserves(), 100.0));
+    }
+
+    #[test]
+    fn test_concentrated_full_range_matches_cpmm() {
+        let cpmm = CpmmState::new(100.0, 4.0).unwrap();
+        let full = ConcentratedCpmmState::from(cpmm);
+        assert!(approx_eq(full.base_reserves(), cpmm.base_reserves()));
+        assert!(approx_eq(full.quote_reserves(), cpmm.quote_reserves()));
+
+        // Widening a finite range converges on the full-range reserves
+        let wide = ConcentratedCpmmState::new(100.0, 4.0, 1e-12, 1e12).unwrap();
+        assert!((wide.base_reserves() - cpmm.base_reserves()).abs() < 1e-3);
+        assert!((wide.quote_reserves() - cpmm.quote_reserves()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_concentrated_invalid_range() {
+        assert_eq!(
+            ConcentratedCpmmState::new(100.0, 1.0, 2.0, 2.0).unwrap_err(),
+            CpmmError::InvalidPriceRange
+        );
+        assert_eq!(
+            ConcentratedCpmmState::new(100.0, 1.0, -1.0, 2.0).unwrap_err(),
+            CpmmError::InvalidPriceRange
+        );
+        assert_eq!(
+            ConcentratedCpmmState::new(100.0, 1.0, 0.5, f64::NAN).unwrap_err(),
+            CpmmError::InvalidPriceRange
+        );
+    }
+
+    #[test]
+    fn test_concentrated_trade_stops_at_range_edge() {
+        // Moving past p_upper trades no more than moving to p_upper
+        let initial = ConcentratedCpmmState::new(100.0, 1.0, 0.25, 4.0).unwrap();
+        let to_edge = ConcentratedCpmmState::new(100.0, 4.0, 0.25, 4.0).unwrap();
+        let past_edge = ConcentratedCpmmState::new(100.0, 16.0, 0.25, 4.0).unwrap();
+        let edge = TradeResult::compute(initial, to_edge, 0.003);
+        let past = TradeResult::compute(initial, past_edge, 0.003);
+        assert!(approx_eq(edge.base_wallet_delta, past.base_wallet_delta));
+        assert!(approx_eq(edge.quote_wallet_delta, past.quote_wallet_delta));
+    }
+
+    #[test]
+    fn test_invert_price() {
+        assert!(approx_eq(invert_price(4.0), 0.25));
+        assert!(approx_eq(invert_price(invert_price(1.1)), 1.1));
+        assert_eq!(invert_price(0.0), f64::INFINITY);
+        assert_eq!(invert_price(f64::INFINITY), 0.0);
+    }
+
+    #[test]
+    fn test_inverted_slider_mirrors_position() {
+        let mut state = AppState {
+            center_price: 2.0,
+            ..AppState::default()
+        };
+        let upright = state.slider_for_price(20.0);
+        state.inverted = true;
+        let inverted = state.slider_for_price(20.0);
+        // 1/20 sits as far below 1/2 as 20 sits above 2
+        assert!(approx_eq(upright + inverted, 1.0));
+        assert!(approx_eq(state.price_for_slider(inverted), 20.0));
+    }
+
+    #[test]
+    fn test_inverted_range_swaps_bounds() {
+        let mut state = AppState {
+            price_lower: 0.5,
+            price_upper: 4.0,
+            inverted: true,
+            ..AppState::default()
+        };
+        let (lower, upper) = state.displayed_range();
+        assert!(approx_eq(lower, 0.25));
+        assert!(approx_eq(upper, 2.0));
+
+        state.set_displayed_range(0.0, 2.0);
+        assert!(approx_eq(state.price_lower, 0.5));
+        assert_eq!(state.price_upper, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_fee_capture_narrower_than_move() {
+        // Move 1 -> 3, position [1.5, 2]: captures 0.5 of a 2.0 move
+        assert!(approx_eq(fee_capture_fraction(1.5, 2.0, 1.0, 3.0), 0.25));
+        // Direction does not matter
+        assert!(approx_eq(fee_capture_fraction(1.5, 2.0, 3.0, 1.0), 0.25));
+    }
+
+    #[test]
+    fn test_fee_capture_wider_than_move() {
+        assert!(approx_eq(fee_capture_fraction(0.5, 4.0, 1.0, 3.0), 1.0));
+        assert!(approx_eq(
+            fee_capture_fraction(0.0, f64::INFINITY, 1.0, 1.1),
+            1.0
+        ));
+    }
+
+    #[test]
+    fn test_fee_capture_partial_and_disjoint() {
+        // Position covers the upper half of the move
+        assert!(approx_eq(fee_capture_fraction(2.0, 5.0, 1.0, 3.0), 0.5));
+        assert!(approx_eq(fee_capture_fraction(4.0, 5.0, 1.0, 3.0), 0.0));
+    }
+
+    #[test]
+    fn test_constrained_trade_price_binds() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let (result, constraint) = constrained_trade(initial, 1.05, 0.1, 0.003).unwrap();
+        assert_eq!(constraint, Constraint::Price);
+        assert!(approx_eq(result.price_delta, 0.05));
+        assert!(result.price_impact < 0.1);
+    }
+
+    #[test]
+    fn test_constrained_trade_slippage_binds_buying() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let (result, constraint) = constrained_trade(initial, 2.0, 0.02, 0.003).unwrap();
+        assert_eq!(constraint, Constraint::Slippage);
+        // Stops exactly at the cap, short of the target
+        assert!(approx_eq(result.price_impact, 0.02));
+        assert!(result.price_delta > 0.0 && result.price_delta < 1.0);
+    }
+
+    #[test]
+    fn test_constrained_trade_slippage_binds_selling() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let (result, constraint) = constrained_trade(initial, 0.5, 0.02, 0.003).unwrap();
+        assert_eq!(constraint, Constraint::Slippage);
+        assert!(approx_eq(result.price_impact, -0.02));
+    }
+
+    #[test]
+    fn test_constrained_trade_cap_below_fee() {
+        // The fee alone costs more than the cap, so nothing trades
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let (result, constraint) = constrained_trade(initial, 1.5, 0.001, 0.003).unwrap();
+        assert_eq!(constraint, Constraint::Slippage);
+        assert!(approx_eq(result.price_delta, 0.0));
+        assert!(approx_eq(result.base_wallet_delta, 0.0));
+    }
+
+    #[test]
+    fn test_app_state_json_round_trip() {
+        let state = AppState {
+            initial_liquidity: 2500.0,
+            initial_price: 1.7,
+            final_price: 0.9,
+            fee_percent: 1.0,
+            center_price: 2.0,
+            decades: 4.0,
+            inverted: true,
+            rebate_tiers: vec![(1.0, 0.01)],
+            ..AppState::default()
+        };
+        assert_eq!(AppState::from_json(&state.to_json()), Some(state));
+    }
+
+    #[test]
+    fn test_app_state_from_json_rejects_corrupt() {
+        assert_eq!(AppState::from_json("not json"), None);
+        assert_eq!(AppState::from_json(r#"{"initialLiquidity": "x"}"#), None);
+        // Parses, but describes an invalid pool
+        assert_eq!(AppState::from_json(r#"{"initialLiquidity": -5}"#), None);
+        // Missing fields take their defaults
+        assert_eq!(AppState::from_json("{}"), Some(AppState::default()));
+    }
+
+    #[test]
+    fn test_gbm_fees_zero_volatility() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let fees = expected_fees_gbm(initial, 0.0, 0.0, 250, 7, 0.003);
+        assert!(fees.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_gbm_fees_increase_with_volatility() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        for seed in [1, 2, 3] {
+            let calm = expected_fees_gbm(initial, 0.2, 0.0, 250, seed, 0.003);
+            let wild = expected_fees_gbm(initial, 0.8, 0.0, 250, seed, 0.003);
+            assert!(calm > 0.0);
+            assert!(wild > calm);
+        }
+    }
+
+    #[test]
+    fn test_gbm_fees_reproducible_from_seed() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let a = expected_fees_gbm(initial, 0.5, 0.1, 100, 42, 0.003);
+        let b = expected_fees_gbm(initial, 0.5, 0.1, 100, 42, 0.003);
+        let c = expected_fees_gbm(initial, 0.5, 0.1, 100, 43, 0.003);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_split_mix_uniform_range() {
+        let mut rng = SplitMix64::new(0);
+        for _ in 0..1000 {
+            let u = rng.next_f64();
+            assert!((0.0..1.0).contains(&u));
+        }
+    }
+
+    #[test]
+    fn test_query_round_trip() {
+        let states = [
+            AppState::default(),
+            AppState {
+                initial_liquidity: 2500.5,
+                initial_price: 0.000123,
+                final_price: 1.75,
+                fee_percent: 1.0,
+                ..AppState::default()
+            },
+            AppState {
+                initial_liquidity: 1e9,
+                initial_price: 3000.0,
+                final_price: 2999.999,
+                fee_percent: 0.0,
+                center_price: 3000.0,
+                decades: 1.5,
+                ..AppState::default()
+            },
+        ];
+        for state in states {
+            assert_eq!(
+                parse_state_from_query(&state_to_query(&state, "calc"), "calc"),
+                state
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_parses_example() {
+        let state =
+            parse_state_from_query("?calc.l=1000&calc.ip=1&calc.fp=1.1&calc.fee=0.3", "calc");
+        assert_eq!(state, AppState::default());
+        let state = parse_state_from_query("?calc.ip=2&calc.fp=3", "calc");
+        assert!(approx_eq(state.initial_price, 2.0));
+        assert!(approx_eq(state.final_price, 3.0));
+    }
+
+    #[test]
+    fn test_query_keys_are_scoped_to_their_calculator() {
+        let query = "?page=7&ip=5&first.ip=2&second.ip=3&first.fp=4";
+        let first = parse_state_from_query(query, "first");
+        assert!(approx_eq(first.initial_price, 2.0));
+        assert!(approx_eq(first.final_price, 4.0));
+        let second = parse_state_from_query(query, "second");
+        assert!(approx_eq(second.initial_price, 3.0));
+        assert_eq!(second.final_price, AppState::default().final_price);
+        // Unprefixed keys belong to the host page
+        assert_eq!(parse_state_from_query(query, "third"), AppState::default());
+    }
+
+    #[test]
+    fn test_query_invalid_fields_fall_back_per_field() {
+        let defaults = AppState::default();
+        let state =
+            parse_state_from_query("c.l=-5&c.ip=abc&c.fp=2&c.fee=100&c.d=0&junk&c.x=1", "c");
+        assert_eq!(state.initial_liquidity, defaults.initial_liquidity);
+        assert_eq!(state.initial_price, defaults.initial_price);
+        assert_eq!(state.fee_percent, defaults.fee_percent);
+        assert_eq!(state.decades, defaults.decades);
+        assert!(approx_eq(state.final_price, 2.0));
+        assert_eq!(parse_state_from_query("", "c"), defaults);
+    }
+
+    #[test]
+    fn test_position_composition_at_bounds() {
+        assert!(approx_eq(fully_base_price(1.0), 1.0));
+        assert!(approx_eq(fully_quote_price(4.0), 4.0));
+
+        assert_eq!(position_composition(1.0, 1.0, 4.0), (1.0, 0.0));
+        assert_eq!(position_composition(0.5, 1.0, 4.0), (1.0, 0.0));
+        assert_eq!(position_composition(4.0, 1.0, 4.0), (0.0, 1.0));
+        assert_eq!(position_composition(9.0, 1.0, 4.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_position_composition_midpoint() {
+        // Even split at the geometric midpoint sqrt(1 * 4) = 2
+        let (base, quote) = position_composition(2.0, 1.0, 4.0);
+        assert!(approx_eq(base, 0.5));
+        assert!(approx_eq(quote, 0.5));
+
+        // Above the midpoint the position holds more quote
+        let (base, quote) = position_composition(3.0, 1.0, 4.0);
+        assert!(quote > base);
+        assert!(approx_eq(base + quote, 1.0));
+    }
+
+    #[test]
+    fn test_format_number_default_precision() {
+        assert_eq!(format_number(1.1, 6), "1.100000");
+        assert_eq!(format_number(0.00001234, 6), "1.234000e-5");
+        assert_eq!(format_number(2_500_000.0, 6), "2.5000e6");
+        assert_eq!(format_number(0.0, 6), "0.000000");
+    }
+
+    #[test]
+    fn test_format_number_precision_two() {
+        assert_eq!(format_number(1.23456, 2), "1.23");
+        assert_eq!(format_number(0.00001234, 2), "1.23e-5");
+        assert_eq!(format_number(2_500_000.0, 2), "2e6");
+    }
+
+    #[test]
+    fn test_format_number_precision_twelve() {
+        assert_eq!(format_number(1.0 / 3.0, 12), "0.333333333333");
+        assert_eq!(format_number(1.5e-18, 12), "1.500000000000e-18");
+        assert_eq!(format_number(1.5e9, 12), "1.5000000000e9");
+    }
+
+    #[test]
+    fn test_display_values_fields() {
+        let display = compute_display_values(&AppState::default()).unwrap();
+        assert_eq!(display.get("initial-base-reserves"), Some("1000.000000"));
+        assert_eq!(
+            display.get("delta-price"),
+            Some(format_number(0.1, 6).as_str())
+        );
+        assert_eq!(display.get("initial-price"), None);
+    }
+
+    #[test]
+    fn test_compute_display_values_rejects_invalid_pool() {
+        let state = AppState {
+            initial_liquidity: -1.0,
+            ..AppState::default()
+        };
+        assert_eq!(
+            compute_display_values(&state).unwrap_err(),
+            CpmmError::NonPositiveLiquidity
+        );
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands("1234.5"), "1,234.5");
+        assert_eq!(group_thousands("1000000.000000"), "1,000,000.000000");
+        assert_eq!(group_thousands("999.25"), "999.25");
+        assert_eq!(group_thousands("-1234567"), "-1,234,567");
+        assert_eq!(group_thousands("1.5e-7"), "1.5e-7");
+    }
+
+    #[test]
+    fn test_format_number_grouped() {
+        assert_eq!(format_number_grouped(1234.5, 1), "1,234.5");
+        assert_eq!(format_number_grouped(-1234.5, 2), "-1,234.50");
+        assert_eq!(format_number_grouped(1_000_000.0, 6), "1,000,000.000000");
+        // Extreme magnitudes keep the scientific fallback
+        assert_eq!(format_number_grouped(2e18, 6), "2.0000e18");
+        assert_eq!(format_number_grouped(0.00001, 2), "1.00e-5");
+    }
+
+    #[test]
+    fn test_format_abbreviated() {
+        assert_eq!(format_abbreviated(1200.0, 1), "1.2K");
+        assert_eq!(format_abbreviated(3_400_000.0, 1), "3.4M");
+        assert_eq!(format_abbreviated(5.6e9, 1), "5.6B");
+        assert_eq!(format_abbreviated(7.8e12, 1), "7.8T");
+        assert_eq!(format_abbreviated(-3_400_000.0, 2), "-3.40M");
+        assert_eq!(format_abbreviated(0.0, 2), "0.00");
+        // Small, huge and non-finite values keep format_number
+        assert_eq!(format_abbreviated(999.5, 1), format_number(999.5, 1));
+        assert_eq!(format_abbreviated(2e18, 6), format_number(2e18, 6));
+        assert_eq!(format_abbreviated(f64::NAN, 2), "NaN");
+    }
+
+    #[test]
+    fn test_format_abbreviated_rounding_carries_to_next_suffix() {
+        assert_eq!(format_abbreviated(999_999.0, 1), "1.0M");
+        assert_eq!(format_abbreviated(-999_999.0, 2), "-1.00M");
+        assert_eq!(format_abbreviated(999_999_999.0, 1), "1.0B");
+        assert_eq!(format_abbreviated(999.96, 1), "1.0K");
+        // Just below the carry the smaller suffix stays
+        assert_eq!(format_abbreviated(999_940.0, 1), "999.9K");
+        assert_eq!(format_abbreviated(999_999.0, 3), "999.999K");
+    }
+
+    #[test]
+    fn test_display_mode_abbreviates_computed_values_only() {
+        let state = AppState {
+            initial_liquidity: 2_500_000.0,
+            display_precision: 2,
+            display_mode: DisplayMode::Abbreviated,
+            ..AppState::default()
+        };
+        assert_eq!(state.display(2_500_000.0), format_number(2_500_000.0, 2));
+        let display = compute_display_values(&state).unwrap();
+        assert_eq!(display.get("initial-base-reserves"), Some("2.50M"));
+    }
+
+    #[test]
+    fn test_parse_user_number_strips_grouping() {
+        for value in [1234.5, -9_876_543.25, 0.5] {
+            let text = format_number_grouped(value, 6);
+            assert_eq!(parse_user_number(&text), Some(value));
+        }
+    }
+
+    #[test]
+    fn test_parse_user_number() {
+        assert_eq!(parse_user_number(" 1e6 "), Some(1e6));
+        assert_eq!(parse_user_number("1,000.5"), Some(1000.5));
+        assert_eq!(parse_user_number("0.3%"), Some(0.003));
+        assert_eq!(parse_user_number("-2.5E-3"), Some(-0.0025));
+        for garbage in ["abc", "", "%", "1%%", "1e", "NaN", "inf"] {
+            assert_eq!(parse_user_number(garbage), None, "{}", garbage);
+        }
+    }
+
+    #[test]
+    fn test_parse_user_count() {
+        assert_eq!(parse_user_count(" 1,000 "), Some(1000));
+        assert_eq!(parse_user_count("1e3"), Some(1000));
+        assert_eq!(parse_user_count("1.5"), None);
+        assert_eq!(parse_user_count("-1"), None);
+    }
+
+    #[test]
+    fn test_batch_clearing_balanced_flows() {
+        let initial = CpmmState::new(1000.0, 2.0).unwrap();
+        // 100 quote of buys against 50 base of sells at price 2
+        let (final_state, clearing) = batch_clearing(initial, &[60.0, 40.0], &[20.0, 30.0], 0.003);
+        assert!(approx_eq(final_state.price, 2.0));
+        assert!(approx_eq(clearing, 2.0));
+    }
+
+    #[test]
+    fn test_batch_clearing_nets_before_applying() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        // Net flow is 30 quote of buying
+        let (final_state, clearing) = batch_clearing(initial, &[50.0], &[20.0], 0.003);
+        let (_, expected) = initial.swap_exact_quote_in(30.0, 0.003);
+        assert!(approx_eq(final_state.price, expected.price));
+        assert!(approx_eq(clearing, final_state.price));
+        assert!(clearing > 1.0);
+
+        // Net selling moves the price down
+        let (_, clearing) = batch_clearing(initial, &[10.0], &[40.0], 0.003);
+        assert!(clearing < 1.0);
+    }
+
+    #[test]
+    fn test_batch_clearing_empty_batch() {
+        let initial = CpmmState::new(1000.0, 1.5).unwrap();
+        let (_, clearing) = batch_clearing(initial, &[], &[], 0.003);
+        assert!(approx_eq(clearing, 1.5));
+    }
+
+    #[test]
+    fn test_price_halflife_steps_geometric_decay() {
+        // Closing half the gap each step halves it in exactly one step
+        assert!(approx_eq(price_halflife_steps(1.2, 1.0, 0.5), 1.0));
+        // 10% per step: 0.9^n = 0.5
+        let steps = price_halflife_steps(1.2, 1.0, 0.1);
+        assert!(approx_eq(0.9f64.powf(steps), 0.5));
+        // The half-life does not depend on the size or sign of the gap
+        assert!(approx_eq(steps, price_halflife_steps(0.5, 1.0, 0.1)));
+        // Stronger pressure closes the gap faster
+        assert!(price_halflife_steps(1.2, 1.0, 0.2) < steps);
+    }
+
+    #[test]
+    fn test_price_halflife_steps_edge_cases() {
+        assert_eq!(price_halflife_steps(1.0, 1.0, 0.1), 0.0);
+        assert!(price_halflife_steps(1.2, 1.0, 0.0).is_infinite());
+        assert_eq!(price_halflife_steps(1.2, 1.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_liquidity_change_pure_add() {
+        // Doubling liquidity at a constant price deposits both tokens, fee-free
+        let price = 4.0;
+        let initial = ConcentratedCpmmState::from(CpmmState::new(1000.0, price).unwrap());
+        let final_state = ConcentratedCpmmState::from(CpmmState::new(2000.0, price).unwrap());
+        let change =
+            LiquidityChangeResult::compute(initial, final_state, 0.003, 1.0, FeeMode::OnInput);
+
+        assert!(approx_eq(change.base_liquidity_delta, -500.0));
+        assert!(approx_eq(change.quote_liquidity_delta, -2000.0));
+        // Deposited in the pool's ratio, quote / base = P
+        assert!(approx_eq(
+            change.quote_liquidity_delta / change.base_liquidity_delta,
+            price
+        ));
+        assert_eq!(change.trade.base_wallet_delta, 0.0);
+        assert_eq!(change.trade.quote_wallet_delta, 0.0);
+        assert_eq!(change.trade.base_fee_collected, 0.0);
+        assert_eq!(change.trade.quote_fee_collected, 0.0);
+    }
+
+    #[test]
+    fn test_liquidity_change_with_price_move() {
+        // The trade part matches a constant-liquidity trade to the same price
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let moved = CpmmState::new(1000.0, 1.21).unwrap();
+        let final_state = CpmmState::new(800.0, 1.21).unwrap();
+        let change = LiquidityChangeResult::compute(
+            initial.into(),
+            final_state.into(),
+            0.003,
+            1.0,
+            FeeMode::OnInput,
+        );
+        let trade = TradeResult::compute(initial, moved, 0.003);
+
+        assert!(approx_eq(
+            change.trade.base_wallet_delta,
+            trade.base_wallet_delta
+        ));
+        assert!(approx_eq(
+            change.trade.quote_fee_collected,
+            trade.quote_fee_collected
+        ));
+        // Removing liquidity returns both tokens to the wallet
+        assert!(approx_eq(change.base_liquidity_delta, 200.0 / 1.1));
+        assert!(approx_eq(change.quote_liquidity_delta, 200.0 * 1.1));
+        // Trade and withdrawal together account for the whole reserve change
+        let total_base = change.trade.base_wallet_delta + change.base_liquidity_delta;
+        assert!(approx_eq(
+            total_base,
+            initial.base_reserves() - final_state.base_reserves()
+        ));
+    }
+
+    #[test]
+    fn test_display_values_pure_liquidity_add() {
+        let state = AppState {
+            final_price: 1.0,
+            final_liquidity: 1500.0,
+            ..AppState::default()
+        };
+        let display = compute_display_values(&state).unwrap();
+        let value = |id| display.get(id).unwrap().parse::<f64>().unwrap();
+        assert_eq!(value("delta-base-reserves"), 0.0);
+        assert_eq!(value("fee-quote-collected"), 0.0);
+        assert_eq!(
+            display.get("delta-base-liquidity"),
+            Some(format_number(-500.0, 6).as_str())
+        );
+        assert_eq!(
+            display.get("final-base-reserves"),
+            Some(format_number(1500.0, 6).as_str())
+        );
+    }
+
+    #[test]
+    fn test_stableswap_d_balanced() {
+        // Balanced reserves give D = x + y for any amplification
+        for a in [1.0, 10.0, 1000.0] {
+            assert!(approx_eq(stableswap_d(a, 1000.0, 1000.0), 2000.0));
+        }
+    }
+
+    #[test]
+    fn test_stableswap_d_satisfies_invariant() {
+        // Slightly off balance, where the curve is flattest
+        let (a, x, y) = (100.0, 1000.0, 1000.001);
+        let d = stableswap_d(a, x, y);
+        let lhs = 4.0 * a * (x + y) + d;
+        let rhs = 4.0 * a * d + d * d * d / (4.0 * x * y);
+        assert!(((lhs - rhs) / lhs).abs() < 1e-12);
+        assert!(d > 0.0 && d <= x + y);
+        assert!(approx_eq(stableswap_y(a, x, d), y));
+    }
+
+    #[test]
+    fn test_stableswap_from_price_round_trip() {
+        let balanced = StableswapState::from_price(100.0, 2000.0, 1.0).unwrap();
+        assert!(approx_eq(balanced.base, 1000.0));
+        assert!(approx_eq(balanced.quote, 1000.0));
+        for price in [0.5, 0.99, 1.0001, 2.0] {
+            let state = StableswapState::from_price(100.0, 2000.0, price).unwrap();
+            assert!(approx_eq(state.price(), price));
+            assert!(approx_eq(state.invariant(), 2000.0));
+        }
+        assert_eq!(
+            StableswapState::from_price(0.0, 2000.0, 1.0).unwrap_err(),
+            CpmmError::InvalidAmplification
+        );
+    }
+
+    #[test]
+    fn test_stableswap_slippage_below_constant_product() {
+        // Same reserves and the same trade size in both pools
+        let cpmm = CpmmState::new(1000.0, 1.0).unwrap();
+        let stable = StableswapState::from_price(100.0, 2000.0, 1.0).unwrap();
+        let (cpmm_out, _) = cpmm.swap_exact_base_in(100.0, 0.0);
+        let (stable_out, stable_after) = stable.swap_exact_base_in(100.0, 0.0);
+
+        // Slippage: shortfall from the initial price of 1
+        let cpmm_slippage = 1.0 - cpmm_out / 100.0;
+        let stable_slippage = 1.0 - stable_out / 100.0;
+        assert!(stable_slippage < cpmm_slippage);
+        assert!(stable_slippage > 0.0);
+        assert!(stable_after.price() < 1.0);
+
+        // Higher amplification flattens the curve further
+        let flatter = StableswapState::from_price(1000.0, 2000.0, 1.0).unwrap();
+        let (flatter_out, _) = flatter.swap_exact_base_in(100.0, 0.0);
+        assert!(flatter_out > stable_out);
+    }
+
+    #[test]
+    fn test_display_values_stableswap_model() {
+        let cpmm = compute_display_values(&AppState::default()).unwrap();
+        let stable = compute_display_values(&AppState {
+            model: AmmModel::Stableswap,
+            final_price: 1.001,
+            ..AppState::default()
+        })
+        .unwrap();
+        // Balanced reserves match the CPMM at price 1
+        assert_eq!(
+            stable.get("initial-base-reserves"),
+            cpmm.get("initial-base-reserves")
+        );
+        // A small move on the flat part of the curve exchanges far more
+        let value =
+            |display: &DisplayValues, id| -> f64 { display.get(id).unwrap().parse().unwrap() };
+        let cpmm_small = compute_display_values(&AppState {
+            final_price: 1.001,
+            ..AppState::default()
+        })
+        .unwrap();
+        assert!(
+            value(&stable, "delta-base-reserves")
+                > 10.0 * value(&cpmm_small, "delta-base-reserves")
+        );
+    }
+
+    #[test]
+    fn test_composition_sweep_sums_to_tvl() {
+        let sweep = composition_sweep(1000.0, 2.0, 3.0, 31);
+        assert_eq!(sweep.len(), 31);
+        for (price, base_value, quote_value) in &sweep {
+            let tvl = 2.0 * 1000.0 * price.sqrt();
+            assert!(((base_value + quote_value) / tvl - 1.0).abs() < 1e-12);
+        }
+        // The sweep spans the slider range and is centered on the center price
+        assert!(approx_eq(sweep[0].0, 2.0e-3));
+        assert!(approx_eq(sweep[15].0, 2.0));
+        assert!(approx_eq(sweep[30].0, 2.0e3));
+    }
+
+    #[test]
+    fn test_stacked_area_points() {
+        let sweep = [(1.0, 1.0, 1.0), (4.0, 2.0, 2.0)];
+        let (base, quote) = stacked_area_points(&sweep, 100.0, 40.0);
+        // The largest total fills the height; base is half of it
+        assert_eq!(base, "0.00,30.00 100.00,20.00 100.00,40.00 0.00,40.00");
+        assert_eq!(quote, "0.00,20.00 100.00,0.00 100.00,20.00 0.00,30.00");
+        assert_eq!(
+            stacked_area_points(&[], 100.0, 40.0),
+            (String::new(), String::new())
+        );
+    }
+
+    #[test]
+    fn test_annualized_price_effect_30_days() {
+        // A 10% rise over 30 days annualizes to 10% * 365 / 30
+        let boost = annualized_price_effect(1.0, 1.1, 30.0);
+        assert!(approx_eq(boost, 0.1 * 365.0 / 30.0));
+        // A 10% fall is a drag of the same size
+        assert!(approx_eq(annualized_price_effect(2.0, 1.8, 30.0), -boost));
+        // The same move over a longer horizon is a smaller annual effect
+        assert!(annualized_price_effect(1.0, 1.1, 365.0) < boost);
+    }
+
+    #[test]
+    fn test_annualized_price_effect_edge_cases() {
+        assert_eq!(annualized_price_effect(1.0, 1.0, 30.0), 0.0);
+        assert_eq!(annualized_price_effect(1.0, 1.0, 0.0), 0.0);
+        assert_eq!(annualized_price_effect(1.0, 0.9, 0.0), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_amm_model_option_values_round_trip() {
+        for (value, _) in AmmModel::OPTIONS {
+            assert_eq!(AmmModel::parse(value).unwrap().as_str(), *value);
+        }
+        for model in [
+            AmmModel::ConstantProduct,
+            AmmModel::Stableswap,
+            AmmModel::Concentrated,
+            AmmModel::Weighted,
+        ] {
+            assert_eq!(AmmModel::parse(model.as_str()), Some(model));
+            // Saved state uses the same strings as the options
+            let json = serde_json::to_string(&model).unwrap();
+            assert_eq!(json, format!("\"{}\"", model.as_str()));
+        }
+        assert_eq!(AmmModel::parse("orderBook"), None);
+    }
+
+    #[test]
+    fn test_display_values_concentrated_model() {
+        let base = |model| -> f64 {
+            let state = AppState {
+                model,
+                ..AppState::default()
+            };
+            let display = compute_display_values(&state).unwrap();
+            display
+                .get("initial-base-reserves")
+                .unwrap()
+                .parse()
+                .unwrap()
+        };
+        // The range ends the position's base at p_upper, so it holds less
+        assert!(base(AmmModel::Concentrated) < base(AmmModel::ConstantProduct));
+    }
+
+    #[test]
+    fn test_max_fee_for_roundtrip_budget_matches_round_trip() {
+        for budget in [0.0, 0.0005, 0.01, 0.2, 1.0] {
+            let fee = max_fee_for_roundtrip_budget(budget);
+            assert!(approx_eq(round_trip(fee), budget));
+        }
+        // 10 bps round trip allows just over 5 bps per leg
+        let fee = max_fee_for_roundtrip_budget(0.001);
+        assert!(fee > 0.0005 && fee - 0.0005 < 1e-6);
+    }
+
+    #[test]
+    fn test_round_trip_matches_tiny_swaps() {
+        let fee = max_fee_for_roundtrip_budget(0.006);
+        let state = CpmmState::new(1000.0, 1.0).unwrap();
+        let (quote_out, mid) = state.swap_exact_base_in(0.001, fee);
+        let (base_back, _) = mid.swap_exact_quote_in(quote_out, fee);
+        let cost = 1.0 - base_back / 0.001;
+        assert!((cost - round_trip(fee)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fee_split_sums_to_total() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let final_state = CpmmState::new(1000.0, 1.21).unwrap();
+        for share in [0.0, 0.25, 1.0] {
+            let result =
+                TradeResult::compute_split(initial, final_state, 0.003, share, FeeMode::OnInput);
+            assert!(approx_eq(
+                result.quote_lp_fee + result.quote_protocol_fee,
+                result.quote_fee_collected
+            ));
+            assert!(approx_eq(
+                result.quote_protocol_fee,
+                result.quote_fee_collected * share
+            ));
+            assert_eq!(result.base_lp_fee + result.base_protocol_fee, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_full_protocol_share_matches_compute() {
+        let initial = CpmmState::new(1000.0, 1.21).unwrap();
+        let final_state = CpmmState::new(1000.0, 1.0).unwrap();
+        let split = TradeResult::compute_split(initial, final_state, 0.003, 1.0, FeeMode::OnInput);
+        let whole = TradeResult::compute(initial, final_state, 0.003);
+        assert_eq!(split.base_fee_collected, whole.base_fee_collected);
+        assert_eq!(split.base_protocol_fee, whole.base_fee_collected);
+        assert_eq!(split.base_lp_fee, 0.0);
+        assert_eq!(split.base_wallet_delta_net, whole.base_wallet_delta_net);
+    }
+
+    #[test]
+    fn test_log_return_matches_ln_of_ratio() {
+        assert!(approx_eq(log_return(2.0, 5.0), 2.5f64.ln()));
+        assert!(approx_eq(log_return(1.0, std::f64::consts::E), 1.0));
+        // Reversing a move negates its log return
+        assert!(approx_eq(log_return(5.0, 2.0), -log_return(2.0, 5.0)));
+    }
+
+    #[test]
+    fn test_display_values_no_trade_is_exactly_zero() {
+        let state = AppState {
+            final_price: AppState::default().initial_price,
+            ..AppState::default()
+        };
+        let display = compute_display_values(&state).unwrap();
+        let zero = format_number(0.0, 6);
+        for id in [
+            "delta-price",
+            "delta-base-reserves",
+            "delta-quote-reserves",
+            "delta-base-net",
+            "delta-quote-net",
+            "fee-base-collected",
+            "fee-quote-collected",
+            "fee-base-lp",
+            "fee-quote-lp",
+            "fee-base-protocol",
+            "fee-quote-protocol",
+            "delta-price-change",
+            "delta-log-return",
+            "delta-log-drift",
+        ] {
+            assert_eq!(display.get(id), Some(zero.as_str()), "{}", id);
+        }
+        assert_eq!(
+            display.get("delta-execution-price"),
+            Some(UNDEFINED_DISPLAY)
+        );
+        assert_eq!(
+            display.get("delta-price-impact"),
+            Some(format_percent(0.0, 6).as_str())
+        );
+    }
+
+    #[test]
+    fn test_is_no_trade_tolerance() {
+        assert!(is_no_trade(1.1, 1.1));
+        assert!(is_no_trade(1.1, 1.1 * (1.0 + 1e-15)));
+        assert!(!is_no_trade(1.1, 1.1001));
+    }
+
+    #[test]
+    fn test_reserves_for_target_depth_reproduces_depth() {
+        for (depth, pct, price) in [(500.0, 0.01, 1.0), (20.0, 0.02, 2500.0), (1e6, 0.005, 0.3)] {
+            let (base, quote) = reserves_for_target_depth(depth, pct, price);
+            assert!(approx_eq(quote / base, price));
+            // Rebuild the pool from its reserves: L = sqrt(x * y)
+            let state = CpmmState::new((base * quote).sqrt(), price).unwrap();
+            assert!(((depth_within(state, pct) - depth) / depth).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_depth_within_one_percent() {
+        // About 1% of reserves on each side, so about 1% in total
+        let state = CpmmState::new(1000.0, 1.0).unwrap();
+        let depth = depth_within(state, 0.01);
+        assert!((depth - 10.0).abs() < 0.01);
+        // A wider band holds more depth
+        assert!(depth_within(state, 0.02) > depth);
+    }
+
+    #[test]
+    fn test_apply_lp_actions_keeps_price() {
+        let initial = CpmmState::new(1000.0, 1.7).unwrap();
+        let actions = [
+            LpAction::Add(500.0),
+            LpAction::Remove(1200.0),
+            LpAction::Add(0.125),
+            LpAction::Remove(100.0),
+            LpAction::Add(3000.0),
+        ];
+        let mut state = initial;
+        for action in actions {
+            state = apply_lp_actions(state, &[action]).unwrap();
+            assert_eq!(state.price, initial.price);
+            // Reserves keep the pool's ratio after every step
+            assert!(approx_eq(
+                state.quote_reserves() / state.base_reserves(),
+                initial.price
+            ));
+        }
+        let final_state = apply_lp_actions(initial, &actions).unwrap();
+        assert_eq!(final_state.price, initial.price);
+        assert!(approx_eq(final_state.liquidity, 3200.125));
+        assert_eq!(apply_lp_actions(initial, &[]).unwrap().liquidity, 1000.0);
+    }
+
+    #[test]
+    fn test_apply_lp_actions_rejects_draining_the_pool() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        assert_eq!(
+            apply_lp_actions(initial, &[LpAction::Remove(1000.0)]).unwrap_err(),
+            CpmmError::NonPositiveLiquidity
+        );
+    }
+
+    #[test]
+    fn test_nudge_value_relative_steps() {
+        assert!(approx_eq(nudge_value(100.0, 1, false), 101.0));
+        assert!(approx_eq(nudge_value(100.0, -1, false), 99.0));
+        assert!(approx_eq(nudge_value(100.0, 1, true), 110.0));
+        assert!(approx_eq(nudge_value(100.0, -1, true), 90.0));
+        // The same relative step at any magnitude
+        assert!(approx_eq(nudge_value(0.002, 1, false), 0.00202));
+        assert!(approx_eq(nudge_value(5e6, -1, false), 4.95e6));
+    }
+
+    #[test]
+    fn test_nudge_value_from_zero() {
+        assert!(approx_eq(nudge_value(0.0, 1, false), NUDGE_STEP));
+        assert!(approx_eq(nudge_value(0.0, 1, true), NUDGE_SHIFT_STEP));
+        assert!(approx_eq(nudge_value(0.0, -1, false), -NUDGE_STEP));
+    }
+
+    #[test]
+    fn test_apply_rebase_base_only() {
+        let state = CpmmState::new(1000.0, 2.0).unwrap();
+        // Doubling the base supply halves the price and scales L by sqrt(2)
+        let rebased = apply_rebase(state, 2.0, 1.0).unwrap();
+        assert!(approx_eq(rebased.price, 1.0));
+        assert!(approx_eq(rebased.liquidity, 1000.0 * 2.0f64.sqrt()));
+        assert!(approx_eq(
+            rebased.base_reserves(),
+            2.0 * state.base_reserves()
+        ));
+        assert!(approx_eq(rebased.quote_reserves(), state.quote_reserves()));
+        // A negative rebase raises the price
+        assert!(approx_eq(apply_rebase(state, 0.8, 1.0).unwrap().price, 2.5));
+    }
+
+    #[test]
+    fn test_apply_rebase_uniform_keeps_price() {
+        let state = CpmmState::new(1000.0, 2.0).unwrap();
+        let rebased = apply_rebase(state, 1.05, 1.05).unwrap();
+        assert!(approx_eq(rebased.price, 2.0));
+        assert!(approx_eq(rebased.liquidity, 1050.0));
+        assert!(apply_rebase(state, 0.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_displayed_invariant_is_reserve_product() {
+        let state = AppState {
+            initial_liquidity: 1500.0,
+            initial_price: 2.5,
+            final_liquidity: 900.0,
+            final_price: 0.4,
+            ..AppState::default()
+        };
+        let display = compute_display_values(&state).unwrap();
+        for (prefix, liquidity, price) in [("initial", 1500.0, 2.5), ("final", 900.0, 0.4)] {
+            let pool = CpmmState::new(liquidity, price).unwrap();
+            let shown: f64 = display
+                .get(&format!("{}-invariant", prefix))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let product = pool.base_reserves() * pool.quote_reserves();
+            assert!((shown - product).abs() < 1e-6 * product);
+            assert!((pool.invariant() - product).abs() < 1e-9 * product);
+        }
+    }
+
+    #[test]
+    fn test_implied_liquidity_recovers_impact_curve() {
+        let state = CpmmState::new(1000.0, 4.0).unwrap();
+        let sizes = [1.0, 10.0, 100.0];
+        for fee in [0.0, 0.003] {
+            let impacts = impact_curve(state, &sizes, fee);
+            for (&size, &impact) in sizes.iter().zip(&impacts) {
+                let liquidity = implied_liquidity(size, impact, state.price, fee);
+                assert!((liquidity - 1000.0).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_implied_liquidity_edges() {
+        // An impact at or below the fee needs unbounded depth
+        assert_eq!(implied_liquidity(10.0, 0.003, 1.0, 0.003), f64::INFINITY);
+        assert_eq!(implied_liquidity(10.0, 0.0, 1.0, 0.0), f64::INFINITY);
+        // A larger quoted impact implies a shallower pool
+        assert!(implied_liquidity(10.0, 0.05, 1.0, 0.0) < implied_liquidity(10.0, 0.01, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_slider_decades_scale_position() {
+        let narrow = AppState {
+            decades: 1.0,
+            ..AppState::default()
+        };
+        let wide = AppState {
+            decades: 4.0,
+            ..AppState::default()
+        };
+        // More decades pull a fixed off-center price toward the middle
+        let above = narrow.slider_for_price(5.0);
+        assert!(wide.slider_for_price(5.0) < above);
+        assert!(wide.slider_for_price(5.0) > 0.5);
+        let below = narrow.slider_for_price(0.2);
+        assert!(wide.slider_for_price(0.2) > below);
+        assert!(wide.slider_for_price(0.2) < 0.5);
+    }
+
+    #[test]
+    fn test_format_results_text() {
+        let state = AppState {
+            initial_liquidity: 1000.0,
+            final_liquidity: 1000.0,
+            initial_price: 1.0,
+            final_price: 1.21,
+            fee_percent: 0.0,
+            display_precision: 2,
+            ..AppState::default()
+        };
+        let result = model_outcome(&state).unwrap().trade;
+        let expected = "\
+Initial Price: 1.00
+Final Price: 1.21
+Initial Base Reserves: 1000.00
+Initial Quote Reserves: 1000.00
+Final Base Reserves: 909.09
+Final Quote Reserves: 1100.00
+Base Reserves Delta: 90.91
+Quote Reserves Delta: -100.00
+Base Net Delta: 90.91
+Quote Net Delta: -100.00
+Base Fee Collected: 0.00
+Quote Fee Collected: 0.00
+Base LP Fee: 0.00
+Quote LP Fee: 0.00
+Base Protocol Fee: 0.00
+Quote Protocol Fee: 0.00
+";
+        assert_eq!(format_results_text(&state, &result), expected);
+
+        // Inverted, prices flip and the base/quote labels swap
+        let inverted = AppState {
+            inverted: true,
+            ..state
+        };
+        let text = format_results_text(&inverted, &result);
+        assert!(text.starts_with("Initial Price: 1.00\nFinal Price: 0.83\n"));
+        assert!(text.contains("Initial Quote Reserves: 1000.00\nInitial Base Reserves: 1000.00\n"));
+    }
+
+    #[test]
+    fn test_mid_and_fee_from_quotes_roundtrip() {
+        for (price, fee) in [(1.0, 0.003), (2500.0, 0.0005), (0.04, 0.01)] {
+            let (bid, ask) = bid_ask(price, fee);
+            let (mid, implied_fee) = mid_and_fee_from_quotes(bid, ask);
+            assert!((mid - price).abs() < 1e-12 * price);
+            // First-order estimate: the error is of order fee^2
+            assert!((implied_fee - fee).abs() <= 2.0 * fee * fee);
+        }
+        assert_eq!(mid_and_fee_from_quotes(2.0, 2.0), (2.0, 0.0));
+    }
+
+    #[test]
+    fn test_session_totals_are_not_saved_or_validated() {
+        let mut state = AppState::default();
+        state.session.accrued_quote_lp_fees = 1.5;
+        state.session.sim_base_fees = 2.5;
+        state.session.cumulative_log_drift = -0.1;
+        let json = state.to_json();
+        assert!(!json.contains("accruedQuoteLpFees") && !json.contains("simBaseFees"));
+        assert!(!scenario_json(&state).contains("cumulativeLogDrift"));
+        // Restored and imported states start a fresh session
+        let restored = AppState::from_json(&json).unwrap();
+        assert_eq!(restored.session, SessionTotals::default());
+        let imported = parse_scenario(r#"{"accruedBaseLpFees": -1, "simQuoteFees": 3}"#).unwrap();
+        assert_eq!(imported.session, SessionTotals::default());
+        // Totals are results, so validation ignores them
+        state.session.accrued_base_lp_fees = f64::NAN;
+        assert!(state.validate().is_empty());
+    }
+
+    #[test]
+    fn test_toggle_protocol_fee_keeps_accrued_fees() {
+        let mut state = AppState {
+            final_price: 1.2,
+            protocol_fee_percent: 0.0,
+            ..AppState::default()
+        };
+        // Raising the price buys base with quote, so the fees are in quote
+        let before_toggle = model_outcome(&state).unwrap().trade;
+        accrue_fees(&mut state, &before_toggle);
+        let accrued_lp = (
+            state.session.accrued_base_lp_fees,
+            state.session.accrued_quote_lp_fees,
+        );
+        assert!(accrued_lp.1 > 0.0);
+        assert_eq!(state.session.accrued_quote_protocol_fees, 0.0);
+
+        toggle_protocol_fee(&mut state, 0.25);
+        assert_eq!(state.protocol_fee_percent, 25.0);
+        assert_eq!(
+            (
+                state.session.accrued_base_lp_fees,
+                state.session.accrued_quote_lp_fees
+            ),
+            accrued_lp
+        );
+        assert_eq!(state.session.accrued_quote_protocol_fees, 0.0);
+
+        // Only the next trade splits a quarter off to the protocol
+        let after_toggle = model_outcome(&state).unwrap().trade;
+        accrue_fees(&mut state, &after_toggle);
+        assert!(approx_eq(
+            state.session.accrued_quote_protocol_fees,
+            0.25 * after_toggle.quote_fee_collected
+        ));
+        assert!(approx_eq(
+            state.session.accrued_quote_lp_fees,
+            accrued_lp.1 + 0.75 * after_toggle.quote_fee_collected
+        ));
+    }
+
+    #[test]
+    fn test_scenario_json_default() {
+        let state = AppState::default();
+        let json: serde_json::Value = serde_json::from_str(&scenario_json(&state)).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["state"]["initialLiquidity"], 1000.0);
+        assert_eq!(json["state"]["finalPrice"], state.final_price);
+        assert_eq!(json["state"]["model"], "constantProduct");
+        let trade = model_outcome(&state).unwrap().trade;
+        for (key, value) in [
+            ("priceDelta", trade.price_delta),
+            ("baseWalletDelta", trade.base_wallet_delta),
+            ("quoteWalletDeltaNet", trade.quote_wallet_delta_net),
+            ("quoteFeeCollected", trade.quote_fee_collected),
+            ("executionPrice", trade.execution_price),
+        ] {
+            assert!(
+                approx_eq(json["trade"][key].as_f64().unwrap(), value),
+                "{}",
+                key
+            );
+        }
+
+        // An invalid state still exports, with a null trade
+        let invalid = AppState {
+            initial_liquidity: -1.0,
+            ..AppState::default()
+        };
+        let json: serde_json::Value = serde_json::from_str(&scenario_json(&invalid)).unwrap();
+        assert!(json["trade"].is_null());
+    }
+
+    #[test]
+    fn test_equivalent_full_range_liquidity_wide_range() {
+        // At the geometric center of [P / w, P * w], equivalent L = L * (1 - w^-1/2)
+        for width in [4.0, 100.0, 1e6] {
+            let position =
+                ConcentratedCpmmState::new(1000.0, 2.0, 2.0 / width, 2.0 * width).unwrap();
+            let equivalent = equivalent_full_range_liquidity(&position);
+            assert!((equivalent - 1000.0 * (1.0 - 1.0 / f64::sqrt(width))).abs() < 1e-6);
+            assert!(equivalent < 1000.0);
+        }
+        // Wider ranges approach the concentrated liquidity
+        let mut previous = 0.0;
+        for width in [10.0, 1e3, 1e6, 1e12] {
+            let position =
+                ConcentratedCpmmState::new(1000.0, 2.0, 2.0 / width, 2.0 * width).unwrap();
+            let equivalent = equivalent_full_range_liquidity(&position);
+            assert!(equivalent > previous);
+            previous = equivalent;
+        }
+        assert!(1000.0 - previous < 0.01);
+        // A full range is already a plain CPMM
+        let full = ConcentratedCpmmState::new(1000.0, 2.0, 0.0, f64::INFINITY).unwrap();
+        assert!(approx_eq(equivalent_full_range_liquidity(&full), 1000.0));
+    }
+
+    #[test]
+    fn test_parse_scenario_roundtrip() {
+        let state = AppState {
+            initial_liquidity: 2500.0,
+            final_price: 1.5,
+            fee_percent: 0.05,
+            decades: 2.0,
+            ..AppState::default()
+        };
+        let imported = parse_scenario(&scenario_json(&state)).unwrap();
+        assert_eq!(imported.initial_liquidity, 2500.0);
+        assert_eq!(imported.final_price, 1.5);
+        assert_eq!(imported.decades, 2.0);
+        // A bare state object works too
+        assert_eq!(parse_scenario(&state.to_json()), Some(state));
+    }
 
-    fn approx_eq(a: f64, b: f64) -> bool {
-        (a - b).abs() < EPSILON
+    #[test]
+    fn test_parse_scenario_missing_fields_use_defaults() {
+        let imported = parse_scenario(r#"{"state": {"finalPrice": 2.0}}"#).unwrap();
+        assert_eq!(imported.final_price, 2.0);
+        assert_eq!(
+            AppState {
+                final_price: AppState::default().final_price,
+                ..imported
+            },
+            AppState::default()
+        );
     }
 
     #[test]
-    fn test_cpmm_state_reserves() {
-        // L = 100, P = 4
-        // x = L / sqrt(P) = 100 / 2 = 50
-        // y = L * sqrt(P) = 100 * 2 = 200
-        let state = CpmmState::new(100.0, 4.0);
-        assert!(approx_eq(state.base_reserves(), 50.0));
-        assert!(approx_eq(state.quote_reserves(), 200.0));
+    fn test_parse_scenario_rejects_invalid() {
+        assert_eq!(parse_scenario("not json"), None);
+        assert_eq!(
+            parse_scenario(r#"{"state": {"initialLiquidity": 0}}"#),
+            None
+        );
+        assert_eq!(parse_scenario(r#"{"feePercent": 100}"#), None);
+        assert_eq!(parse_scenario(r#"{"state": {"decades": -1}}"#), None);
+        assert_eq!(parse_scenario(r#"{"state": {"initialPrice": "1"}}"#), None);
     }
 
     #[test]
-    fn test_cpmm_invariant() {
-        // k = L^2 = x * y
-        let state = CpmmState::new(100.0, 4.0);
-        let k = state.invariant();
-        let xy = state.base_reserves() * state.quote_reserves();
-        assert!(approx_eq(k, xy));
-        assert!(approx_eq(k, 10000.0));
+    fn test_swap_exact_base_in_concentrated_in_range() {
+        let pos = ConcentratedCpmmState::new(1000.0, 4.0, 1.0, 9.0).unwrap();
+        let (next, quote_out, unfilled) = swap_exact_base_in_concentrated(pos, 50.0, 0.003);
+        // Inside the range it trades like the full-range pool with the same L
+        let (cpmm_out, cpmm_next) = CpmmState::new(1000.0, 4.0)
+            .unwrap()
+            .swap_exact_base_in(50.0, 0.003);
+        assert!(approx_eq(quote_out, cpmm_out));
+        assert!(approx_eq(next.price, cpmm_next.price));
+        assert_eq!(unfilled, 0.0);
+        assert!(approx_eq(
+            pos.quote_reserves() - next.quote_reserves(),
+            quote_out
+        ));
     }
 
     #[test]
-    fn test_price_from_reserves() {
-        // P = y / x
-        let state = CpmmState::new(100.0, 4.0);
-        let computed_price = state.quote_reserves() / state.base_reserves();
-        assert!(approx_eq(computed_price, state.price));
+    fn test_swap_exact_base_in_concentrated_exits_range() {
+        let pos = ConcentratedCpmmState::new(1000.0, 4.0, 1.0, 9.0).unwrap();
+        // 500 base (after fees) drains the range: L * (1/sqrt(1) - 1/sqrt(4)) = 500
+        let fee = 0.003;
+        let (next, quote_out, unfilled) = swap_exact_base_in_concentrated(pos, 800.0, fee);
+        assert!(approx_eq(next.price, 1.0));
+        assert!(approx_eq(quote_out, pos.quote_reserves()));
+        assert!(approx_eq(unfilled, 800.0 - 500.0 / (1.0 - fee)));
+        assert!(approx_eq(next.quote_reserves(), 0.0));
+        // Below the range nothing fills
+        let (_, quote_out, unfilled) = swap_exact_base_in_concentrated(next, 10.0, fee);
+        assert_eq!(quote_out, 0.0);
+        assert!(approx_eq(unfilled, 10.0));
     }
 
     #[test]
-    fn test_trade_result_buy_base() {
-        // Initial: L=1000, P=1.0 => x=1000, y=1000
-        // Final: L=1000, P=1.21 => x=909.09, y=1100
-        // Trader buys base: receives ~90.91 base, pays ~100 quote
-        let initial = CpmmState::new(1000.0, 1.0);
-        let final_state = CpmmState::new(1000.0, 1.21);
-        let result = TradeResult::compute(initial, final_state, 0.003);
+    fn test_swap_across_positions_matches_single_swaps() {
+        let positions = [
+            ConcentratedCpmmState::new(1000.0, 4.0, 2.0, 8.0).unwrap(),
+            ConcentratedCpmmState::new(500.0, 4.0, 3.0, 5.0).unwrap(),
+            // Below the price: only trades once the price falls to 1.5
+            ConcentratedCpmmState::new(2000.0, 4.0, 0.5, 1.5).unwrap(),
+            // Above the price: never trades on a sale
+            ConcentratedCpmmState::new(800.0, 4.0, 6.0, 9.0).unwrap(),
+        ];
+        let fee = 0.003;
+        for base_in in [10.0, 200.0, 1000.0] {
+            let (quote_out, per_position) = swap_across_positions(&positions, base_in, fee);
+            assert!((per_position.iter().sum::<f64>() - base_in).abs() < 1e-9);
+            assert_eq!(per_position[3], 0.0);
+            let summed: f64 = positions
+                .iter()
+                .zip(&per_position)
+                .map(|(&pos, &taken)| swap_exact_base_in_concentrated(pos, taken, fee).1)
+                .sum();
+            assert!((quote_out - summed).abs() < 1e-9);
+        }
+        // Only the large sale crosses the gap into the lowest range
+        assert_eq!(swap_across_positions(&positions, 200.0, fee).1[2], 0.0);
+        assert!(swap_across_positions(&positions, 1000.0, fee).1[2] > 0.0);
+    }
 
-        assert!(result.base_wallet_delta > 0.0); // Trader receives base
-        assert!(result.quote_wallet_delta < 0.0); // Trader pays quote
-        assert!(result.quote_fee_collected > 0.0); // Fee on quote input
-        assert!(approx_eq(result.base_fee_collected, 0.0)); // No fee on base
+    #[test]
+    fn test_swap_across_positions_exhausts_ranges() {
+        let positions = [ConcentratedCpmmState::new(1000.0, 4.0, 1.0, 9.0).unwrap()];
+        let (quote_out, per_position) = swap_across_positions(&positions, 800.0, 0.0);
+        // The range absorbs 500 base before the price reaches p_lower
+        assert!(approx_eq(per_position[0], 500.0));
+        assert!(approx_eq(quote_out, positions[0].quote_reserves()));
+        assert_eq!(swap_across_positions(&[], 10.0, 0.0), (0.0, vec![]));
     }
 
     #[test]
-    fn test_trade_result_sell_base() {
-        // Price decreases: trader sells base for quote
-        let initial = CpmmState::new(1000.0, 1.0);
-        let final_state = CpmmState::new(1000.0, 0.81);
-        let result = TradeResult::compute(initial, final_state, 0.003);
+    fn test_weighted_pool_even_weights_match_cpmm() {
+        for (liquidity, price) in [(1000.0, 1.0), (250.0, 4.0), (5000.0, 0.01)] {
+            let weighted = WeightedPoolState::new(liquidity, price, 0.5, 0.5).unwrap();
+            let cpmm = CpmmState::new(liquidity, price).unwrap();
+            assert!((weighted.base_reserves() - cpmm.base_reserves()).abs() < 1e-9 * liquidity);
+            assert!((weighted.quote_reserves() - cpmm.quote_reserves()).abs() < 1e-9 * liquidity);
+            let (weighted_out, weighted_next) = weighted.swap_exact_base_in(10.0, 0.003);
+            let (cpmm_out, cpmm_next) = cpmm.swap_exact_base_in(10.0, 0.003);
+            assert!((weighted_out - cpmm_out).abs() < 1e-9 * liquidity);
+            assert!((weighted_next.price - cpmm_next.price).abs() < 1e-9 * price);
+        }
+        // The display matches the constant-product model too
+        let cpmm_state = AppState {
+            final_price: 1.3,
+            ..AppState::default()
+        };
+        let weighted_state = AppState {
+            model: AmmModel::Weighted,
+            ..cpmm_state.clone()
+        };
+        assert_eq!(
+            compute_display_values(&weighted_state)
+                .unwrap()
+                .get("delta-base-net"),
+            compute_display_values(&cpmm_state)
+                .unwrap()
+                .get("delta-base-net")
+        );
+    }
 
-        assert!(result.base_wallet_delta < 0.0); // Trader pays base
-        assert!(result.quote_wallet_delta > 0.0); // Trader receives quote
-        assert!(result.base_fee_collected > 0.0); // Fee on base input
-        assert!(approx_eq(result.quote_fee_collected, 0.0)); // No fee on quote
+    #[test]
+    fn test_weighted_pool_uneven_weights_change_slippage() {
+        let even = (
+            WeightedPoolState::new(1000.0, 1.0, 0.5, 0.5).unwrap(),
+            WeightedPoolState::new(1000.0, 1.1, 0.5, 0.5).unwrap(),
+        );
+        let skewed = (
+            WeightedPoolState::new(1000.0, 1.0, 0.8, 0.2).unwrap(),
+            WeightedPoolState::new(1000.0, 1.1, 0.8, 0.2).unwrap(),
+        );
+        // Spot price follows the weights: P = (y / w_quote) / (x / w_base)
+        let pool = skewed.0;
+        let spot = (pool.quote_reserves() / 0.2) / (pool.base_reserves() / 0.8);
+        assert!(approx_eq(spot, 1.0));
+        // The swap lands on the spot price formula
+        let (_, next) = pool.swap_exact_base_in(50.0, 0.0);
+        let next_spot = (next.quote_reserves() / 0.2) / (next.base_reserves() / 0.8);
+        assert!(approx_eq(next.price, next_spot));
+
+        let even_trade = TradeResult::compute(even.0, even.1, 0.0);
+        let skewed_trade = TradeResult::compute(skewed.0, skewed.1, 0.0);
+        // The same move fills a much smaller trade at a different execution price
+        assert!(skewed_trade.base_wallet_delta < 0.6 * even_trade.base_wallet_delta);
+        assert!((even_trade.price_impact - skewed_trade.price_impact).abs() > 1e-4);
+
+        assert!(WeightedPoolState::new(1000.0, 1.0, 0.7, 0.2).is_err());
+        assert!(WeightedPoolState::new(1000.0, 1.0, 1.0, 0.0).is_err());
+        let invalid = AppState {
+            weight_base: 0.6,
+            ..AppState::default()
+        };
+        assert!(!invalid.validate().is_empty());
     }
 
     #[test]
-    fn test_slider_price_conversion_roundtrip() {
-        let center = 1.0;
-        let decades = 3.0;
-        let prices = [0.001, 0.1, 1.0, 10.0, 100.0, 1000.0];
+    fn test_skewed_quotes_long_inventory_lowers_quotes() {
+        let (neutral_bid, neutral_ask) = skewed_quotes(100.0, 0.003, 0.0, 0.5);
+        assert!(approx_eq(neutral_bid, 99.7));
+        assert!(approx_eq(neutral_ask, 100.3));
 
-        for &price in &prices {
-            let slider = price_to_slider(price, center, decades);
-            let recovered = slider_to_price(slider, center, decades);
-            assert!(
-                (price - recovered).abs() / price < 0.001,
-                "Roundtrip failed for price {}",
-                price
+        let (long_bid, long_ask) = skewed_quotes(100.0, 0.003, 0.8, 0.5);
+        assert!(long_bid < neutral_bid);
+        assert!(long_ask < neutral_ask);
+        // Skewing shifts the quotes but keeps the total spread
+        assert!(approx_eq(long_ask - long_bid, neutral_ask - neutral_bid));
+
+        let (short_bid, short_ask) = skewed_quotes(100.0, 0.003, -0.8, 0.5);
+        assert!(short_bid > neutral_bid);
+        assert!(short_ask > neutral_ask);
+    }
+
+    #[test]
+    fn test_impact_is_high() {
+        assert!(!impact_is_high(0.01, 0.05));
+        assert!(impact_is_high(0.06, 0.05));
+        // Sells have negative impact; only the magnitude counts
+        assert!(impact_is_high(-0.06, 0.05));
+        // Exactly at the threshold does not warn
+        assert!(!impact_is_high(0.05, 0.05));
+        assert!(!impact_is_high(-0.05, 0.05));
+        assert!(!impact_is_high(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_trade_to_liquidation_reaches_price_at_a_cost() {
+        let initial = CpmmState::new(10_000.0, 2000.0).unwrap();
+        // Pushing the price down (selling base) and up (buying base) both cost
+        for liquidation_price in [1500.0, 2600.0] {
+            let result = trade_to_liquidation(initial, liquidation_price, 0.003).unwrap();
+            assert!(approx_eq(
+                initial.price + result.price_delta,
+                liquidation_price
+            ));
+            assert!(manipulation_cost(&result, initial.price) > 0.0);
+        }
+        // A deeper pool is costlier to push the same distance
+        let shallow = trade_to_liquidation(initial, 1500.0, 0.003).unwrap();
+        let deep_pool = CpmmState::new(40_000.0, 2000.0).unwrap();
+        let deep = trade_to_liquidation(deep_pool, 1500.0, 0.003).unwrap();
+        assert!(manipulation_cost(&deep, 2000.0) > manipulation_cost(&shallow, 2000.0));
+        assert!(trade_to_liquidation(initial, 0.0, 0.003).is_err());
+    }
+
+    #[test]
+    fn test_curve_points_on_invariant() {
+        let k = 1_000_000.0;
+        let points = curve_points(k, 250.0, 4000.0, 32);
+        assert_eq!(points.len(), 32);
+        assert!(approx_eq(points[0].0, 250.0));
+        assert!(approx_eq(points[31].0, 4000.0));
+        for (x, y) in &points {
+            assert!((x * y - k).abs() < 1e-9 * k);
+        }
+        // x rises and y falls along the curve
+        for pair in points.windows(2) {
+            assert!(pair[1].0 > pair[0].0);
+            assert!(pair[1].1 < pair[0].1);
+        }
+        assert_eq!(curve_points(k, 100.0, 200.0, 1), vec![(100.0, 10_000.0)]);
+        assert!(curve_points(k, 100.0, 200.0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_resolve_theme() {
+        for system_dark in [false, true] {
+            assert_eq!(resolve_theme(Theme::Auto, system_dark), system_dark);
+            assert!(!resolve_theme(Theme::Light, system_dark));
+            assert!(resolve_theme(Theme::Dark, system_dark));
+        }
+        // The toggle cycles through all three
+        assert_eq!(Theme::Auto.next().next().next(), Theme::Auto);
+        assert_eq!(Theme::Auto.next(), Theme::Light);
+    }
+
+    #[test]
+    fn test_validate_fee() {
+        assert!(validate_fee(-1.0).is_err());
+        assert_eq!(validate_fee(0.0), Ok(0.0));
+        assert_eq!(validate_fee(99.9), Ok(99.9));
+        assert!(validate_fee(100.0).is_err());
+        assert!(validate_fee(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_simulate_fees_fixed_moves() {
+        let start = CpmmState::new(1000.0, 1.0).unwrap();
+        let moves = [1.1, 1.0 / 1.1, 0.9];
+        let (base_fees, quote_fees) = simulate_fees(start, &moves, 0.003);
+
+        // Up, back down, then down again: one quote-in and two base-in trades
+        let up = CpmmState::new(1000.0, 1.1).unwrap();
+        let down = CpmmState::new(1000.0, 0.9).unwrap();
+        let first = TradeResult::compute(start, up, 0.003);
+        let second = TradeResult::compute(up, start, 0.003);
+        let third = TradeResult::compute(start, down, 0.003);
+        assert!(approx_eq(quote_fees, first.quote_fee_collected));
+        assert!(approx_eq(
+            base_fees,
+            second.base_fee_collected + third.base_fee_collected
+        ));
+        assert!(base_fees > 0.0 && quote_fees > 0.0);
+    }
+
+    #[test]
+    fn test_simulate_fees_no_moves_or_fee() {
+        let start = CpmmState::new(1000.0, 1.0).unwrap();
+        assert_eq!(simulate_fees(start, &[], 0.003), (0.0, 0.0));
+        let moves = random_walk_moves(7, 50, SIMULATION_STEP_VOL);
+        assert_eq!(simulate_fees(start, &moves, 0.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_random_walk_moves_reproducible_from_seed() {
+        let a = random_walk_moves(42, 20, SIMULATION_STEP_VOL);
+        assert_eq!(a.len(), 20);
+        assert_eq!(a, random_walk_moves(42, 20, SIMULATION_STEP_VOL));
+        assert_ne!(a, random_walk_moves(43, 20, SIMULATION_STEP_VOL));
+        assert!(a.iter().all(|m| *m > 0.0));
+    }
+
+    #[test]
+    fn test_impermanent_loss_double_price() {
+        // 2 * sqrt(2) / 3 - 1 = -5.719%
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let doubled = CpmmState::new(1000.0, 2.0).unwrap();
+        let il = impermanent_loss(initial, doubled);
+        assert!(approx_eq(il, 2.0 * 2.0_f64.sqrt() / 3.0 - 1.0));
+        assert!((il + 0.0572).abs() < 1e-4);
+        // Halving the price loses the same fraction
+        let halved = CpmmState::new(1000.0, 0.5).unwrap();
+        assert!(approx_eq(impermanent_loss(initial, halved), il));
+    }
+
+    #[test]
+    fn test_impermanent_loss_no_move_and_sign() {
+        let initial = CpmmState::new(1000.0, 3.0).unwrap();
+        assert_eq!(impermanent_loss(initial, initial), 0.0);
+        for price in [0.01, 0.5, 2.9, 3.1, 10.0, 1e6] {
+            let moved = CpmmState::new(1000.0, price).unwrap();
+            assert!(impermanent_loss(initial, moved) <= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_amount_entry_round_trip() {
+        let initial = CpmmState::new(1000.0, 2.0).unwrap();
+        for mode in [EntryMode::BaseIn, EntryMode::QuoteIn] {
+            let price = final_price_for_amount(initial, mode, 50.0, 0.003).unwrap();
+            let amount = amount_for_final_price(initial, mode, price, 0.003).unwrap();
+            assert!((amount - 50.0).abs() < 1e-9);
+        }
+        // Base in lowers the price, quote in raises it
+        let base_in = final_price_for_amount(initial, EntryMode::BaseIn, 50.0, 0.003).unwrap();
+        let quote_in = final_price_for_amount(initial, EntryMode::QuoteIn, 50.0, 0.003).unwrap();
+        assert!(base_in < 2.0 && quote_in > 2.0);
+    }
+
+    #[test]
+    fn test_amount_entry_wrong_direction_or_price_mode() {
+        let initial = CpmmState::new(1000.0, 2.0).unwrap();
+        assert!(amount_for_final_price(initial, EntryMode::BaseIn, 2.5, 0.003).is_none());
+        assert!(amount_for_final_price(initial, EntryMode::QuoteIn, 1.5, 0.003).is_none());
+        assert!(amount_for_final_price(initial, EntryMode::Price, 1.5, 0.003).is_none());
+        assert!(final_price_for_amount(initial, EntryMode::Price, 50.0, 0.003).is_none());
+    }
+
+    #[test]
+    fn test_switching_entry_modes_does_not_drift() {
+        let mut state = AppState {
+            final_price: 0.9,
+            ..AppState::default()
+        };
+        for _ in 0..10 {
+            state.entry_mode = EntryMode::BaseIn;
+            state.trade_amount = state.trade_amount_for_final_price().unwrap();
+            state.final_price = state.final_price_for_trade_amount().unwrap();
+            state.entry_mode = EntryMode::Price;
+        }
+        assert!((state.final_price - 0.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_initial_price_change_keeps_amount_entry_consistent() {
+        let mut state = AppState {
+            entry_mode: EntryMode::QuoteIn,
+            trade_amount: 50.0,
+            ..AppState::default()
+        };
+        state.final_price = state.final_price_for_trade_amount().unwrap();
+        state.initial_price = 2.0;
+        state.final_price = state.final_price_for_trade_amount().unwrap();
+        assert!(state.final_price > 2.0);
+        let amount = state.trade_amount_for_final_price().unwrap();
+        assert!((amount - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_amount_entry_follows_orientation() {
+        // Displayed base in, when inverted, is internal quote in
+        let state = AppState {
+            inverted: true,
+            entry_mode: EntryMode::BaseIn,
+            ..AppState::default()
+        };
+        assert!(state.final_price_for_trade_amount().unwrap() > state.initial_price);
+    }
+
+    #[test]
+    fn test_apply_slippage_tolerance_directions() {
+        // 0.5% off an output, 0.5% on top of an input
+        assert!(approx_eq(
+            apply_slippage_tolerance(200.0, 0.005, true),
+            199.0
+        ));
+        assert!(approx_eq(
+            apply_slippage_tolerance(200.0, 0.005, false),
+            201.0
+        ));
+    }
+
+    #[test]
+    fn test_apply_slippage_tolerance_bounds() {
+        assert_eq!(apply_slippage_tolerance(200.0, 0.0, true), 200.0);
+        assert_eq!(apply_slippage_tolerance(200.0, 0.0, false), 200.0);
+        // Out-of-range tolerances are clamped to [0, 100%]
+        assert_eq!(apply_slippage_tolerance(200.0, -0.1, true), 200.0);
+        assert_eq!(apply_slippage_tolerance(200.0, 1.5, true), 0.0);
+        assert_eq!(apply_slippage_tolerance(200.0, 1.5, false), 400.0);
+    }
+
+    #[test]
+    fn test_display_values_worst_case_amounts() {
+        let state = AppState::default();
+        let values = compute_display_values(&state).unwrap();
+        let result = model_outcome(&state).unwrap().trade;
+        // Price rises: quote is sent and base received
+        assert_eq!(
+            values.get("delta-min-received").unwrap(),
+            state.display(result.base_wallet_delta_net * 0.995)
+        );
+        assert_eq!(
+            values.get("delta-max-sent").unwrap(),
+            state.display(-result.quote_wallet_delta_net * 1.005)
+        );
+    }
+
+    #[test]
+    fn test_slider_step_keeps_resolution() {
+        // One step moves the price by the same factor at any range
+        for decades in [1.0, 3.0, 6.0] {
+            let step = slider_step_for_decades(decades);
+            let moved = slider_to_price(0.5 + step, 1.0, decades);
+            assert!((moved.log10() - SLIDER_STEP_DECADES).abs() < 1e-12);
+        }
+        assert!(slider_step_for_decades(6.0) < slider_step_for_decades(3.0));
+        assert!((slider_step_for_decades(3.0) - 0.001).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_one_slider_step_passes_jitter_guard() {
+        for decades in [3.0, 6.0, 10.0] {
+            let state = AppState {
+                decades,
+                ..AppState::default()
+            };
+            let step = slider_step_for_decades(decades);
+            let previous = state.slider_for_price(1.234);
+            let threshold = state.slider_jitter_threshold();
+            assert!(slider_delta_exceeds_min(
+                previous,
+                previous + step,
+                threshold
+            ));
+            assert!(slider_delta_exceeds_min(
+                previous,
+                previous - step,
+                threshold
+            ));
+        }
+        // The fixed default alone would drop a single step at 10 decades
+        let step = slider_step_for_decades(10.0);
+        assert!(!slider_delta_exceeds_min(0.5, 0.5 + step, 0.0005));
+    }
+
+    #[test]
+    fn test_slider_step_clamped() {
+        assert_eq!(slider_step_for_decades(1e-9), MAX_SLIDER_STEP);
+        assert_eq!(slider_step_for_decades(1e9), MIN_SLIDER_STEP);
+    }
+
+    #[test]
+    fn test_tick_round_trip_within_spacing() {
+        for price in [1e-6, 0.01, 0.5, 1.0, 1.00005, 2.0, 1234.5, 1e6] {
+            let tick = price_to_tick(price);
+            let back = tick_to_price(tick);
+            // Nearest tick: at most half a tick away
+            assert!((back / price).ln().abs() <= TICK_BASE.ln() / 2.0 + 1e-12);
+            for spacing in [1, 10, 60, 200] {
+                let snapped = tick_to_price(nearest_valid_tick(price, spacing));
+                assert!((snapped / price).ln().abs() <= TICK_BASE.ln() * spacing as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tick_boundaries() {
+        assert_eq!(price_to_tick(1.0), 0);
+        assert_eq!(price_to_tick(tick_to_price(-120)), -120);
+        // Just either side of the midpoint between ticks 0 and 1
+        let mid = TICK_BASE.sqrt();
+        assert_eq!(price_to_tick(mid * 0.999_999), 0);
+        assert_eq!(price_to_tick(mid * 1.000_001), 1);
+        // Spacing 60: tick 29 snaps down, tick 31 up
+        assert_eq!(nearest_valid_tick(tick_to_price(29), 60), 0);
+        assert_eq!(nearest_valid_tick(tick_to_price(31), 60), 60);
+        assert_eq!(nearest_valid_tick(tick_to_price(-31), 60), -60);
+        // Out-of-range prices clamp to the ends of the tick range
+        assert_eq!(price_to_tick(f64::MAX), MAX_TICK);
+        assert_eq!(price_to_tick(0.0), -MAX_TICK);
+        assert!(nearest_valid_tick(f64::MAX, 60) <= MAX_TICK);
+    }
+
+    #[test]
+    fn test_snap_to_tick_only_in_tick_mode() {
+        let mut state = AppState {
+            final_price: 1.1,
+            ..AppState::default()
+        };
+        state.snap_prices_to_ticks();
+        assert_eq!(state.final_price, 1.1);
+        state.tick_mode = true;
+        state.snap_prices_to_ticks();
+        let tick = price_to_tick(state.final_price);
+        assert_eq!(tick % 60, 0);
+        assert_eq!(state.final_price, tick_to_price(tick));
+    }
+
+    #[test]
+    fn test_fee_on_output_buy_base() {
+        // Price rises: the trader buys base with quote
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let final_state = CpmmState::new(1000.0, 1.21).unwrap();
+        let on_input =
+            TradeResult::compute_split(initial, final_state, 0.003, 1.0, FeeMode::OnInput);
+        let on_output =
+            TradeResult::compute_split(initial, final_state, 0.003, 1.0, FeeMode::OnOutput);
+        assert_eq!(on_input.base_fee_collected, 0.0);
+        assert!(on_input.quote_fee_collected > 0.0);
+        assert_eq!(on_output.quote_fee_collected, 0.0);
+        assert!(approx_eq(
+            on_output.base_fee_collected,
+            on_output.base_wallet_delta * 0.003
+        ));
+        // The whole input reaches the pool; the base received is reduced
+        assert_eq!(
+            on_output.quote_wallet_delta_net,
+            on_output.quote_wallet_delta
+        );
+        assert!(on_output.base_wallet_delta_net < on_output.base_wallet_delta);
+    }
+
+    #[test]
+    fn test_fee_on_output_sell_base() {
+        let initial = CpmmState::new(1000.0, 1.21).unwrap();
+        let final_state = CpmmState::new(1000.0, 1.0).unwrap();
+        let on_output =
+            TradeResult::compute_split(initial, final_state, 0.003, 1.0, FeeMode::OnOutput);
+        assert_eq!(on_output.base_fee_collected, 0.0);
+        assert!(approx_eq(
+            on_output.quote_fee_collected,
+            on_output.quote_wallet_delta * 0.003
+        ));
+    }
+
+    #[test]
+    fn test_fee_modes_no_trade_collect_nothing() {
+        let pool = CpmmState::new(1000.0, 1.0).unwrap();
+        for mode in [FeeMode::OnInput, FeeMode::OnOutput] {
+            let result = TradeResult::compute_split(pool, pool, 0.003, 1.0, mode);
+            assert_eq!(result.base_fee_collected, 0.0);
+            assert_eq!(result.quote_fee_collected, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_history_undo_redo() {
+        let mut history = History::new(1, HISTORY_DEPTH);
+        assert_eq!(history.undo(), None);
+        history.push(2);
+        history.push(3);
+        assert_eq!(history.undo(), Some(&2));
+        assert_eq!(history.undo(), Some(&1));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), Some(&2));
+        assert_eq!(history.redo(), Some(&3));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn test_history_new_edit_clears_redo() {
+        let mut history = History::new(1, HISTORY_DEPTH);
+        history.push(2);
+        history.undo();
+        assert!(history.can_redo());
+        history.push(5);
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(&1));
+    }
+
+    #[test]
+    fn test_history_ignores_unchanged_value() {
+        let mut history = History::new(1, HISTORY_DEPTH);
+        history.push(2);
+        history.undo();
+        // Recommitting the restored value keeps the redo step
+        history.push(1);
+        assert!(!history.can_undo());
+        assert_eq!(history.redo(), Some(&2));
+    }
+
+    #[test]
+    fn test_history_depth_is_capped() {
+        let mut history = History::new(0, 3);
+        for value in 1..=10 {
+            history.push(value);
+        }
+        assert_eq!(history.undo(), Some(&9));
+        assert_eq!(history.undo(), Some(&8));
+        assert_eq!(history.undo(), Some(&7));
+        assert_eq!(history.undo(), None);
+    }
+
+    #[test]
+    fn test_format_percent() {
+        assert_eq!(format_percent(0.003, 3), "0.300%");
+        assert_eq!(format_percent(-0.025, 2), "-2.50%");
+        // Near-zero values switch to scientific notation like format_number
+        assert_eq!(format_percent(1e-9, 2), "1.00e-7%");
+        assert_eq!(format_percent(0.0, 2), "0.00%");
+    }
+
+    #[test]
+    fn test_parse_user_percent_accepts_optional_sign() {
+        assert_eq!(parse_user_percent("0.3%"), Some(0.3));
+        assert_eq!(parse_user_percent(" 0.3 "), Some(0.3));
+        assert_eq!(parse_user_percent("%"), None);
+    }
+
+    #[test]
+    fn test_max_trade_for_impact_reaches_impact() {
+        let pool = CpmmState::new(1000.0, 2.0).unwrap();
+        let fee = 0.003;
+        for max_impact in [0.01, 0.05, 0.5] {
+            // The fee is paid on top of what reaches the pool
+            let base_in = max_base_in_for_impact(pool, max_impact, fee, FeeMode::OnInput);
+            let (_, sold) = pool.swap_exact_base_in(base_in / (1.0 + fee), 0.0);
+            let impact = TradeResult::compute(pool, sold, fee).price_impact;
+            assert!((impact + max_impact).abs() < 1e-9, "{}", impact);
+
+            let quote_in = max_quote_in_for_impact(pool, max_impact, fee, FeeMode::OnInput);
+            let (_, bought) = pool.swap_exact_quote_in(quote_in / (1.0 + fee), 0.0);
+            let impact = TradeResult::compute(pool, bought, fee).price_impact;
+            assert!((impact - max_impact).abs() < 1e-9, "{}", impact);
+        }
+    }
+
+    #[test]
+    fn test_max_trade_for_impact_edge_cases() {
+        let pool = CpmmState::new(1000.0, 2.0).unwrap();
+        assert_eq!(
+            max_base_in_for_impact(pool, 0.0, 0.003, FeeMode::OnInput),
+            0.0
+        );
+        assert_eq!(
+            max_quote_in_for_impact(pool, 0.0, 0.003, FeeMode::OnInput),
+            0.0
+        );
+        assert_eq!(
+            max_base_in_for_impact(pool, 0.0, 0.0, FeeMode::OnInput),
+            0.0
+        );
+        // The fee alone already moves the execution price further
+        assert_eq!(
+            max_base_in_for_impact(pool, 0.002, 0.003, FeeMode::OnInput),
+            0.0
+        );
+        // No base input reaches a 100% impact
+        assert_eq!(
+            max_base_in_for_impact(pool, 1.0, 0.003, FeeMode::OnInput),
+            f64::INFINITY
+        );
+        // Huge impacts buy nearly, but never all, of the base reserves
+        let quote_in = max_quote_in_for_impact(pool, 1e6, 0.003, FeeMode::OnInput);
+        let (base_out, _) = pool.swap_exact_quote_in(quote_in, 0.003);
+        assert!(base_out < pool.base_reserves());
+        assert!(base_out > 0.99 * pool.base_reserves());
+    }
+
+    #[test]
+    fn test_max_trades_for_impact_fee_on_output() {
+        let state = AppState {
+            fee_mode: FeeMode::OnOutput,
+            max_impact_percent: 2.0,
+            ..AppState::default()
+        };
+        let pool = state.amount_pool().unwrap();
+        let fee = state.fee_percent / 100.0;
+        let (base_in, _) = state.max_trades_for_impact().unwrap();
+        let (_, sold) = pool.swap_exact_base_in(base_in, 0.0);
+        let trade = TradeResult::compute_split(pool, sold, fee, 1.0, FeeMode::OnOutput);
+        assert!((trade.price_impact + 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_route_two_hop_zero_fee_matches_composed_swaps() {
+        let pool_ab = CpmmState::new(1000.0, 2.0).unwrap();
+        let pool_bc = CpmmState::new(500.0, 0.5).unwrap();
+        let (quote_out, next_ab, next_bc) = route_two_hop(pool_ab, pool_bc, 25.0, 0.0);
+        let (intermediate, ab) = pool_ab.swap_exact_base_in(25.0, 0.0);
+        let (expected, bc) = pool_bc.swap_exact_base_in(intermediate, 0.0);
+        assert_eq!(quote_out, expected);
+        assert_eq!(next_ab.price, ab.price);
+        assert_eq!(next_bc.price, bc.price);
+        // Both pools keep their invariants
+        assert!(approx_eq(next_ab.liquidity, pool_ab.liquidity));
+        assert!(approx_eq(next_bc.liquidity, pool_bc.liquidity));
+    }
+
+    #[test]
+    fn test_route_two_hop_degrades_with_fee() {
+        let pool_ab = CpmmState::new(1000.0, 2.0).unwrap();
+        let pool_bc = CpmmState::new(500.0, 0.5).unwrap();
+        let outputs: Vec<f64> = [0.0, 0.003, 0.01]
+            .iter()
+            .map(|&fee| route_two_hop(pool_ab, pool_bc, 25.0, fee).0)
+            .collect();
+        assert!(outputs[0] > outputs[1] && outputs[1] > outputs[2]);
+        // The fee is paid on both hops, so it costs more than on a single hop
+        let (single_free, _) = pool_ab.swap_exact_base_in(25.0, 0.0);
+        let (single_fee, _) = pool_ab.swap_exact_base_in(25.0, 0.01);
+        assert!(outputs[2] / outputs[0] < single_fee / single_free);
+    }
+
+    #[test]
+    fn test_sweep_csv_layout() {
+        let initial = CpmmState::new(1000.0, 2.0).unwrap();
+        let csv = sweep_csv(initial, 3.0, 2.0, 0.003, 10);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], SWEEP_CSV_HEADER);
+        assert_eq!(lines.len(), 1 + 11);
+        let columns = SWEEP_CSV_HEADER.split(',').count();
+        assert!(lines.iter().all(|line| line.split(',').count() == columns));
+
+        // The middle row is the center price; the ends span the decades
+        let price = |line: &str| -> f64 { line.split(',').next().unwrap().parse().unwrap() };
+        assert!(approx_eq(price(lines[6]), 3.0));
+        assert!((price(lines[1]) - 0.03).abs() < 1e-12);
+        assert!((price(lines[11]) - 300.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sweep_csv_rows_match_trades() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        let csv = sweep_csv(initial, 1.0, 1.0, 0.003, 2);
+        // At the initial price nothing is traded
+        let middle: Vec<f64> = csv
+            .lines()
+            .nth(2)
+            .unwrap()
+            .split(',')
+            .map(|value| value.parse().unwrap())
+            .collect();
+        assert!(approx_eq(middle[1], initial.base_reserves()));
+        assert_eq!(&middle[3..], &[0.0; 4]);
+        // Zero steps still yield one row, at the center
+        assert_eq!(sweep_csv(initial, 1.0, 1.0, 0.003, 0).lines().count(), 2);
+    }
+
+    #[test]
+    fn test_execution_price_continuous_across_initial_price() {
+        let initial = CpmmState::new(1000.0, 2.0).unwrap();
+        let execution = |final_price: f64, fee: f64| {
+            let final_state = CpmmState {
+                price: final_price,
+                ..initial
+            };
+            TradeResult::compute(initial, final_state, fee).execution_price
+        };
+        // Without a fee both sides approach the initial price
+        for epsilon in [1e-3, 1e-6] {
+            let below = execution(2.0 * (1.0 - epsilon), 0.0);
+            let above = execution(2.0 * (1.0 + epsilon), 0.0);
+            assert!(below < 2.0 && above > 2.0);
+            assert!((above - below) / 2.0 < 2.0 * epsilon);
+        }
+        // A fee opens a spread of about the fee on either side
+        let below = execution(2.0 * (1.0 - 1e-9), 0.003);
+        let above = execution(2.0 * (1.0 + 1e-9), 0.003);
+        assert!((below - 2.0 / (1.0 + 0.003)).abs() < 1e-6);
+        assert!((above - 2.0 * (1.0 + 0.003)).abs() < 1e-6);
+
+        // Exactly at the initial price it is undefined
+        let state = AppState::default();
+        let at_initial = AppState {
+            final_price: state.initial_price,
+            ..state.clone()
+        };
+        let trade = model_outcome(&at_initial).unwrap().trade;
+        assert_eq!(execution_price_text(&at_initial, &trade), UNDEFINED_DISPLAY);
+        let trade = model_outcome(&state).unwrap().trade;
+        assert_ne!(execution_price_text(&state, &trade), UNDEFINED_DISPLAY);
+    }
+
+    #[test]
+    fn test_fee_revenue_and_apr() {
+        // 1000 liquidity at price 1 holds 2000 in quote value
+        let daily = fee_revenue(100_000.0, 0.003, 1000.0);
+        assert!(approx_eq(daily, 300.0));
+        assert!(approx_eq(simple_apr(daily, 2000.0), 54.75));
+        assert_eq!(fee_revenue(0.0, 0.003, 1000.0), 0.0);
+        assert_eq!(simple_apr(0.0, 2000.0), 0.0);
+        // An empty pool earns nothing and has no APR
+        assert_eq!(fee_revenue(100_000.0, 0.003, 0.0), 0.0);
+        assert_eq!(simple_apr(300.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_is_narrow_breakpoint() {
+        assert!(is_narrow(320.0));
+        assert!(is_narrow(NARROW_BREAKPOINT - 0.5));
+        assert!(!is_narrow(NARROW_BREAKPOINT));
+        assert!(!is_narrow(1280.0));
+    }
+
+    #[test]
+    fn test_compare_fees() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        for final_price in [1.2, 0.8] {
+            let final_state = CpmmState::new(1000.0, final_price).unwrap();
+            let (at_fee, free) = compare_fees(initial, final_state, 0.003, 0.0);
+            let expected = TradeResult::compute(initial, final_state, 0.003);
+            assert_eq!(at_fee.base_wallet_delta_net, expected.base_wallet_delta_net);
+            assert_eq!(
+                at_fee.quote_wallet_delta_net,
+                expected.quote_wallet_delta_net
             );
+            // The move fixes what the pool pays out, so without the fee the
+            // trader receives the same and pays in less
+            assert!(free.base_wallet_delta_net >= at_fee.base_wallet_delta_net);
+            assert!(free.quote_wallet_delta_net >= at_fee.quote_wallet_delta_net);
+            let gain = (free.base_wallet_delta_net - at_fee.base_wallet_delta_net)
+                + (free.quote_wallet_delta_net - at_fee.quote_wallet_delta_net);
+            assert!(gain > 0.0);
+            assert_eq!(free.base_fee_collected + free.quote_fee_collected, 0.0);
         }
     }
 
     #[test]
-    fn test_slider_center() {
-        let center = 10.0;
-        let decades = 2.0;
+    fn test_breakdown_sides() {
+        let initial = CpmmState::new(1000.0, 1.0).unwrap();
+        for fee_mode in [FeeMode::OnInput, FeeMode::OnOutput] {
+            for (final_price, base_paid) in [(1.2, false), (0.8, true)] {
+                let final_state = CpmmState::new(1000.0, final_price).unwrap();
+                let result = TradeResult::compute_split(initial, final_state, 0.003, 1.0, fee_mode);
+                let parts = breakdown(&result);
+                assert_eq!(parts.base_paid, base_paid);
+                assert!(parts.paid.net < 0.0 && parts.received.net > 0.0);
 
-        // Slider at 0.5 should give center price
-        let price = slider_to_price(0.5, center, decades);
-        assert!(approx_eq(price, center));
+                let (fee_side, free_side) = match fee_mode {
+                    FeeMode::OnInput => (parts.paid, parts.received),
+                    FeeMode::OnOutput => (parts.received, parts.paid),
+                };
+                assert!(fee_side.fee > 0.0);
+                assert_eq!(fee_side.gross - fee_side.fee, fee_side.net);
+                assert_eq!(free_side.fee, 0.0);
+                assert_eq!(free_side.gross, free_side.net);
+            }
+        }
+    }
+
+    #[test]
+    fn test_auto_slider_range_tiny_price() {
+        let (center, decades) = auto_slider_range(1e-9, 1.1e-9);
+        assert_eq!((center, decades), (1e-9, DEFAULT_SLIDER_DECADES));
+        assert!((price_to_slider(1e-9, center, decades) - 0.5).abs() < 1e-9);
+        assert!(slider_covers(1.1e-9, center, decades));
+
+        // The default range cannot show it; fitting re-centers the sliders
+        let mut state = AppState {
+            initial_price: 1e-9,
+            final_price: 1.1e-9,
+            ..AppState::default()
+        };
+        assert!(!slider_covers(1e-9, state.center_price, state.decades));
+        state.fit_slider_range();
+        assert_eq!(state.center_price, 1e-9);
+        assert_eq!(state.decades, DEFAULT_SLIDER_DECADES);
+    }
+
+    #[test]
+    fn test_auto_slider_range_spans_orders_of_magnitude() {
+        for (initial, final_price) in [(1e-3, 1e3), (1e4, 1e-2), (2.0, 3e7)] {
+            let (center, decades) = auto_slider_range(initial, final_price);
+            assert_eq!(center, initial);
+            assert!(slider_covers(initial, center, decades));
+            assert!(slider_covers(final_price, center, decades));
+            // A decade of margin keeps the final price off the slider's end
+            let position = price_to_slider(final_price, center, decades);
+            assert!(position > 0.0 && position < 1.0);
+        }
+        assert_eq!(auto_slider_range(1e-3, 1e3).1, 7.0);
+
+        // A range that already covers both prices is kept
+        let mut state = AppState::default();
+        state.fit_slider_range();
+        assert_eq!(state.center_price, 1.0);
+        assert_eq!(state.decades, DEFAULT_SLIDER_DECADES);
     }
 }
@@ -0,0 +1,4753 @@
+//! The calculator's DOM: building the UI, event listeners, persistence and
+//! the `#[wasm_bindgen]` entry points. Compiled only with the `ui` feature.
+
+use super::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{Document, Element, HtmlInputElement, HtmlSelectElement, Node, console};
+
+/// Query string of the current page, including the leading `?`.
+fn page_query() -> Option<String> {
+    web_sys::window()?.location().search().ok()
+}
+
+/// Applies the page URL's parameters for the calculator with this prefix.
+fn apply_page_query(state: &mut AppState, prefix: &str) {
+    let Some(params) = page_query().and_then(|q| web_sys::UrlSearchParams::new_with_str(&q).ok())
+    else {
+        return;
+    };
+    for (key, _) in query_pairs(state) {
+        if let Some(value) = params.get(&query_key(prefix, key)) {
+            apply_query_value(state, key, &value);
+        }
+    }
+}
+
+/// Writes this calculator's parameters into the page URL without adding a
+/// history entry. Other parameters, the host page's included, are kept.
+fn replace_page_query(prefix: &str, state: &AppState) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let location = window.location();
+    let (Ok(path), Ok(search), Ok(hash)) =
+        (location.pathname(), location.search(), location.hash())
+    else {
+        return;
+    };
+    let Ok(params) = web_sys::UrlSearchParams::new_with_str(&search) else {
+        return;
+    };
+    for (key, value) in query_pairs(state) {
+        params.set(&query_key(prefix, key), &value.to_string());
+    }
+    let query = String::from(js_sys::Object::to_string(&params));
+    let url = format!("{}?{}{}", path, query, hash);
+    if let Ok(history) = window.history() {
+        let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&url));
+    }
+}
+
+type SharedState = Rc<RefCell<AppState>>;
+
+/// localStorage key holding the saved state of the calculator with this prefix.
+fn storage_key(prefix: &str) -> String {
+    format!("cpmm-calculator:{}", prefix)
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// Loads the saved state for a calculator.
+/// Missing or corrupt saved values fall back to the defaults.
+fn load_state(prefix: &str) -> AppState {
+    local_storage()
+        .and_then(|storage| storage.get_item(&storage_key(prefix)).ok()?)
+        .and_then(|json| AppState::from_json(&json))
+        .unwrap_or_default()
+}
+
+/// Saves the state of a calculator so it survives a reload.
+fn save_state(prefix: &str, state: &AppState) {
+    if let Some(storage) = local_storage()
+        && let Err(e) = storage.set_item(&storage_key(prefix), &state.to_json())
+    {
+        console::error_1(&format!("CPMM Calculator: Failed to save state: {:?}", e).into());
+    }
+}
+
+/// Closure run for a DOM event.
+type EventClosure = Closure<dyn Fn(web_sys::Event)>;
+
+/// DOM event listener owned by a calculator instance.
+/// Dropping it unregisters the closure, so targets that outlive the instance,
+/// like the window, never call into a freed closure.
+struct Listener {
+    target: web_sys::EventTarget,
+    event: String,
+    closure: EventClosure,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback(
+            &self.event,
+            self.closure.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+/// A calculator injected into the page.
+/// Listeners live here instead of being leaked with `forget()`, so dropping the
+/// instance releases them along with the shared state they capture.
+struct Instance {
+    container: Element,
+    ctx: UiContext,
+    state: SharedState,
+    #[allow(dead_code)]
+    listeners: Vec<Listener>,
+}
+
+/// How much the calculator logs to the console. Errors are always logged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogLevel {
+    /// Errors only.
+    Quiet,
+    /// Lifecycle messages such as initialization.
+    Normal,
+    /// Also recompute timing.
+    Verbose,
+}
+
+thread_local! {
+    static LOG_LEVEL: Cell<LogLevel> = const { Cell::new(LogLevel::Normal) };
+}
+
+fn log_level() -> LogLevel {
+    LOG_LEVEL.with(Cell::get)
+}
+
+/// Logs an informational message unless the log level is quiet.
+fn log_info(message: &str) {
+    if log_level() != LogLevel::Quiet {
+        console::log_1(&message.into());
+    }
+}
+
+/// Sets the console log level: `"quiet"`, `"normal"` or `"verbose"`.
+/// Verbose also logs how long each recompute takes.
+#[wasm_bindgen]
+pub fn set_log_level(level: &str) {
+    let level = match level {
+        "quiet" => LogLevel::Quiet,
+        "normal" => LogLevel::Normal,
+        "verbose" => LogLevel::Verbose,
+        _ => {
+            console::error_1(&format!("CPMM Calculator: Unknown log level '{}'", level).into());
+            return;
+        }
+    };
+    LOG_LEVEL.with(|current| current.set(level));
+}
+
+thread_local! {
+    /// Injected calculators keyed by anchor ID, or by the key returned from
+    /// `inject_ui_into_element`.
+    static INSTANCES: RefCell<HashMap<String, Instance>> = RefCell::new(HashMap::new());
+
+    /// Counter for generated registry keys.
+    static NEXT_INSTANCE_KEY: Cell<u32> = const { Cell::new(0) };
+}
+
+/// DOM handles for one calculator instance.
+/// Every element ID is namespaced with `prefix` so instances do not collide.
+#[derive(Clone)]
+struct UiContext {
+    document: Document,
+    root: Element,
+    prefix: String,
+    /// JS callback run after every recompute; shared by all clones.
+    on_change: Rc<RefCell<Option<js_sys::Function>>>,
+    /// Delays recomputes triggered by typing; shared by all clones.
+    debouncer: Rc<Debouncer>,
+    /// Committed states for undo/redo; shared by all clones.
+    history: Rc<RefCell<History<AppState>>>,
+}
+
+impl UiContext {
+    /// Namespaced element ID for this instance.
+    fn id(&self, id: &str) -> String {
+        format!("{}-{}", self.prefix, id)
+    }
+}
+
+/// Default delay between the last keystroke and the recompute, in milliseconds.
+const DEFAULT_DEBOUNCE_MS: i32 = 150;
+
+/// Work waiting for a `Debouncer` timer, shared with its timer callback.
+type PendingWork = Rc<RefCell<Option<Box<dyn FnOnce()>>>>;
+
+/// Collapses bursts of input events into a single recompute.
+/// Scheduling replaces any pending work and restarts the timer, so only the
+/// last value typed is computed. A delay of zero runs work right away.
+struct Debouncer {
+    delay_ms: Cell<i32>,
+    /// Set while an input listener runs, so `refresh` knows to defer.
+    typing: Cell<bool>,
+    timeout: Rc<Cell<Option<i32>>>,
+    pending: PendingWork,
+    /// Timer callback; lives as long as the debouncer and runs `pending`.
+    fire: Closure<dyn Fn()>,
+}
+
+impl Debouncer {
+    fn new(delay_ms: i32) -> Self {
+        let timeout: Rc<Cell<Option<i32>>> = Rc::default();
+        let pending: PendingWork = Rc::default();
+        let fire = {
+            let timeout = Rc::clone(&timeout);
+            let pending = Rc::clone(&pending);
+            Closure::wrap(Box::new(move || {
+                timeout.set(None);
+                let work = pending.borrow_mut().take();
+                if let Some(work) = work {
+                    work();
+                }
+            }) as Box<dyn Fn()>)
+        };
+        Self {
+            delay_ms: Cell::new(delay_ms),
+            typing: Cell::new(false),
+            timeout,
+            pending,
+            fire,
+        }
+    }
+
+    /// Runs `work` after the delay, dropping whatever was pending before.
+    /// Falls back to running it right away if no timer can be set.
+    fn schedule(&self, work: Box<dyn FnOnce()>) {
+        self.cancel();
+        let delay_ms = self.delay_ms.get();
+        if delay_ms <= 0 {
+            work();
+            return;
+        }
+        let handle = web_sys::window().and_then(|window| {
+            window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(
+                    self.fire.as_ref().unchecked_ref(),
+                    delay_ms,
+                )
+                .ok()
+        });
+        match handle {
+            Some(handle) => {
+                *self.pending.borrow_mut() = Some(work);
+                self.timeout.set(Some(handle));
+            }
+            None => work(),
+        }
+    }
+
+    /// Drops pending work without running it.
+    fn cancel(&self) {
+        self.clear_timer();
+        self.pending.borrow_mut().take();
+    }
+
+    /// Runs pending work right away instead of waiting for the timer.
+    fn flush(&self) {
+        self.clear_timer();
+        let work = self.pending.borrow_mut().take();
+        if let Some(work) = work {
+            work();
+        }
+    }
+
+    fn clear_timer(&self) {
+        if let Some(handle) = self.timeout.take()
+            && let Some(window) = web_sys::window()
+        {
+            window.clear_timeout_with_handle(handle);
+        }
+    }
+}
+
+/// Converts an Element to a Node reference for append operations.
+fn as_node(element: &Element) -> &Node {
+    element.as_ref()
+}
+
+/// Creates a labeled input row with one or two fields.
+/// Rows with one field get `cpmm-row-single` and span the whole row.
+fn create_input_row(
+    ctx: &UiContext,
+    label1: &str,
+    id1: &str,
+    value1: &str,
+    label2: Option<&str>,
+    id2: Option<&str>,
+    value2: Option<&str>,
+) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    if label2.is_some() {
+        row.set_attribute("class", "cpmm-row")?;
+    } else {
+        row.set_attribute("class", "cpmm-row cpmm-row-single")?;
+    }
+
+    let create_field = |label: &str, id: &str, value: &str| -> Result<Element, JsValue> {
+        let field = ctx.document.create_element("div")?;
+        field.set_attribute("class", "cpmm-field")?;
+
+        let lbl = ctx.document.create_element("label")?;
+        lbl.set_text_content(Some(label));
+        lbl.set_attribute("for", &ctx.id(id))?;
+
+        let input = ctx.document.create_element("input")?;
+        input.set_attribute("type", "text")?;
+        input.set_attribute("id", &ctx.id(id))?;
+        input.set_attribute("value", value)?;
+
+        field.append_child(as_node(&lbl))?;
+        field.append_child(as_node(&input))?;
+        Ok(field)
+    };
+
+    let field1 = create_field(label1, id1, value1)?;
+    row.append_child(as_node(&field1))?;
+
+    if let (Some(l2), Some(i2), Some(v2)) = (label2, id2, value2) {
+        let field2 = create_field(l2, i2, v2)?;
+        row.append_child(as_node(&field2))?;
+    }
+
+    Ok(row)
+}
+
+/// Creates a slider row over [0, 1].
+fn create_slider_row(
+    ctx: &UiContext,
+    label_text: &str,
+    id: &str,
+    value: f64,
+    step: f64,
+) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    row.set_attribute("class", "cpmm-slider-row")?;
+
+    let label = ctx.document.create_element("label")?;
+    label.set_text_content(Some(label_text));
+
+    let slider = ctx.document.create_element("input")?;
+    slider.set_attribute("type", "range")?;
+    slider.set_attribute("id", &ctx.id(id))?;
+    slider.set_attribute("min", "0")?;
+    slider.set_attribute("max", "1")?;
+    slider.set_attribute("step", &step.to_string())?;
+    slider.set_attribute("value", &value.to_string())?;
+    slider.set_attribute("class", "cpmm-slider")?;
+
+    row.append_child(as_node(&label))?;
+    row.append_child(as_node(&slider))?;
+    Ok(row)
+}
+
+/// Creates a section with a title.
+fn create_section(ctx: &UiContext, title: &str) -> Result<Element, JsValue> {
+    let section = ctx.document.create_element("div")?;
+    section.set_attribute("class", "cpmm-section")?;
+
+    let header = ctx.document.create_element("div")?;
+    header.set_attribute("class", "cpmm-section-header")?;
+    header.set_text_content(Some(title));
+
+    section.append_child(as_node(&header))?;
+    Ok(section)
+}
+
+/// Creates a row holding a single button.
+fn create_button_row(ctx: &UiContext, label: &str, id: &str) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    row.set_attribute("class", "cpmm-button-row")?;
+
+    let button = ctx.document.create_element("button")?;
+    button.set_attribute("type", "button")?;
+    button.set_attribute("id", &ctx.id(id))?;
+    button.set_attribute("class", "cpmm-button")?;
+    button.set_text_content(Some(label));
+
+    row.append_child(as_node(&button))?;
+    Ok(row)
+}
+
+/// Creates a row holding a labeled checkbox.
+fn create_checkbox_row(
+    ctx: &UiContext,
+    label_text: &str,
+    id: &str,
+    checked: bool,
+) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    row.set_attribute("class", "cpmm-checkbox-row")?;
+
+    let checkbox = ctx.document.create_element("input")?;
+    checkbox.set_attribute("type", "checkbox")?;
+    checkbox.set_attribute("id", &ctx.id(id))?;
+    if checked {
+        checkbox.set_attribute("checked", "")?;
+    }
+
+    let label = ctx.document.create_element("label")?;
+    label.set_text_content(Some(label_text));
+    label.set_attribute("for", &ctx.id(id))?;
+
+    row.append_child(as_node(&checkbox))?;
+    row.append_child(as_node(&label))?;
+    Ok(row)
+}
+
+/// Creates a row of radio buttons sharing the group `id`.
+/// Options are (value, label) pairs; each button has the ID `{id}-{value}`.
+fn create_radio_row(
+    ctx: &UiContext,
+    label_text: &str,
+    id: &str,
+    options: &[(&str, &str)],
+    selected: &str,
+) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    row.set_attribute("class", "cpmm-radio-row")?;
+
+    let label = ctx.document.create_element("span")?;
+    label.set_text_content(Some(label_text));
+    row.append_child(as_node(&label))?;
+
+    for (value, text) in options {
+        let option_id = ctx.id(&format!("{}-{}", id, value));
+        let radio = ctx.document.create_element("input")?;
+        radio.set_attribute("type", "radio")?;
+        radio.set_attribute("id", &option_id)?;
+        radio.set_attribute("name", &ctx.id(id))?;
+        radio.set_attribute("value", value)?;
+        if *value == selected {
+            radio.set_attribute("checked", "")?;
+        }
+
+        let option_label = ctx.document.create_element("label")?;
+        option_label.set_text_content(Some(text));
+        option_label.set_attribute("for", &option_id)?;
+
+        row.append_child(as_node(&radio))?;
+        row.append_child(as_node(&option_label))?;
+    }
+    Ok(row)
+}
+
+/// Creates a row holding a labeled dropdown.
+/// Options are (value, label) pairs; `selected` is the value initially chosen.
+fn create_select_row(
+    ctx: &UiContext,
+    label_text: &str,
+    id: &str,
+    options: &[(&str, &str)],
+    selected: &str,
+) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    row.set_attribute("class", "cpmm-select-row")?;
+
+    let label = ctx.document.create_element("label")?;
+    label.set_text_content(Some(label_text));
+    label.set_attribute("for", &ctx.id(id))?;
+
+    let select = ctx.document.create_element("select")?;
+    select.set_attribute("id", &ctx.id(id))?;
+    for (value, text) in options {
+        let option = ctx.document.create_element("option")?;
+        option.set_attribute("value", value)?;
+        option.set_text_content(Some(text));
+        if *value == selected {
+            option.set_attribute("selected", "")?;
+        }
+        select.append_child(as_node(&option))?;
+    }
+
+    row.append_child(as_node(&label))?;
+    row.append_child(as_node(&select))?;
+    Ok(row)
+}
+
+/// SVG namespace for chart elements.
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+
+/// Creates a stacked-area chart with one polygon per layer.
+/// Layers are (id, CSS class) pairs drawn in order; their points are filled in
+/// later by ID.
+fn create_chart(
+    ctx: &UiContext,
+    layers: &[(&str, &str)],
+    width: f64,
+    height: f64,
+) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    row.set_attribute("class", "cpmm-chart-row")?;
+
+    let svg = ctx.document.create_element_ns(Some(SVG_NAMESPACE), "svg")?;
+    svg.set_attribute("class", "cpmm-chart")?;
+    svg.set_attribute("viewBox", &format!("0 0 {} {}", width, height))?;
+    svg.set_attribute("preserveAspectRatio", "none")?;
+    for (id, class) in layers {
+        let polygon = ctx
+            .document
+            .create_element_ns(Some(SVG_NAMESPACE), "polygon")?;
+        polygon.set_attribute("id", &ctx.id(id))?;
+        polygon.set_attribute("class", class)?;
+        svg.append_child(as_node(&polygon))?;
+    }
+
+    row.append_child(as_node(&svg))?;
+    Ok(row)
+}
+
+/// Creates a chart of the invariant curve with a dot for each pool state.
+/// The curve and dots are positioned later by ID.
+fn create_curve_chart(ctx: &UiContext, width: f64, height: f64) -> Result<Element, JsValue> {
+    let row = ctx.document.create_element("div")?;
+    row.set_attribute("class", "cpmm-chart-row")?;
+
+    let svg = ctx.document.create_element_ns(Some(SVG_NAMESPACE), "svg")?;
+    svg.set_attribute("class", "cpmm-chart")?;
+    svg.set_attribute("viewBox", &format!("0 0 {} {}", width, height))?;
+
+    let curve = ctx
+        .document
+        .create_element_ns(Some(SVG_NAMESPACE), "polyline")?;
+    curve.set_attribute("id", &ctx.id("curve-line"))?;
+    curve.set_attribute("class", "cpmm-chart-curve")?;
+    svg.append_child(as_node(&curve))?;
+
+    for (id, class) in [
+        ("curve-initial", "cpmm-chart-initial"),
+        ("curve-final", "cpmm-chart-final"),
+    ] {
+        let dot = ctx
+            .document
+            .create_element_ns(Some(SVG_NAMESPACE), "circle")?;
+        dot.set_attribute("id", &ctx.id(id))?;
+        dot.set_attribute("class", class)?;
+        dot.set_attribute("r", "4")?;
+        svg.append_child(as_node(&dot))?;
+    }
+
+    row.append_child(as_node(&svg))?;
+    Ok(row)
+}
+
+/// Finds an element of this instance by its unprefixed ID.
+/// Lookups are scoped to the calculator rather than the document so they also
+/// work inside a container that is not attached to the page.
+fn find_element(ctx: &UiContext, id: &str) -> Option<Element> {
+    ctx.root
+        .query_selector(&format!("[id=\"{}\"]", ctx.id(id)))
+        .ok()
+        .flatten()
+}
+
+/// Gets an input element of this instance by its unprefixed ID.
+fn get_input(ctx: &UiContext, id: &str) -> Option<HtmlInputElement> {
+    find_element(ctx, id).and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+}
+
+/// Gets a dropdown of this instance by its unprefixed ID.
+fn get_select(ctx: &UiContext, id: &str) -> Option<HtmlSelectElement> {
+    find_element(ctx, id).and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
+}
+
+/// Marks an input invalid with a message, or clears the mark with None.
+/// The message goes into the span with ID `{id}-error`.
+fn set_field_error(ctx: &UiContext, id: &str, message: Option<&str>) {
+    if let Some(input) = find_element(ctx, id) {
+        let _ = input
+            .class_list()
+            .toggle_with_force("cpmm-input-error", message.is_some());
+    }
+    if let Some(span) = find_element(ctx, &format!("{}-error", id)) {
+        span.set_text_content(message);
+    }
+}
+
+/// Sets the value of an input element.
+fn set_input_value(ctx: &UiContext, id: &str, value: &str) {
+    if let Some(input) = get_input(ctx, id) {
+        input.set_value(value);
+    }
+}
+
+/// Sets whether a checkbox is checked.
+fn set_checked(ctx: &UiContext, id: &str, checked: bool) {
+    if let Some(input) = get_input(ctx, id) {
+        input.set_checked(checked);
+    }
+}
+
+/// Labels naming the base or quote token, as (id, label, inverted label).
+/// Inverting the pair swaps which token is called base.
+const BASE_QUOTE_LABELS: &[(&str, &str, &str)] = &[
+    ("initial-base-reserves", "Base Reserves:", "Quote Reserves:"),
+    (
+        "initial-quote-reserves",
+        "Quote Reserves:",
+        "Base Reserves:",
+    ),
+    ("final-base-reserves", "Base Reserves:", "Quote Reserves:"),
+    ("final-quote-reserves", "Quote Reserves:", "Base Reserves:"),
+    (
+        "delta-base-reserves",
+        "Base Reserves Delta:",
+        "Quote Reserves Delta:",
+    ),
+    (
+        "delta-quote-reserves",
+        "Quote Reserves Delta:",
+        "Base Reserves Delta:",
+    ),
+    ("delta-base-net", "Base Net Delta:", "Quote Net Delta:"),
+    (
+        "rebase-base-factor",
+        "Base Rebase Factor:",
+        "Quote Rebase Factor:",
+    ),
+    (
+        "rebase-quote-factor",
+        "Quote Rebase Factor:",
+        "Base Rebase Factor:",
+    ),
+    (
+        "delta-base-liquidity",
+        "Base Liquidity Delta:",
+        "Quote Liquidity Delta:",
+    ),
+    (
+        "delta-quote-liquidity",
+        "Quote Liquidity Delta:",
+        "Base Liquidity Delta:",
+    ),
+    ("delta-quote-net", "Quote Net Delta:", "Base Net Delta:"),
+    (
+        "fee-base-collected",
+        "Base Fee Collected:",
+        "Quote Fee Collected:",
+    ),
+    (
+        "fee-quote-collected",
+        "Quote Fee Collected:",
+        "Base Fee Collected:",
+    ),
+    ("compare-base-net", "Base Net Delta:", "Quote Net Delta:"),
+    ("compare-quote-net", "Quote Net Delta:", "Base Net Delta:"),
+    ("compare-base-fee", "Base Fee:", "Quote Fee:"),
+    ("compare-quote-fee", "Quote Fee:", "Base Fee:"),
+    ("fee-base-lp", "Base LP Fee:", "Quote LP Fee:"),
+    ("fee-quote-lp", "Quote LP Fee:", "Base LP Fee:"),
+    ("weight-base", "Base Weight:", "Quote Weight:"),
+    ("weight-quote", "Quote Weight:", "Base Weight:"),
+    (
+        "accrued-base-lp",
+        "Accrued Base LP Fees:",
+        "Accrued Quote LP Fees:",
+    ),
+    (
+        "accrued-quote-lp",
+        "Accrued Quote LP Fees:",
+        "Accrued Base LP Fees:",
+    ),
+    (
+        "lp-daily-volume",
+        "Daily Volume (quote):",
+        "Daily Volume (base):",
+    ),
+    ("max-impact-base-in", "Max Base In:", "Max Quote In:"),
+    ("max-impact-quote-in", "Max Quote In:", "Max Base In:"),
+    (
+        "sim-base-fees",
+        "Simulated Base Fees:",
+        "Simulated Quote Fees:",
+    ),
+    (
+        "sim-quote-fees",
+        "Simulated Quote Fees:",
+        "Simulated Base Fees:",
+    ),
+    (
+        "accrued-base-protocol",
+        "Accrued Base Protocol Fees:",
+        "Accrued Quote Protocol Fees:",
+    ),
+    (
+        "accrued-quote-protocol",
+        "Accrued Quote Protocol Fees:",
+        "Accrued Base Protocol Fees:",
+    ),
+    (
+        "fee-base-protocol",
+        "Base Protocol Fee:",
+        "Quote Protocol Fee:",
+    ),
+    (
+        "fee-quote-protocol",
+        "Quote Protocol Fee:",
+        "Base Protocol Fee:",
+    ),
+    (
+        "collateral-base",
+        "Collateral (Base):",
+        "Collateral (Quote):",
+    ),
+    ("debt-quote", "Debt (Quote):", "Debt (Base):"),
+];
+
+/// Sets the text of the label for an input.
+fn set_label_text(ctx: &UiContext, id: &str, text: &str) {
+    if let Ok(Some(label)) = ctx
+        .root
+        .query_selector(&format!("label[for=\"{}\"]", ctx.id(id)))
+    {
+        label.set_text_content(Some(text));
+    }
+}
+
+/// Moves both price sliders to the positions of the current prices and
+/// matches their step to the range.
+/// Used when the slider mapping changes while the prices stay put.
+fn sync_slider_positions(ctx: &UiContext, state: &AppState) {
+    let sliders = [
+        ("initial-price-slider", state.initial_price),
+        ("final-price-slider", state.final_price),
+    ];
+    // The step goes first; the browser snaps the value to it
+    let step = slider_step_for_decades(state.decades).to_string();
+    for (id, price) in sliders {
+        if let Some(slider) = get_input(ctx, id) {
+            slider.set_step(&step);
+        }
+        set_input_value(ctx, id, &state.slider_for_price(price).to_string());
+    }
+}
+
+/// Writes the displayed value of every editable price input and slider.
+/// Used when the orientation changes, since typed values are otherwise left alone.
+fn sync_price_inputs(ctx: &UiContext, state: &AppState) {
+    let prices = [
+        ("initial-price", state.initial_price),
+        ("final-price", state.final_price),
+        ("external-price", state.external_price),
+        ("slider-center-price", state.center_price),
+    ];
+    for (id, price) in prices {
+        set_input_value(ctx, id, &state.display(state.oriented_price(price)));
+    }
+    sync_slider_positions(ctx, state);
+    let (lower, upper) = state.displayed_range();
+    set_input_value(ctx, "range-lower", &state.display(lower));
+    set_input_value(ctx, "range-upper", &state.display(upper));
+}
+
+/// Writes every editable input, slider and checkbox from the state.
+/// Used when the whole state is replaced, e.g. by a reset.
+fn sync_inputs(ctx: &UiContext, state: &AppState) {
+    sync_price_inputs(ctx, state);
+    let numbers = [
+        ("initial-liquidity", state.initial_liquidity),
+        ("final-liquidity", state.final_liquidity),
+        ("amplification", state.amplification),
+        ("weight-base", state.weight_base),
+        ("weight-quote", state.weight_quote),
+        ("protocol-fee-percent", state.protocol_fee_percent),
+        ("breakeven-fee-apr", state.fee_apr_percent),
+        ("breakeven-il", state.il_percent),
+        ("holder-horizon-days", state.horizon_days),
+        ("collateral-base", state.collateral_base),
+        ("debt-quote", state.debt_quote),
+        ("halflife-close-percent", state.arb_close_percent),
+        ("revenue-base-volume", state.base_volume),
+        ("revenue-elasticity", state.volume_elasticity),
+        ("rebate-volume", state.cumulative_volume),
+        ("rebase-base-factor", state.base_rebase_factor),
+        ("rebase-quote-factor", state.quote_rebase_factor),
+        ("implied-trade-size", state.implied_trade_size),
+        ("implied-impact-percent", state.implied_impact_percent),
+        ("slider-decades", state.decades),
+        ("quotes-bid", state.quoted_bid),
+        ("quotes-ask", state.quoted_ask),
+        ("skew-inventory", state.inventory_fraction),
+        ("skew-factor", state.skew_factor),
+        ("impact-warn-threshold", state.warn_threshold),
+        ("trade-amount", state.trade_amount),
+        ("slippage-tolerance", state.slippage_tolerance_percent),
+        ("max-impact-percent", state.max_impact_percent),
+        ("route-liquidity", state.route_liquidity),
+        ("route-price", state.route_price),
+        ("route-amount", state.route_amount),
+        ("lp-daily-volume", state.daily_volume),
+        ("alt-fee-percent", state.alt_fee_percent),
+    ];
+    for (id, value) in numbers {
+        set_input_value(ctx, id, &state.display(value));
+    }
+    set_input_value(
+        ctx,
+        "oracle-weight-slider",
+        &state.oracle_weight.to_string(),
+    );
+    set_input_value(
+        ctx,
+        "rebate-tiers",
+        &format_rebate_tiers(&state.rebate_tiers),
+    );
+    set_input_value(
+        ctx,
+        "display-precision",
+        &state.display_precision.to_string(),
+    );
+    set_input_value(ctx, "tick-spacing", &state.tick_spacing.to_string());
+    set_checked(ctx, "tick-mode", state.tick_mode);
+    set_input_value(ctx, "sim-seed", &state.sim_seed.to_string());
+    set_input_value(ctx, "sim-steps", &state.sim_steps.to_string());
+    set_checked(ctx, "display-grouped", state.grouped);
+    for (value, _) in DisplayMode::OPTIONS {
+        let id = format!("display-mode-{}", value);
+        set_checked(ctx, &id, *value == state.display_mode.as_str());
+    }
+    set_input_value(
+        ctx,
+        "fee-percent",
+        &state.display_percent(state.fee_percent / 100.0),
+    );
+    set_field_error(ctx, "fee-percent", None);
+    if let Some(select) = get_select(ctx, "amm-model") {
+        select.set_value(state.model.as_str());
+    }
+    for (value, _) in FeeMode::OPTIONS {
+        let id = format!("fee-mode-{}", value);
+        set_checked(ctx, &id, *value == state.fee_mode.as_str());
+    }
+    if let Some(select) = get_select(ctx, "entry-mode") {
+        select.set_value(state.entry_mode.as_str());
+    }
+}
+
+/// Sets the trade amount, and its input, to the amount that reproduces the
+/// final price. Left alone in price mode or when the move goes the other way.
+fn backfill_trade_amount(ctx: &UiContext, state: &mut AppState) {
+    if let Some(amount) = state.trade_amount_for_final_price() {
+        state.trade_amount = amount;
+        set_input_value(ctx, "trade-amount", &state.display(amount));
+    }
+}
+
+/// Re-solves the final price from the trade amount after the pool changes,
+/// moving the final price field and slider to match. In price mode the final
+/// price is the input, so it is left alone.
+fn resolve_final_price(ctx: &UiContext, state: &mut AppState) {
+    if let Some(price) = state.final_price_for_trade_amount() {
+        state.final_price = price;
+        set_input_value(
+            ctx,
+            "final-price",
+            &state.display(state.oriented_price(price)),
+        );
+        set_input_value(
+            ctx,
+            "final-price-slider",
+            &state.slider_for_price(price).to_string(),
+        );
+    }
+}
+
+/// Restores the default state and rewrites every input to match.
+fn reset_state(ctx: &UiContext, state: &SharedState) {
+    *state.borrow_mut() = AppState::default();
+    let s = state.borrow();
+    sync_inputs(ctx, &s);
+    refresh(ctx, &s);
+}
+
+/// Saves the state, mirrors it into the URL and updates the computed fields
+/// after an input change. Changes typed into an input are debounced; any other
+/// change supersedes a pending recompute and runs right away.
+fn refresh(ctx: &UiContext, state: &AppState) {
+    if ctx.debouncer.typing.get() {
+        let ctx_clone = ctx.clone();
+        let snapshot = state.clone();
+        ctx.debouncer
+            .schedule(Box::new(move || refresh_now(&ctx_clone, &snapshot)));
+    } else {
+        ctx.debouncer.cancel();
+        refresh_now(ctx, state);
+    }
+}
+
+/// Undebounced body of `refresh`.
+fn refresh_now(ctx: &UiContext, state: &AppState) {
+    ctx.history.borrow_mut().push(state.clone());
+    sync_history_buttons(ctx);
+    save_state(&ctx.prefix, state);
+    replace_page_query(&ctx.prefix, state);
+    update_computed_fields(ctx, state);
+}
+
+/// Updates just the execution price, ahead of a debounced recompute, so it
+/// follows every frame of a slider drag.
+fn update_execution_price(ctx: &UiContext, state: &AppState) {
+    if let Ok(outcome) = model_outcome(state) {
+        set_input_value(
+            ctx,
+            "delta-execution-price",
+            &execution_price_text(state, &outcome.trade),
+        );
+    }
+}
+
+/// Disables the Undo/Redo buttons when there is nothing to step to.
+fn sync_history_buttons(ctx: &UiContext) {
+    let history = ctx.history.borrow();
+    for (id, enabled) in [("undo", history.can_undo()), ("redo", history.can_redo())] {
+        if let Some(button) = find_element(ctx, id) {
+            let _ = if enabled {
+                button.remove_attribute("disabled")
+            } else {
+                button.set_attribute("disabled", "")
+            };
+        }
+    }
+}
+
+/// Steps the state back (or forward with `redo`) through the history and
+/// rewrites every input to match. A pending typed change is committed first,
+/// so it is the first thing undone.
+fn step_history(ctx: &UiContext, state: &SharedState, redo: bool) {
+    ctx.debouncer.flush();
+    let restored = {
+        let mut history = ctx.history.borrow_mut();
+        let step = if redo { history.redo() } else { history.undo() };
+        step.cloned()
+    };
+    let Some(restored) = restored else {
+        return;
+    };
+    *state.borrow_mut() = restored;
+    let s = state.borrow();
+    sync_inputs(ctx, &s);
+    refresh(ctx, &s);
+}
+
+/// Media query matching a system-wide dark color scheme.
+const DARK_SCHEME_QUERY: &str = "(prefers-color-scheme: dark)";
+
+/// The system dark-mode media query, if the browser supports it.
+fn dark_scheme_query() -> Option<web_sys::MediaQueryList> {
+    web_sys::window()?
+        .match_media(DARK_SCHEME_QUERY)
+        .ok()
+        .flatten()
+}
+
+/// Applies the theme: toggles `cpmm-dark` on the container and labels the
+/// toggle with the current choice.
+fn apply_theme(ctx: &UiContext, theme: Theme) {
+    let system_dark = dark_scheme_query().is_some_and(|query| query.matches());
+    let _ = ctx
+        .root
+        .class_list()
+        .toggle_with_force("cpmm-dark", resolve_theme(theme, system_dark));
+    if let Some(toggle) = find_element(ctx, "theme-toggle") {
+        toggle.set_text_content(Some(theme.label()));
+    }
+}
+
+/// Stacks row fields into one column on narrow viewports by toggling
+/// `cpmm-narrow` on the container.
+fn apply_layout(ctx: &UiContext) {
+    let width = web_sys::window()
+        .and_then(|window| window.inner_width().ok())
+        .and_then(|width| width.as_f64());
+    if let Some(width) = width {
+        let _ = ctx
+            .root
+            .class_list()
+            .toggle_with_force("cpmm-narrow", is_narrow(width));
+    }
+}
+
+/// Rows that only apply to one AMM model, as (element ID, model).
+const MODEL_ROWS: &[(&str, AmmModel)] = &[
+    ("amplification-row", AmmModel::Stableswap),
+    ("weights-row", AmmModel::Weighted),
+    ("range-section", AmmModel::Concentrated),
+];
+
+/// Shows the rows of the selected model and hides those of the others.
+fn show_model_rows(ctx: &UiContext, model: AmmModel) {
+    for (id, row_model) in MODEL_ROWS {
+        if let Some(row) = find_element(ctx, id) {
+            let _ = row
+                .class_list()
+                .toggle_with_force("cpmm-hidden", *row_model != model);
+        }
+    }
+}
+
+/// Updates all computed fields based on current state.
+/// Invalid pool parameters are logged and leave the fields untouched.
+fn update_computed_fields(ctx: &UiContext, state: &AppState) {
+    for (id, label, inverted_label) in BASE_QUOTE_LABELS {
+        let text = if state.inverted {
+            inverted_label
+        } else {
+            label
+        };
+        set_label_text(ctx, id, text);
+    }
+    show_model_rows(ctx, state.model);
+    for id in ["initial-tick-row", "final-tick-row"] {
+        if let Some(row) = find_element(ctx, id) {
+            let _ = row
+                .class_list()
+                .toggle_with_force("cpmm-hidden", !state.tick_mode);
+        }
+    }
+    if let Some(row) = find_element(ctx, "trade-amount-row") {
+        let _ = row
+            .class_list()
+            .toggle_with_force("cpmm-hidden", state.entry_mode == EntryMode::Price);
+    }
+    apply_theme(ctx, state.theme);
+    if let Some(indicator) = find_element(ctx, "delta-no-trade") {
+        let no_trade = is_no_trade(state.initial_price, state.final_price);
+        let _ = indicator
+            .class_list()
+            .toggle_with_force("cpmm-hidden", !no_trade);
+    }
+    // Equal liquidity means equal k, so the move was a pure price change
+    let invariant_match = state.initial_liquidity == state.final_liquidity;
+    for id in ["initial-invariant", "final-invariant"] {
+        if let Some(field) = find_element(ctx, id) {
+            let _ = field
+                .class_list()
+                .toggle_with_force("cpmm-invariant-match", invariant_match);
+        }
+    }
+    let base_paid = model_outcome(state).is_ok_and(|outcome| breakdown(&outcome.trade).base_paid);
+    let (paid_token, received_token) = if base_paid != state.inverted {
+        ("Base", "Quote")
+    } else {
+        ("Quote", "Base")
+    };
+    for part in ["Gross", "Fee", "Net"] {
+        let suffix = part.to_lowercase();
+        set_label_text(
+            ctx,
+            &format!("breakdown-paid-{}", suffix),
+            &format!("{} Paid {}:", paid_token, part),
+        );
+        set_label_text(
+            ctx,
+            &format!("breakdown-received-{}", suffix),
+            &format!("{} Received {}:", received_token, part),
+        );
+    }
+    if let Some(section) = find_element(ctx, "delta-section") {
+        let high = model_outcome(state).is_ok_and(|outcome| {
+            let (_, impact) = displayed_execution(state, &outcome.trade);
+            impact_is_high(impact, state.warn_threshold)
+        });
+        let _ = section.class_list().toggle_with_force("cpmm-warning", high);
+    }
+    match compute_all(state) {
+        Ok((display, micros)) => {
+            for (id, value) in &display.values {
+                set_input_value(ctx, id, value);
+            }
+            if log_level() == LogLevel::Verbose {
+                log_info(&format!("CPMM Calculator: Recomputed in {:.1} µs", micros));
+            }
+        }
+        Err(e) => console::error_1(&format!("CPMM Calculator: {}", e).into()),
+    }
+    update_composition_chart(ctx, state);
+    update_curve_chart(ctx, state);
+    notify_change(ctx, state);
+}
+
+/// Passes the current scenario to the instance's change callback, if any.
+/// A throwing callback is logged and does not interrupt the calculator.
+fn notify_change(ctx: &UiContext, state: &AppState) {
+    // Cloned out so the callback may replace itself
+    let Some(callback) = ctx.on_change.borrow().clone() else {
+        return;
+    };
+    let scenario = match serde_wasm_bindgen::to_value(&Scenario::new(state)) {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            console::error_1(&format!("CPMM Calculator: {}", e).into());
+            return;
+        }
+    };
+    if let Err(e) = callback.call1(&JsValue::NULL, &scenario) {
+        console::error_2(&"CPMM Calculator: Change callback failed:".into(), &e);
+    }
+}
+
+/// Redraws the composition chart for the current liquidity and slider range.
+fn update_composition_chart(ctx: &UiContext, state: &AppState) {
+    let sweep = composition_sweep(
+        state.initial_liquidity,
+        state.center_price,
+        state.decades,
+        COMPOSITION_SWEEP_STEPS,
+    );
+    let (base_points, quote_points) = stacked_area_points(&sweep, CHART_WIDTH, CHART_HEIGHT);
+    for (id, points) in [
+        ("composition-base", base_points),
+        ("composition-quote", quote_points),
+    ] {
+        if let Some(polygon) = find_element(ctx, id) {
+            let _ = polygon.set_attribute("points", &points);
+        }
+    }
+}
+
+/// Redraws the invariant curve through the initial reserves, with dots at the
+/// initial and final reserves. The x-range spans half the smaller base reserve
+/// to twice the larger, and the y-axis tops out at the curve's left end.
+fn update_curve_chart(ctx: &UiContext, state: &AppState) {
+    let Ok(outcome) = model_outcome(state) else {
+        return;
+    };
+    let (initial, final_reserves) = (outcome.initial_reserves, outcome.final_reserves);
+    let k = initial.0 * initial.1;
+    let x_min = 0.5 * initial.0.min(final_reserves.0);
+    let x_max = 2.0 * initial.0.max(final_reserves.0);
+    let y_max = (k / x_min).max(final_reserves.1);
+    if !(x_min > 0.0 && x_max > x_min && y_max > 0.0) {
+        return;
+    }
+    let to_svg = |(x, y): (f64, f64)| {
+        (
+            (x - x_min) / (x_max - x_min) * CHART_WIDTH,
+            CHART_HEIGHT - y / y_max * CHART_HEIGHT,
+        )
+    };
+    let points: Vec<String> = curve_points(k, x_min, x_max, CURVE_STEPS)
+        .into_iter()
+        .map(|point| {
+            let (x, y) = to_svg(point);
+            format!("{:.2},{:.2}", x, y)
+        })
+        .collect();
+    if let Some(curve) = find_element(ctx, "curve-line") {
+        let _ = curve.set_attribute("points", &points.join(" "));
+    }
+    for (id, reserves) in [("curve-initial", initial), ("curve-final", final_reserves)] {
+        if let Some(dot) = find_element(ctx, id) {
+            let (x, y) = to_svg(reserves);
+            let _ = dot.set_attribute("cx", &format!("{:.2}", x));
+            let _ = dot.set_attribute("cy", &format!("{:.2}", y));
+        }
+    }
+}
+
+/// Current time in microseconds from `performance.now()`, if available.
+fn now_micros() -> Option<f64> {
+    Some(web_sys::window()?.performance()?.now() * 1000.0)
+}
+
+/// Runs the full computation once and reports how long it took, in microseconds.
+/// Timing only happens at the verbose log level; otherwise the time is 0.
+fn compute_all(state: &AppState) -> Result<(DisplayValues, f64), CpmmError> {
+    if log_level() != LogLevel::Verbose {
+        return Ok((compute_display_values(state)?, 0.0));
+    }
+    let start = now_micros();
+    let display = compute_display_values(state)?;
+    let elapsed = match (start, now_micros()) {
+        (Some(start), Some(end)) => end - start,
+        _ => 0.0,
+    };
+    Ok((display, elapsed))
+}
+
+/// Logs a failed copy and says so next to the copy button.
+fn copy_failed(ctx: &UiContext, reason: &JsValue) {
+    console::error_2(&"CPMM Calculator: Copy failed:".into(), reason);
+    if let Some(status) = find_element(ctx, "copy-status") {
+        status.set_text_content(Some("Copy failed"));
+    }
+}
+
+/// Writes the results summary to the clipboard.
+/// The clipboard is only available in secure contexts, and the browser may
+/// still reject the write; either way the failure is reported, not thrown.
+/// `on_reject` handles a rejected write; the caller keeps it alive, since the
+/// promise settles after this returns.
+fn copy_results(ctx: &UiContext, state: &AppState, on_reject: &Closure<dyn FnMut(JsValue)>) {
+    if let Some(status) = find_element(ctx, "copy-status") {
+        status.set_text_content(None);
+    }
+    let text = match model_outcome(state) {
+        Ok(outcome) => format_results_text(state, &outcome.trade),
+        Err(e) => return copy_failed(ctx, &e.to_string().into()),
+    };
+    let Some(window) = web_sys::window() else {
+        return copy_failed(ctx, &"no window".into());
+    };
+    let clipboard = window.navigator().clipboard();
+    if clipboard.is_undefined() {
+        return copy_failed(ctx, &"clipboard unavailable".into());
+    }
+    let _ = clipboard.write_text(&text).catch(on_reject);
+}
+
+/// Downloads the price sweep of the initial pool across the slider range as
+/// `cpmm-sweep.csv`. Failures are logged; nothing is shown in the page.
+fn download_sweep_csv(state: &AppState) {
+    let initial = match state.amount_pool() {
+        Ok(initial) => initial,
+        Err(e) => return console::error_1(&format!("CPMM Calculator: {}", e).into()),
+    };
+    let csv = sweep_csv(
+        initial,
+        state.center_price,
+        state.decades,
+        state.fee_percent / 100.0,
+        SWEEP_CSV_STEPS,
+    );
+    if let Err(e) = download_text(&csv, "text/csv", "cpmm-sweep.csv") {
+        console::error_1(&format!("CPMM Calculator: CSV download failed: {:?}", e).into());
+    }
+}
+
+/// Saves text as a file by clicking a temporary link to a Blob URL.
+fn download_text(text: &str, mime_type: &str, file_name: &str) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or("no window")?;
+    let document = window.document().ok_or("no document")?;
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let parts = js_sys::Array::of1(&JsValue::from_str(text));
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let link: web_sys::HtmlElement = document.create_element("a")?.dyn_into()?;
+    link.set_attribute("href", &url)?;
+    link.set_attribute("download", file_name)?;
+    link.click();
+    web_sys::Url::revoke_object_url(&url)
+}
+
+/// Registers a listener closure on an element and keeps it in `listeners`.
+/// A failed registration is logged and the closure dropped, rather than
+/// panicking and taking down the whole module.
+fn add_listener(
+    listeners: &mut Vec<Listener>,
+    target: &web_sys::EventTarget,
+    event: &str,
+    id: &str,
+    closure: EventClosure,
+) {
+    match target.add_event_listener_with_callback(event, closure.as_ref().unchecked_ref()) {
+        Ok(()) => listeners.push(Listener {
+            target: target.clone(),
+            event: event.to_string(),
+            closure,
+        }),
+        Err(e) => console::error_1(
+            &format!(
+                "CPMM Calculator: Failed to attach {} listener to '{}': {:?}",
+                event, id, e
+            )
+            .into(),
+        ),
+    }
+}
+
+/// Attaches an input event listener to an element.
+/// The closure is stored in `listeners` so it lives as long as the instance.
+/// Recomputes triggered by the callback go through the instance's debouncer.
+/// Does nothing if the element does not exist.
+fn attach_input_listener<F>(ctx: &UiContext, listeners: &mut Vec<Listener>, id: &str, callback: F)
+where
+    F: Fn(String) + 'static,
+{
+    if let Some(input) = get_input(ctx, id) {
+        let target = input.clone();
+        let debouncer = Rc::clone(&ctx.debouncer);
+        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            debouncer.typing.set(true);
+            callback(target.value());
+            debouncer.typing.set(false);
+        }) as Box<dyn Fn(_)>);
+        add_listener(listeners, &input, "input", id, closure);
+    }
+}
+
+/// Attaches a change event listener to a checkbox.
+/// The closure is stored in `listeners` so it lives as long as the instance.
+fn attach_checkbox_listener<F>(
+    ctx: &UiContext,
+    listeners: &mut Vec<Listener>,
+    id: &str,
+    callback: F,
+) where
+    F: Fn(bool) + 'static,
+{
+    if let Some(checkbox) = get_input(ctx, id) {
+        let target = checkbox.clone();
+        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            callback(target.checked());
+        }) as Box<dyn Fn(_)>);
+        add_listener(listeners, &checkbox, "change", id, closure);
+    }
+}
+
+/// Attaches a change event listener to each button of a radio group made by
+/// `create_radio_row`. The callback gets the value of the button selected.
+fn attach_radio_listener<F>(
+    ctx: &UiContext,
+    listeners: &mut Vec<Listener>,
+    id: &str,
+    options: &[(&str, &str)],
+    callback: F,
+) where
+    F: Fn(String) + 'static,
+{
+    let callback = Rc::new(callback);
+    for (value, _) in options {
+        let option_id = format!("{}-{}", id, value);
+        if let Some(radio) = get_input(ctx, &option_id) {
+            let target = radio.clone();
+            let callback = Rc::clone(&callback);
+            let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+                if target.checked() {
+                    callback(target.value());
+                }
+            }) as Box<dyn Fn(_)>);
+            add_listener(listeners, &radio, "change", &option_id, closure);
+        }
+    }
+}
+
+/// Attaches a change event listener to a dropdown.
+/// The closure is stored in `listeners` so it lives as long as the instance.
+fn attach_select_listener<F>(ctx: &UiContext, listeners: &mut Vec<Listener>, id: &str, callback: F)
+where
+    F: Fn(String) + 'static,
+{
+    if let Some(select) = get_select(ctx, id) {
+        let target = select.clone();
+        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            callback(target.value());
+        }) as Box<dyn Fn(_)>);
+        add_listener(listeners, &select, "change", id, closure);
+    }
+}
+
+/// Attaches a keydown event listener to an input.
+/// The closure is stored in `listeners` so it lives as long as the instance.
+/// Does nothing if the element does not exist.
+fn attach_keydown_listener<F>(ctx: &UiContext, listeners: &mut Vec<Listener>, id: &str, callback: F)
+where
+    F: Fn(web_sys::KeyboardEvent) + 'static,
+{
+    if let Some(input) = get_input(ctx, id) {
+        let closure = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            if let Ok(event) = event.dyn_into::<web_sys::KeyboardEvent>() {
+                callback(event);
+            }
+        }) as Box<dyn Fn(_)>);
+        add_listener(listeners, &input, "keydown", id, closure);
+    }
+}
+
+/// Inputs that Up/Down arrows nudge.
+const NUDGE_INPUTS: &[&str] = &[
+    "initial-liquidity",
+    "initial-price",
+    "final-liquidity",
+    "final-price",
+    "fee-percent",
+];
+
+/// Nudges a numeric input and runs it through its input listener, which
+/// validates the value, updates the state and keeps a linked slider in step.
+fn nudge_input(ctx: &UiContext, state: &SharedState, id: &str, direction: i32, shift: bool) {
+    let Some(input) = get_input(ctx, id) else {
+        return;
+    };
+    // Percent fields keep their `%` sign
+    let text = input.value();
+    let percent = text.trim_end().ends_with('%');
+    let Some(current) = parse_user_percent(&text) else {
+        return;
+    };
+    let next = nudge_value(current, direction, shift);
+    let displayed = if percent {
+        state.borrow().display_percent(next / 100.0)
+    } else {
+        state.borrow().display(next)
+    };
+    input.set_value(&displayed);
+    if let Ok(event) = web_sys::Event::new("input") {
+        let _ = input.dispatch_event(&event);
+    }
+}
+
+/// Returns `{ version, features }` describing this build, for bug reports.
+#[wasm_bindgen]
+pub fn build_info() -> JsValue {
+    serde_wasm_bindgen::to_value(&BuildInfo::current()).unwrap_or(JsValue::NULL)
+}
+
+/// Attaches a click event listener to an element.
+/// The closure is stored in `listeners` so it lives as long as the instance.
+fn attach_click_listener<F>(ctx: &UiContext, listeners: &mut Vec<Listener>, id: &str, callback: F)
+where
+    F: Fn() + 'static,
+{
+    if let Some(element) = find_element(ctx, id) {
+        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            callback();
+        }) as Box<dyn Fn(_)>);
+        add_listener(listeners, &element, "click", id, closure);
+    }
+}
+
+/// Error object returned to JS in place of a result.
+#[derive(Serialize)]
+struct ErrorObject {
+    error: String,
+}
+
+/// Computes a trade without the DOM.
+///
+/// On success returns `{ priceDelta, baseWalletDelta, quoteWalletDelta,
+/// baseWalletDeltaNet, quoteWalletDeltaNet, baseFeeCollected, quoteFeeCollected,
+/// baseLpFee, quoteLpFee, baseProtocolFee, quoteProtocolFee, executionPrice,
+/// priceImpact }`. The whole fee is reported as protocol fee. Invalid inputs return `{ error }` instead.
+#[wasm_bindgen]
+pub fn compute_trade(
+    initial_liquidity: f64,
+    initial_price: f64,
+    final_price: f64,
+    fee_percent: f64,
+) -> JsValue {
+    trade_value(trade_from_inputs(
+        initial_liquidity,
+        initial_price,
+        final_price,
+        fee_percent,
+    ))
+}
+
+/// Computes the trade that moves a pool to a target marginal (post-trade)
+/// price, holding liquidity constant. Returns the `compute_trade` layout, or
+/// `{ error }` for invalid inputs.
+#[wasm_bindgen]
+pub fn js_trade_to_marginal_price(
+    initial_liquidity: f64,
+    initial_price: f64,
+    target_price: f64,
+    fee_percent: f64,
+) -> JsValue {
+    let result = validate_fee(fee_percent).and_then(|fee_percent| {
+        let initial =
+            CpmmState::new(initial_liquidity, initial_price).map_err(|e| e.to_string())?;
+        trade_to_marginal_price(initial, target_price, fee_percent / 100.0)
+            .map_err(|e| e.to_string())
+    });
+    trade_value(result)
+}
+
+/// Serializes a trade, or its error as `{ error }`.
+fn trade_value(result: Result<TradeResult, String>) -> JsValue {
+    let value = match result {
+        Ok(result) => serde_wasm_bindgen::to_value(&result),
+        Err(error) => serde_wasm_bindgen::to_value(&ErrorObject { error }),
+    };
+    value.unwrap_or(JsValue::NULL)
+}
+
+/// Checks the center price and decades shared by the slider mappings.
+fn validate_slider_scale(center_price: f64, decades: f64) -> Result<(), JsValue> {
+    if !(center_price.is_finite() && center_price > 0.0) {
+        return Err(JsValue::from_str("Center price must be positive"));
+    }
+    if !(decades.is_finite() && decades > 0.0) {
+        return Err(JsValue::from_str("Decades must be positive"));
+    }
+    Ok(())
+}
+
+/// Converts a slider value in [0, 1] to a price on the calculator's
+/// logarithmic scale; 0.5 maps to `center_price`. Throws if `center_price` or
+/// `decades` is not positive.
+#[wasm_bindgen]
+pub fn js_slider_to_price(
+    slider_value: f64,
+    center_price: f64,
+    decades: f64,
+) -> Result<f64, JsValue> {
+    validate_slider_scale(center_price, decades)?;
+    Ok(slider_to_price(slider_value, center_price, decades))
+}
+
+/// Converts a price to the slider value the calculator would show for it,
+/// clamped to [0, 1] like the slider thumb. Throws if `center_price` or
+/// `decades` is not positive.
+#[wasm_bindgen]
+pub fn js_price_to_slider(price: f64, center_price: f64, decades: f64) -> Result<f64, JsValue> {
+    validate_slider_scale(center_price, decades)?;
+    Ok(price_to_slider(price, center_price, decades).clamp(0.0, 1.0))
+}
+
+/// Validates a raw config object before injecting.
+/// Returns an array of error strings, empty when the config is valid.
+#[wasm_bindgen]
+pub fn validate_config(config: JsValue) -> JsValue {
+    let errors = match serde_wasm_bindgen::from_value::<AppState>(config) {
+        Ok(state) => state.validate(),
+        Err(e) => vec![format!("Invalid config: {}", e)],
+    };
+    errors
+        .iter()
+        .map(|e| JsValue::from_str(e))
+        .collect::<js_sys::Array>()
+        .into()
+}
+
+/// Handle to an injected calculator.
+/// Holds the calculator's state and DOM context directly, so follow-up calls
+/// need no element IDs. Dropping the handle leaves the calculator on the page;
+/// `destroy` removes it.
+#[wasm_bindgen]
+pub struct CpmmCalculator {
+    key: String,
+    ctx: UiContext,
+    state: SharedState,
+}
+
+#[wasm_bindgen]
+impl CpmmCalculator {
+    /// Injects a calculator before the element with ID `anchor_id`, as
+    /// `inject_ui` does, and returns a handle to it. Throws if the anchor is
+    /// missing or has no parent element, or if the UI fails to build.
+    #[wasm_bindgen(constructor)]
+    pub fn new(anchor_id: &str) -> Result<CpmmCalculator, JsValue> {
+        inject_at_anchor(anchor_id, false)
+    }
+
+    /// ID prefix of the calculator's elements, and its key for the
+    /// ID-based functions.
+    #[wasm_bindgen(getter)]
+    pub fn prefix(&self) -> String {
+        self.key.clone()
+    }
+
+    /// Sets the initial price, in the displayed orientation, as if typed into
+    /// its field. Returns false and changes nothing unless the price is
+    /// positive and finite.
+    pub fn set_initial_price(&self, price: f64) -> bool {
+        set_trade_price(&self.ctx, &self.state, price, false)
+    }
+
+    /// Sets the final price, in the displayed orientation, as if typed into
+    /// its field. Returns false and changes nothing unless the price is
+    /// positive and finite.
+    pub fn set_final_price(&self, price: f64) -> bool {
+        set_trade_price(&self.ctx, &self.state, price, true)
+    }
+
+    /// Sets the fee percentage. Returns false and changes nothing unless it
+    /// is in [0, 100).
+    pub fn set_fee(&self, percent: f64) -> bool {
+        let Ok(percent) = validate_fee(percent) else {
+            return false;
+        };
+        self.state.borrow_mut().fee_percent = percent;
+        let s = self.state.borrow();
+        set_input_value(
+            &self.ctx,
+            "fee-percent",
+            &s.display_percent(percent / 100.0),
+        );
+        set_field_error(&self.ctx, "fee-percent", None);
+        refresh(&self.ctx, &s);
+        true
+    }
+
+    /// The current trade, in the layout `compute_trade` returns, or
+    /// `{ error }` when the state does not describe a valid pool.
+    pub fn get_result(&self) -> JsValue {
+        let value = match model_outcome(&self.state.borrow()) {
+            Ok(outcome) => serde_wasm_bindgen::to_value(&outcome.trade),
+            Err(error) => serde_wasm_bindgen::to_value(&ErrorObject {
+                error: error.to_string(),
+            }),
+        };
+        value.unwrap_or(JsValue::NULL)
+    }
+
+    /// Removes the calculator from the page, as `destroy_ui` does.
+    pub fn destroy(self) {
+        destroy_ui(&self.key);
+    }
+}
+
+/// Looks up the anchor by ID and injects a calculator before it.
+fn inject_at_anchor(anchor_id: &str, fallback_to_body: bool) -> Result<CpmmCalculator, JsValue> {
+    log_info("CPMM Calculator: Initializing...");
+
+    let document = web_sys::window()
+        .ok_or("No window object found")?
+        .document()
+        .ok_or("No document object found")?;
+    let anchor = document
+        .get_element_by_id(anchor_id)
+        .ok_or_else(|| format!("Anchor element '{}' not found", anchor_id))?;
+    inject_before(&document, &anchor, anchor_id, fallback_to_body)
+}
+
+/// Injects a calculator keyed by `key` before `anchor`.
+/// An anchor without a parent element has nowhere to put the calculator: it is
+/// either detached or the document root, whose parent cannot take a second
+/// element. That is an error unless `fallback_to_body` is set, in which case
+/// the calculator is appended to the body instead. The check runs before
+/// anything is built, so a failed injection leaves no listeners behind.
+fn inject_before(
+    document: &Document,
+    anchor: &Element,
+    key: &str,
+    fallback_to_body: bool,
+) -> Result<CpmmCalculator, JsValue> {
+    let (parent, reference): (Node, Option<&Element>) = match anchor.parent_element() {
+        Some(parent) => (parent.into(), Some(anchor)),
+        None if fallback_to_body => (document.body().ok_or("No body element found")?.into(), None),
+        None => {
+            return Err(format!("Anchor element '{}' has no parent element", key).into());
+        }
+    };
+
+    // Re-injecting replaces the previous calculator rather than duplicating it
+    if INSTANCES.with(|instances| instances.borrow().contains_key(key)) {
+        destroy_ui(key);
+    }
+
+    let instance = build_ui(document, key).map_err(|e| format!("Failed to build UI: {:?}", e))?;
+    parent
+        .insert_before(&instance.container, reference.map(as_node))
+        .map_err(|e| format!("Failed to insert UI: {:?}", e))?;
+    let calculator = CpmmCalculator {
+        key: key.to_string(),
+        ctx: instance.ctx.clone(),
+        state: Rc::clone(&instance.state),
+    };
+    register_instance(key.to_string(), instance);
+    Ok(calculator)
+}
+
+/// Main entry point for injecting the CPMM calculator UI.
+/// Element IDs inside the calculator are prefixed with `anchor_id`, so several
+/// calculators can share a page. Returns that prefix, or `undefined` if the UI
+/// could not be injected. An anchor without a parent element is an error
+/// unless `fallback_to_body` is true, in which case the calculator is appended
+/// to the body. `new CpmmCalculator(anchor_id)` behaves like the default and
+/// returns a handle instead.
+#[wasm_bindgen]
+pub fn inject_ui(anchor_id: &str, fallback_to_body: Option<bool>) -> Option<String> {
+    match inject_at_anchor(anchor_id, fallback_to_body.unwrap_or(false)) {
+        Ok(calculator) => Some(calculator.key),
+        Err(e) => {
+            console::error_1(&e);
+            None
+        }
+    }
+}
+
+/// Injects the calculator into an element handle passed from JS.
+/// The element needs neither an ID nor a place in the document; the calculator
+/// is appended as its last child. Returns the ID prefix, which is also the key
+/// for `destroy_ui`: the element's ID when it has one, otherwise a generated
+/// key. Returns `undefined` if the UI failed to build.
+#[wasm_bindgen]
+pub fn inject_ui_into_element(target: Element) -> Option<String> {
+    log_info("CPMM Calculator: Initializing...");
+
+    let document = match target.owner_document() {
+        Some(d) => d,
+        None => {
+            console::error_1(&"No document object found".into());
+            return None;
+        }
+    };
+
+    let key = match target.id() {
+        id if id.is_empty() => next_instance_key(),
+        id => id,
+    };
+
+    // Re-injecting replaces the previous calculator rather than duplicating it
+    if INSTANCES.with(|instances| instances.borrow().contains_key(&key)) {
+        destroy_ui(&key);
+    }
+
+    let instance = match build_ui(&document, &key) {
+        Ok(instance) => instance,
+        Err(e) => {
+            console::error_1(&format!("Failed to build UI: {:?}", e).into());
+            return None;
+        }
+    };
+    if let Err(e) = target.append_child(as_node(&instance.container)) {
+        console::error_1(&format!("Failed to insert UI: {:?}", e).into());
+        return None;
+    }
+    register_instance(key.clone(), instance);
+    Some(key)
+}
+
+/// Stores an injected calculator so its listeners stay alive.
+fn register_instance(key: String, instance: Instance) {
+    INSTANCES.with(|instances| {
+        instances.borrow_mut().insert(key, instance);
+    });
+}
+
+/// Generates a registry key for a calculator injected into an element without an ID.
+fn next_instance_key() -> String {
+    NEXT_INSTANCE_KEY.with(|next| {
+        let n = next.get();
+        next.set(n + 1);
+        format!("cpmm-calculator-{}", n)
+    })
+}
+
+/// Wipes the saved state of the calculator with this prefix.
+/// The next injection starts from the defaults.
+#[wasm_bindgen]
+pub fn clear_saved_state(anchor_id: &str) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.remove_item(&storage_key(anchor_id));
+    }
+}
+
+/// Exports the scenario of the calculator with this prefix as JSON,
+/// or an empty string if there is none.
+#[wasm_bindgen]
+pub fn export_json(anchor_id: &str) -> String {
+    let state = INSTANCES.with(|instances| {
+        instances
+            .borrow()
+            .get(anchor_id)
+            .map(|instance| instance.state.borrow().clone())
+    });
+    match state {
+        Some(state) => scenario_json(&state),
+        None => {
+            log_info(&format!(
+                "CPMM Calculator: No calculator injected at '{}'",
+                anchor_id
+            ));
+            String::new()
+        }
+    }
+}
+
+/// Replaces the state of the calculator with this prefix by a scenario
+/// from `export_json` (or a bare state object) and updates every input.
+/// Returns false, leaving the calculator untouched, if there is no such
+/// calculator or the scenario is malformed or out of range.
+#[wasm_bindgen]
+pub fn import_json(anchor_id: &str, json: &str) -> bool {
+    let handles = INSTANCES.with(|instances| {
+        instances
+            .borrow()
+            .get(anchor_id)
+            .map(|instance| (instance.ctx.clone(), Rc::clone(&instance.state)))
+    });
+    let Some((ctx, state)) = handles else {
+        log_info(&format!(
+            "CPMM Calculator: No calculator injected at '{}'",
+            anchor_id
+        ));
+        return false;
+    };
+    let Some(mut imported) = parse_scenario(json) else {
+        log_info("CPMM Calculator: Ignoring malformed or invalid scenario");
+        return false;
+    };
+    // Session totals are not part of a scenario, so they carry over
+    imported.session = state.borrow().session.clone();
+    *state.borrow_mut() = imported;
+    let s = state.borrow();
+    sync_inputs(&ctx, &s);
+    refresh(&ctx, &s);
+    true
+}
+
+/// Sets the initial price (or, with `is_final`, the final price) in the
+/// displayed orientation, moving both its field and its slider as the price
+/// listeners do. Returns false and changes nothing unless the price is
+/// positive and finite.
+fn set_trade_price(ctx: &UiContext, state: &SharedState, price: f64, is_final: bool) -> bool {
+    if !(price.is_finite() && price > 0.0) {
+        return false;
+    }
+    {
+        let mut s = state.borrow_mut();
+        let snapped = s.snap_to_tick(s.oriented_price(price));
+        if is_final {
+            s.final_price = snapped;
+            backfill_trade_amount(ctx, &mut s);
+        } else {
+            s.initial_price = snapped;
+            resolve_final_price(ctx, &mut s);
+        }
+    }
+    let s = state.borrow();
+    sync_price_inputs(ctx, &s);
+    refresh(ctx, &s);
+    true
+}
+
+/// Sets the initial price of the calculator with this prefix, in the displayed
+/// orientation, updating its field and slider. Returns false, changing
+/// nothing, if there is no such calculator or the price is not positive.
+#[wasm_bindgen]
+pub fn set_initial_price(anchor_id: &str, price: f64) -> bool {
+    set_instance_price(anchor_id, price, false)
+}
+
+/// Sets the final price of the calculator with this prefix, in the displayed
+/// orientation, updating its field and slider. Returns false, changing
+/// nothing, if there is no such calculator or the price is not positive.
+#[wasm_bindgen]
+pub fn set_final_price(anchor_id: &str, price: f64) -> bool {
+    set_instance_price(anchor_id, price, true)
+}
+
+fn set_instance_price(anchor_id: &str, price: f64, is_final: bool) -> bool {
+    let handles = INSTANCES.with(|instances| {
+        instances
+            .borrow()
+            .get(anchor_id)
+            .map(|instance| (instance.ctx.clone(), Rc::clone(&instance.state)))
+    });
+    match handles {
+        Some((ctx, state)) => set_trade_price(&ctx, &state, price, is_final),
+        None => {
+            log_info(&format!(
+                "CPMM Calculator: No calculator injected at '{}'",
+                anchor_id
+            ));
+            false
+        }
+    }
+}
+
+/// Registers a callback run after every recompute of the calculator with this
+/// prefix, replacing any previous one; `null` removes it. The callback gets the
+/// same `{ version, state, trade }` object that `export_json` serializes, and
+/// runs once right away with the current values.
+#[wasm_bindgen]
+pub fn set_on_change(anchor_id: &str, callback: Option<js_sys::Function>) {
+    let handles = INSTANCES.with(|instances| {
+        instances
+            .borrow()
+            .get(anchor_id)
+            .map(|instance| (instance.ctx.clone(), Rc::clone(&instance.state)))
+    });
+    match handles {
+        Some((ctx, state)) => {
+            *ctx.on_change.borrow_mut() = callback;
+            notify_change(&ctx, &state.borrow());
+        }
+        None => log_info(&format!(
+            "CPMM Calculator: No calculator injected at '{}'",
+            anchor_id
+        )),
+    }
+}
+
+/// Sets how long the calculator with this prefix waits after the last
+/// keystroke before recomputing, in milliseconds; 0 recomputes on every
+/// keystroke. Defaults to 150.
+#[wasm_bindgen]
+pub fn set_debounce_ms(anchor_id: &str, delay_ms: u32) {
+    let ctx = INSTANCES.with(|instances| {
+        instances
+            .borrow()
+            .get(anchor_id)
+            .map(|instance| instance.ctx.clone())
+    });
+    match ctx {
+        Some(ctx) => {
+            ctx.debouncer.flush();
+            ctx.debouncer
+                .delay_ms
+                .set(i32::try_from(delay_ms).unwrap_or(i32::MAX));
+        }
+        None => log_info(&format!(
+            "CPMM Calculator: No calculator injected at '{}'",
+            anchor_id
+        )),
+    }
+}
+
+/// Resets the calculator with this prefix to the default state, as if its
+/// Reset button were clicked.
+#[wasm_bindgen]
+pub fn reset_ui(anchor_id: &str) {
+    let handles = INSTANCES.with(|instances| {
+        instances
+            .borrow()
+            .get(anchor_id)
+            .map(|instance| (instance.ctx.clone(), Rc::clone(&instance.state)))
+    });
+    match handles {
+        Some((ctx, state)) => reset_state(&ctx, &state),
+        None => log_info(&format!(
+            "CPMM Calculator: No calculator injected at '{}'",
+            anchor_id
+        )),
+    }
+}
+
+/// Removes a calculator previously injected with `inject_ui` or
+/// `inject_ui_into_element`.
+/// Dropping the instance releases its event listeners and shared state.
+#[wasm_bindgen]
+pub fn destroy_ui(anchor_id: &str) {
+    let instance = INSTANCES.with(|instances| instances.borrow_mut().remove(anchor_id));
+    match instance {
+        Some(instance) => {
+            // Save the last value typed before the instance goes away.
+            instance.ctx.debouncer.flush();
+            instance.container.remove();
+            log_info("CPMM Calculator: UI destroyed");
+        }
+        None => log_info(&format!(
+            "CPMM Calculator: No calculator injected at '{}'",
+            anchor_id
+        )),
+    }
+}
+
+/// Builds the complete calculator UI in a detached container.
+/// The caller decides where the container is inserted.
+fn build_ui(document: &Document, prefix: &str) -> Result<Instance, JsValue> {
+    // Parameters in the URL take precedence over the saved state
+    let mut initial_state = load_state(prefix);
+    apply_page_query(&mut initial_state, prefix);
+    initial_state.fit_slider_range();
+    let state: SharedState = Rc::new(RefCell::new(initial_state));
+    let mut listeners: Vec<Listener> = Vec::new();
+    let precision = state.borrow().display_precision;
+    let display = |value: f64| state.borrow().display(value);
+
+    let container = document.create_element("div")?;
+    container.set_attribute("class", "cpmm-calculator")?;
+    let ctx = UiContext {
+        document: document.clone(),
+        root: container.clone(),
+        prefix: prefix.to_string(),
+        on_change: Rc::default(),
+        debouncer: Rc::new(Debouncer::new(DEFAULT_DEBOUNCE_MS)),
+        history: Rc::new(RefCell::new(History::new(
+            state.borrow().clone(),
+            HISTORY_DEPTH,
+        ))),
+    };
+
+    let model_row = create_select_row(
+        &ctx,
+        "AMM Model:",
+        "amm-model",
+        AmmModel::OPTIONS,
+        state.borrow().model.as_str(),
+    )?;
+    container.append_child(as_node(&model_row))?;
+
+    let amplification_row = create_input_row(
+        &ctx,
+        "Amplification (A):",
+        "amplification",
+        &display(state.borrow().amplification),
+        None,
+        None,
+        None,
+    )?;
+    amplification_row.set_attribute("id", &ctx.id("amplification-row"))?;
+    container.append_child(as_node(&amplification_row))?;
+
+    let weights_row = create_input_row(
+        &ctx,
+        "Base Weight:",
+        "weight-base",
+        &display(state.borrow().weight_base),
+        Some("Quote Weight:"),
+        Some("weight-quote"),
+        Some(&display(state.borrow().weight_quote)),
+    )?;
+    weights_row.set_attribute("id", &ctx.id("weights-row"))?;
+    container.append_child(as_node(&weights_row))?;
+
+    let invert_row = create_button_row(&ctx, "Invert Base/Quote", "invert-toggle")?;
+    container.append_child(as_node(&invert_row))?;
+
+    let theme_row = create_button_row(&ctx, state.borrow().theme.label(), "theme-toggle")?;
+    container.append_child(as_node(&theme_row))?;
+
+    let undo_row = create_button_row(&ctx, "Undo", "undo")?;
+    container.append_child(as_node(&undo_row))?;
+
+    let redo_row = create_button_row(&ctx, "Redo", "redo")?;
+    container.append_child(as_node(&redo_row))?;
+
+    let reset_row = create_button_row(&ctx, "Reset", "reset-defaults")?;
+    container.append_child(as_node(&reset_row))?;
+
+    let precision_row = create_input_row(
+        &ctx,
+        "Display Precision:",
+        "display-precision",
+        &precision.to_string(),
+        None,
+        None,
+        None,
+    )?;
+    container.append_child(as_node(&precision_row))?;
+
+    let grouped_row = create_checkbox_row(
+        &ctx,
+        "Thousands separators",
+        "display-grouped",
+        state.borrow().grouped,
+    )?;
+    container.append_child(as_node(&grouped_row))?;
+
+    let display_mode_row = create_radio_row(
+        &ctx,
+        "Computed Values:",
+        "display-mode",
+        DisplayMode::OPTIONS,
+        state.borrow().display_mode.as_str(),
+    )?;
+    container.append_child(as_node(&display_mode_row))?;
+
+    // Slider Settings Section
+    let slider_settings_section = create_section(&ctx, "Slider Settings Section")?;
+
+    let slider_settings_row = {
+        let s = state.borrow();
+        create_input_row(
+            &ctx,
+            "Center Price:",
+            "slider-center-price",
+            &display(s.oriented_price(s.center_price)),
+            Some("Decades:"),
+            Some("slider-decades"),
+            Some(&display(s.decades)),
+        )?
+    };
+    slider_settings_section.append_child(as_node(&slider_settings_row))?;
+
+    let tick_mode_row = create_checkbox_row(
+        &ctx,
+        "Snap prices to ticks",
+        "tick-mode",
+        state.borrow().tick_mode,
+    )?;
+    slider_settings_section.append_child(as_node(&tick_mode_row))?;
+
+    let tick_spacing_row = create_input_row(
+        &ctx,
+        "Tick Spacing:",
+        "tick-spacing",
+        &state.borrow().tick_spacing.to_string(),
+        None,
+        None,
+        None,
+    )?;
+    slider_settings_section.append_child(as_node(&tick_spacing_row))?;
+
+    container.append_child(as_node(&slider_settings_section))?;
+
+    // Initial Price Section
+    let initial_section = create_section(&ctx, "Initial Price Section")?;
+
+    let initial_slider_value = {
+        let s = state.borrow();
+        s.slider_for_price(s.initial_price)
+    };
+
+    let row1 = create_input_row(
+        &ctx,
+        "Liquidity:",
+        "initial-liquidity",
+        &display(state.borrow().initial_liquidity),
+        Some("Price:"),
+        Some("initial-price"),
+        Some(&display(state.borrow().initial_price)),
+    )?;
+    initial_section.append_child(as_node(&row1))?;
+
+    let initial_tick_row = create_input_row(&ctx, "Tick:", "initial-tick", "", None, None, None)?;
+    initial_tick_row.set_attribute("id", &ctx.id("initial-tick-row"))?;
+    initial_section.append_child(as_node(&initial_tick_row))?;
+
+    let slider1 = create_slider_row(
+        &ctx,
+        "Logarithmic Price Slider",
+        "initial-price-slider",
+        initial_slider_value,
+        slider_step_for_decades(state.borrow().decades),
+    )?;
+    initial_section.append_child(as_node(&slider1))?;
+
+    let row2 = create_input_row(
+        &ctx,
+        "Base Reserves:",
+        "initial-base-reserves",
+        "",
+        Some("Quote Reserves:"),
+        Some("initial-quote-reserves"),
+        Some(""),
+    )?;
+    initial_section.append_child(as_node(&row2))?;
+
+    let initial_invariant_row = create_input_row(
+        &ctx,
+        "Invariant k:",
+        "initial-invariant",
+        "",
+        None,
+        None,
+        None,
+    )?;
+    initial_section.append_child(as_node(&initial_invariant_row))?;
+
+    container.append_child(as_node(&initial_section))?;
+
+    // Price Range Section
+    let range_section = create_section(&ctx, "Price Range Section")?;
+    range_section.set_attribute("id", &ctx.id("range-section"))?;
+
+    let range_row = create_input_row(
+        &ctx,
+        "Lower Price:",
+        "range-lower",
+        &display(state.borrow().price_lower),
+        Some("Upper Price:"),
+        Some("range-upper"),
+        Some(&display(state.borrow().price_upper)),
+    )?;
+    range_section.append_child(as_node(&range_row))?;
+
+    let capture_row = create_input_row(
+        &ctx,
+        "Fee Capture %:",
+        "range-fee-capture",
+        "",
+        Some("Full-range Equivalent Liquidity:"),
+        Some("range-equivalent-liquidity"),
+        Some(""),
+    )?;
+    range_section.append_child(as_node(&capture_row))?;
+
+    container.append_child(as_node(&range_section))?;
+
+    // Final Price Section
+    let final_section = create_section(&ctx, "Final Price Section")?;
+
+    let final_slider_value = {
+        let s = state.borrow();
+        s.slider_for_price(s.final_price)
+    };
+
+    let entry_mode_row = create_select_row(
+        &ctx,
+        "Entry Mode:",
+        "entry-mode",
+        EntryMode::OPTIONS,
+        state.borrow().entry_mode.as_str(),
+    )?;
+    final_section.append_child(as_node(&entry_mode_row))?;
+
+    let trade_amount_row = create_input_row(
+        &ctx,
+        "Trade Amount:",
+        "trade-amount",
+        &display(state.borrow().trade_amount),
+        None,
+        None,
+        None,
+    )?;
+    trade_amount_row.set_attribute("id", &ctx.id("trade-amount-row"))?;
+    final_section.append_child(as_node(&trade_amount_row))?;
+
+    let row3 = create_input_row(
+        &ctx,
+        "Fee %:",
+        "fee-percent",
+        &state
+            .borrow()
+            .display_percent(state.borrow().fee_percent / 100.0),
+        Some("Price:"),
+        Some("final-price"),
+        Some(&display(state.borrow().final_price)),
+    )?;
+    if let Some(fee_field) = row3.first_element_child() {
+        let fee_error = document.create_element("span")?;
+        fee_error.set_attribute("id", &ctx.id("fee-percent-error"))?;
+        fee_error.set_attribute("class", "cpmm-field-error")?;
+        fee_field.append_child(as_node(&fee_error))?;
+    }
+    final_section.append_child(as_node(&row3))?;
+
+    let fee_mode_row = create_radio_row(
+        &ctx,
+        "Fee Charged On:",
+        "fee-mode",
+        FeeMode::OPTIONS,
+        state.borrow().fee_mode.as_str(),
+    )?;
+    final_section.append_child(as_node(&fee_mode_row))?;
+
+    let final_tick_row = create_input_row(&ctx, "Tick:", "final-tick", "", None, None, None)?;
+    final_tick_row.set_attribute("id", &ctx.id("final-tick-row"))?;
+    final_section.append_child(as_node(&final_tick_row))?;
+
+    let final_liquidity_row = create_input_row(
+        &ctx,
+        "Liquidity:",
+        "final-liquidity",
+        &display(state.borrow().final_liquidity),
+        Some("Protocol Fee Share %:"),
+        Some("protocol-fee-percent"),
+        Some(&display(state.borrow().protocol_fee_percent)),
+    )?;
+    final_section.append_child(as_node(&final_liquidity_row))?;
+
+    let slider2 = create_slider_row(
+        &ctx,
+        "Logarithmic Price Slider",
+        "final-price-slider",
+        final_slider_value,
+        slider_step_for_decades(state.borrow().decades),
+    )?;
+    final_section.append_child(as_node(&slider2))?;
+
+    let row4 = create_input_row(
+        &ctx,
+        "Base Reserves:",
+        "final-base-reserves",
+        "",
+        Some("Quote Reserves:"),
+        Some("final-quote-reserves"),
+        Some(""),
+    )?;
+    final_section.append_child(as_node(&row4))?;
+
+    let final_invariant_row = create_input_row(
+        &ctx,
+        "Invariant k:",
+        "final-invariant",
+        "",
+        None,
+        None,
+        None,
+    )?;
+    final_section.append_child(as_node(&final_invariant_row))?;
+
+    container.append_child(as_node(&final_section))?;
+
+    // Delta Section
+    let delta_section = create_section(&ctx, "Delta Section (Wallet Perspective)")?;
+    delta_section.set_attribute("id", &ctx.id("delta-section"))?;
+
+    if let Some(header) = delta_section.first_element_child() {
+        let indicator = document.create_element("span")?;
+        indicator.set_attribute("id", &ctx.id("delta-no-trade"))?;
+        indicator.set_attribute("class", "cpmm-no-trade cpmm-hidden")?;
+        indicator.set_text_content(Some("No trade"));
+        header.append_child(as_node(&indicator))?;
+    }
+
+    let row5 = create_input_row(&ctx, "Price Delta:", "delta-price", "", None, None, None)?;
+    delta_section.append_child(as_node(&row5))?;
+
+    let row6 = create_input_row(
+        &ctx,
+        "Base Reserves Delta:",
+        "delta-base-reserves",
+        "",
+        Some("Quote Reserves Delta:"),
+        Some("delta-quote-reserves"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&row6))?;
+
+    let net_row = create_input_row(
+        &ctx,
+        "Base Net Delta:",
+        "delta-base-net",
+        "",
+        Some("Quote Net Delta:"),
+        Some("delta-quote-net"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&net_row))?;
+
+    let row7 = create_input_row(
+        &ctx,
+        "Base Fee Collected:",
+        "fee-base-collected",
+        "",
+        Some("Quote Fee Collected:"),
+        Some("fee-quote-collected"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&row7))?;
+
+    let lp_fee_row = create_input_row(
+        &ctx,
+        "Base LP Fee:",
+        "fee-base-lp",
+        "",
+        Some("Quote LP Fee:"),
+        Some("fee-quote-lp"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&lp_fee_row))?;
+
+    let protocol_fee_row = create_input_row(
+        &ctx,
+        "Base Protocol Fee:",
+        "fee-base-protocol",
+        "",
+        Some("Quote Protocol Fee:"),
+        Some("fee-quote-protocol"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&protocol_fee_row))?;
+
+    // Breakdown of the paid (left) and received (right) sides; labels name
+    // the token of each side
+    let breakdown_rows = [
+        ("gross", "Paid Gross:", "Received Gross:"),
+        ("fee", "Paid Fee:", "Received Fee:"),
+        ("net", "Paid Net:", "Received Net:"),
+    ];
+    for (part, paid_label, received_label) in breakdown_rows {
+        let received_id = format!("breakdown-received-{}", part);
+        let row = create_input_row(
+            &ctx,
+            paid_label,
+            &format!("breakdown-paid-{}", part),
+            "",
+            Some(received_label),
+            Some(&received_id),
+            Some(""),
+        )?;
+        delta_section.append_child(as_node(&row))?;
+    }
+
+    // The same move at the current fee (left) and an alternate fee (right)
+    let alt_fee_row = create_input_row(
+        &ctx,
+        "Alternate Fee %:",
+        "alt-fee-percent",
+        &display(state.borrow().alt_fee_percent),
+        None,
+        None,
+        None,
+    )?;
+    delta_section.append_child(as_node(&alt_fee_row))?;
+
+    let comparison_rows = [
+        ("Base Net Delta:", "compare-base-net", "At Alternate Fee:"),
+        ("Quote Net Delta:", "compare-quote-net", "At Alternate Fee:"),
+        ("Base Fee:", "compare-base-fee", "At Alternate Fee:"),
+        ("Quote Fee:", "compare-quote-fee", "At Alternate Fee:"),
+    ];
+    for (label, id, alt_label) in comparison_rows {
+        let alt_id = format!("{}-alt", id);
+        let row = create_input_row(
+            &ctx,
+            label,
+            id,
+            "",
+            Some(alt_label),
+            Some(&alt_id),
+            Some(""),
+        )?;
+        delta_section.append_child(as_node(&row))?;
+    }
+
+    let accrued_lp_row = create_input_row(
+        &ctx,
+        "Accrued Base LP Fees:",
+        "accrued-base-lp",
+        "",
+        Some("Accrued Quote LP Fees:"),
+        Some("accrued-quote-lp"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&accrued_lp_row))?;
+
+    let accrued_protocol_row = create_input_row(
+        &ctx,
+        "Accrued Base Protocol Fees:",
+        "accrued-base-protocol",
+        "",
+        Some("Accrued Quote Protocol Fees:"),
+        Some("accrued-quote-protocol"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&accrued_protocol_row))?;
+
+    let liquidity_delta_row = create_input_row(
+        &ctx,
+        "Base Liquidity Delta:",
+        "delta-base-liquidity",
+        "",
+        Some("Quote Liquidity Delta:"),
+        Some("delta-quote-liquidity"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&liquidity_delta_row))?;
+
+    let slippage_row = create_input_row(
+        &ctx,
+        "Execution Price:",
+        "delta-execution-price",
+        "",
+        Some("Price Impact:"),
+        Some("delta-price-impact"),
+        Some(""),
+    )?;
+    if let Some(execution_price) =
+        slippage_row.query_selector(&format!("[id=\"{}\"]", ctx.id("delta-execution-price")))?
+    {
+        execution_price.set_attribute("class", "cpmm-execution-price")?;
+    }
+    delta_section.append_child(as_node(&slippage_row))?;
+
+    let warn_row = create_input_row(
+        &ctx,
+        "Impact Warning Threshold:",
+        "impact-warn-threshold",
+        &display(state.borrow().warn_threshold),
+        None,
+        None,
+        None,
+    )?;
+    delta_section.append_child(as_node(&warn_row))?;
+
+    let tolerance_row = create_input_row(
+        &ctx,
+        "Slippage Tolerance %:",
+        "slippage-tolerance",
+        &display(state.borrow().slippage_tolerance_percent),
+        None,
+        None,
+        None,
+    )?;
+    delta_section.append_child(as_node(&tolerance_row))?;
+
+    let worst_case_row = create_input_row(
+        &ctx,
+        "Minimum Received:",
+        "delta-min-received",
+        "",
+        Some("Maximum Sent:"),
+        Some("delta-max-sent"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&worst_case_row))?;
+
+    let return_row = create_input_row(
+        &ctx,
+        "Price Change %:",
+        "delta-price-change",
+        "",
+        Some("Log Return:"),
+        Some("delta-log-return"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&return_row))?;
+
+    let max_impact_row = create_input_row(
+        &ctx,
+        "Max Impact %:",
+        "max-impact-percent",
+        &display(state.borrow().max_impact_percent),
+        None,
+        None,
+        None,
+    )?;
+    delta_section.append_child(as_node(&max_impact_row))?;
+
+    let max_trade_row = create_input_row(
+        &ctx,
+        "Max Base In:",
+        "max-impact-base-in",
+        "",
+        Some("Max Quote In:"),
+        Some("max-impact-quote-in"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&max_trade_row))?;
+
+    let drift_row = create_input_row(
+        &ctx,
+        "Log Drift:",
+        "delta-log-drift",
+        "",
+        Some("Cumulative Log Drift:"),
+        Some("cumulative-log-drift"),
+        Some(""),
+    )?;
+    delta_section.append_child(as_node(&drift_row))?;
+
+    let execute_row = create_button_row(&ctx, "Execute Trade", "execute-trade")?;
+    delta_section.append_child(as_node(&execute_row))?;
+
+    let csv_row = create_button_row(&ctx, "Download CSV", "download-csv")?;
+    delta_section.append_child(as_node(&csv_row))?;
+
+    let copy_row = create_button_row(&ctx, "Copy results", "copy-results")?;
+    let copy_status = document.create_element("span")?;
+    copy_status.set_attribute("id", &ctx.id("copy-status"))?;
+    copy_status.set_attribute("class", "cpmm-copy-status")?;
+    copy_row.append_child(as_node(&copy_status))?;
+    delta_section.append_child(as_node(&copy_row))?;
+
+    container.append_child(as_node(&delta_section))?;
+
+    // LP Break-even Section
+    let breakeven_section = create_section(&ctx, "LP Break-even Section")?;
+
+    let row8 = create_input_row(
+        &ctx,
+        "Fee APR %:",
+        "breakeven-fee-apr",
+        &display(state.borrow().fee_apr_percent),
+        Some("Impermanent Loss %:"),
+        Some("breakeven-il"),
+        Some(&display(state.borrow().il_percent)),
+    )?;
+    breakeven_section.append_child(as_node(&row8))?;
+
+    let row9 = create_input_row(
+        &ctx,
+        "Break-even Days:",
+        "breakeven-days",
+        "",
+        None,
+        None,
+        None,
+    )?;
+    breakeven_section.append_child(as_node(&row9))?;
+
+    container.append_child(as_node(&breakeven_section))?;
+
+    // Holder Effect Section
+    let holder_section = create_section(&ctx, "Holder Effect Section")?;
+
+    let holder_row = create_input_row(
+        &ctx,
+        "Horizon (days):",
+        "holder-horizon-days",
+        &display(state.borrow().horizon_days),
+        Some("Annualized Effect %:"),
+        Some("holder-annualized-effect"),
+        Some(""),
+    )?;
+    holder_section.append_child(as_node(&holder_row))?;
+
+    container.append_child(as_node(&holder_section))?;
+
+    // Collateral Section
+    let collateral_section = create_section(&ctx, "Collateral Section")?;
+
+    let row10 = create_input_row(
+        &ctx,
+        "Collateral (Base):",
+        "collateral-base",
+        &display(state.borrow().collateral_base),
+        Some("Debt (Quote):"),
+        Some("debt-quote"),
+        Some(&display(state.borrow().debt_quote)),
+    )?;
+    collateral_section.append_child(as_node(&row10))?;
+
+    let row11 = create_input_row(
+        &ctx,
+        "Ratio Before:",
+        "collateral-ratio-before",
+        "",
+        Some("Ratio After:"),
+        Some("collateral-ratio-after"),
+        Some(""),
+    )?;
+    collateral_section.append_child(as_node(&row11))?;
+
+    container.append_child(as_node(&collateral_section))?;
+
+    // Oracle Section
+    let oracle_section = create_section(&ctx, "Oracle Section")?;
+
+    let row12 = create_input_row(
+        &ctx,
+        "External Price:",
+        "external-price",
+        &display(state.borrow().external_price),
+        Some("Blended Price:"),
+        Some("blended-price"),
+        Some(""),
+    )?;
+    oracle_section.append_child(as_node(&row12))?;
+
+    let weight_slider = create_slider_row(
+        &ctx,
+        "External Price Weight",
+        "oracle-weight-slider",
+        state.borrow().oracle_weight,
+        0.001,
+    )?;
+    oracle_section.append_child(as_node(&weight_slider))?;
+
+    container.append_child(as_node(&oracle_section))?;
+
+    // Arbitrage Half-life Section
+    let halflife_section = create_section(&ctx, "Arbitrage Half-life Section")?;
+
+    let halflife_row = create_input_row(
+        &ctx,
+        "Gap Closed per Step %:",
+        "halflife-close-percent",
+        &display(state.borrow().arb_close_percent),
+        Some("Half-life (steps):"),
+        Some("halflife-steps"),
+        Some(""),
+    )?;
+    halflife_section.append_child(as_node(&halflife_row))?;
+
+    container.append_child(as_node(&halflife_section))?;
+
+    // Fee Revenue Section
+    let revenue_section = create_section(&ctx, "Fee Revenue Section")?;
+
+    let row13 = create_input_row(
+        &ctx,
+        "Base Volume:",
+        "revenue-base-volume",
+        &display(state.borrow().base_volume),
+        Some("Volume Elasticity:"),
+        Some("revenue-elasticity"),
+        Some(&display(state.borrow().volume_elasticity)),
+    )?;
+    revenue_section.append_child(as_node(&row13))?;
+
+    let row14 = create_input_row(
+        &ctx,
+        "Optimal Fee %:",
+        "revenue-optimal-fee",
+        "",
+        Some("Max Revenue:"),
+        Some("revenue-max"),
+        Some(""),
+    )?;
+    revenue_section.append_child(as_node(&row14))?;
+
+    container.append_child(as_node(&revenue_section))?;
+
+    // Volume Rebate Section
+    let rebate_section = create_section(&ctx, "Volume Rebate Section")?;
+
+    let row15 = create_input_row(
+        &ctx,
+        "Cumulative Volume:",
+        "rebate-volume",
+        &display(state.borrow().cumulative_volume),
+        Some("Rebate Tiers (volume:fee %):"),
+        Some("rebate-tiers"),
+        Some(&format_rebate_tiers(&state.borrow().rebate_tiers)),
+    )?;
+    rebate_section.append_child(as_node(&row15))?;
+
+    let row16 = create_input_row(
+        &ctx,
+        "Effective Fee %:",
+        "rebate-effective-fee",
+        "",
+        None,
+        None,
+        None,
+    )?;
+    rebate_section.append_child(as_node(&row16))?;
+
+    container.append_child(as_node(&rebate_section))?;
+
+    // Rebase Section
+    let rebase_section = create_section(&ctx, "Rebase Section")?;
+
+    let rebase_factor_row = create_input_row(
+        &ctx,
+        "Base Rebase Factor:",
+        "rebase-base-factor",
+        &display(state.borrow().base_rebase_factor),
+        Some("Quote Rebase Factor:"),
+        Some("rebase-quote-factor"),
+        Some(&display(state.borrow().quote_rebase_factor)),
+    )?;
+    rebase_section.append_child(as_node(&rebase_factor_row))?;
+
+    let rebase_result_row = create_input_row(
+        &ctx,
+        "Rebased Price:",
+        "rebase-price",
+        "",
+        Some("Rebased Liquidity:"),
+        Some("rebase-liquidity"),
+        Some(""),
+    )?;
+    rebase_section.append_child(as_node(&rebase_result_row))?;
+
+    container.append_child(as_node(&rebase_section))?;
+
+    // Implied Liquidity Section
+    let implied_section = create_section(&ctx, "Implied Liquidity Section")?;
+
+    let implied_quote_row = create_input_row(
+        &ctx,
+        "Base Sold:",
+        "implied-trade-size",
+        &display(state.borrow().implied_trade_size),
+        Some("Quoted Impact %:"),
+        Some("implied-impact-percent"),
+        Some(&display(state.borrow().implied_impact_percent)),
+    )?;
+    implied_section.append_child(as_node(&implied_quote_row))?;
+
+    let implied_result_row = create_input_row(
+        &ctx,
+        "Implied Liquidity:",
+        "implied-liquidity",
+        "",
+        None,
+        None,
+        None,
+    )?;
+    implied_section.append_child(as_node(&implied_result_row))?;
+
+    container.append_child(as_node(&implied_section))?;
+
+    // Quote Calibration Section
+    let quotes_section = create_section(&ctx, "Quote Calibration Section")?;
+
+    let quotes_row = create_input_row(
+        &ctx,
+        "Bid:",
+        "quotes-bid",
+        &display(state.borrow().quoted_bid),
+        Some("Ask:"),
+        Some("quotes-ask"),
+        Some(&display(state.borrow().quoted_ask)),
+    )?;
+    quotes_section.append_child(as_node(&quotes_row))?;
+
+    let quotes_result_row = create_input_row(
+        &ctx,
+        "Implied Mid:",
+        "quotes-mid",
+        "",
+        Some("Implied Fee %:"),
+        Some("quotes-fee-percent"),
+        Some(""),
+    )?;
+    quotes_section.append_child(as_node(&quotes_result_row))?;
+
+    container.append_child(as_node(&quotes_section))?;
+
+    // Inventory Skew Section
+    let skew_section = create_section(&ctx, "Inventory Skew Section")?;
+
+    let skew_row = create_input_row(
+        &ctx,
+        "Inventory (-1 to 1):",
+        "skew-inventory",
+        &display(state.borrow().inventory_fraction),
+        Some("Skew Factor:"),
+        Some("skew-factor"),
+        Some(&display(state.borrow().skew_factor)),
+    )?;
+    skew_section.append_child(as_node(&skew_row))?;
+
+    let skew_result_row = create_input_row(
+        &ctx,
+        "Skewed Bid:",
+        "skew-bid",
+        "",
+        Some("Skewed Ask:"),
+        Some("skew-ask"),
+        Some(""),
+    )?;
+    skew_section.append_child(as_node(&skew_result_row))?;
+
+    container.append_child(as_node(&skew_section))?;
+
+    // Fee Simulation Section
+    let sim_section = create_section(&ctx, "Fee Simulation Section")?;
+
+    let sim_row = create_input_row(
+        &ctx,
+        "Seed:",
+        "sim-seed",
+        &state.borrow().sim_seed.to_string(),
+        Some("Trades:"),
+        Some("sim-steps"),
+        Some(&state.borrow().sim_steps.to_string()),
+    )?;
+    sim_section.append_child(as_node(&sim_row))?;
+
+    let sim_button_row = create_button_row(&ctx, "Simulate Trades", "simulate-fees")?;
+    sim_section.append_child(as_node(&sim_button_row))?;
+
+    let sim_result_row = create_input_row(
+        &ctx,
+        "Simulated Base Fees:",
+        "sim-base-fees",
+        "",
+        Some("Simulated Quote Fees:"),
+        Some("sim-quote-fees"),
+        Some(""),
+    )?;
+    sim_section.append_child(as_node(&sim_result_row))?;
+
+    container.append_child(as_node(&sim_section))?;
+
+    // LP Section
+    let lp_section = create_section(&ctx, "LP Section")?;
+
+    let lp_row = create_input_row(
+        &ctx,
+        "Impermanent Loss %:",
+        "lp-impermanent-loss",
+        "",
+        None,
+        None,
+        None,
+    )?;
+    lp_section.append_child(as_node(&lp_row))?;
+
+    container.append_child(as_node(&lp_section))?;
+
+    // LP Economics Section
+    let economics_section = create_section(&ctx, "LP Economics Section")?;
+
+    let volume_row = create_input_row(
+        &ctx,
+        "Daily Volume (quote):",
+        "lp-daily-volume",
+        &display(state.borrow().daily_volume),
+        None,
+        None,
+        None,
+    )?;
+    economics_section.append_child(as_node(&volume_row))?;
+
+    let revenue_row = create_input_row(
+        &ctx,
+        "Daily Fee Revenue:",
+        "lp-daily-revenue",
+        "",
+        Some("Fee APR:"),
+        Some("lp-fee-apr"),
+        Some(""),
+    )?;
+    economics_section.append_child(as_node(&revenue_row))?;
+
+    container.append_child(as_node(&economics_section))?;
+
+    // Routing Section: base -> intermediate in the initial pool, then
+    // intermediate -> quote in a second pool
+    let route_section = create_section(&ctx, "Routing Section")?;
+
+    let route_pool_row = create_input_row(
+        &ctx,
+        "Second Pool Liquidity:",
+        "route-liquidity",
+        &display(state.borrow().route_liquidity),
+        Some("Second Pool Price:"),
+        Some("route-price"),
+        Some(&display(state.borrow().route_price)),
+    )?;
+    route_section.append_child(as_node(&route_pool_row))?;
+
+    let route_amount_row = create_input_row(
+        &ctx,
+        "Base In:",
+        "route-amount",
+        &display(state.borrow().route_amount),
+        Some("Route Output:"),
+        Some("route-output"),
+        Some(""),
+    )?;
+    route_section.append_child(as_node(&route_amount_row))?;
+
+    let route_rate_row = create_input_row(
+        &ctx,
+        "Route Rate:",
+        "route-rate",
+        "",
+        Some("Direct Rate:"),
+        Some("route-direct-rate"),
+        Some(""),
+    )?;
+    route_section.append_child(as_node(&route_rate_row))?;
+
+    container.append_child(as_node(&route_section))?;
+
+    // Composition Section
+    let composition_section = create_section(&ctx, "Composition Section (Value by Price)")?;
+
+    let composition_chart = create_chart(
+        &ctx,
+        &[
+            ("composition-base", "cpmm-chart-base"),
+            ("composition-quote", "cpmm-chart-quote"),
+        ],
+        CHART_WIDTH,
+        CHART_HEIGHT,
+    )?;
+    composition_section.append_child(as_node(&composition_chart))?;
+
+    container.append_child(as_node(&composition_section))?;
+
+    // Chart Section
+    let chart_section = create_section(&ctx, "Chart Section (x·y = k)")?;
+    let curve_chart = create_curve_chart(&ctx, CHART_WIDTH, CHART_HEIGHT)?;
+    chart_section.append_child(as_node(&curve_chart))?;
+    container.append_child(as_node(&chart_section))?;
+
+    // Initial computation; restored prices may need orienting
+    sync_price_inputs(&ctx, &state.borrow());
+    sync_history_buttons(&ctx);
+    update_computed_fields(&ctx, &state.borrow());
+
+    // Attach event listeners. In an amount mode, changing the pool or the fee
+    // re-solves the final price from the trade amount
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "initial-liquidity", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            {
+                let mut s = state_clone.borrow_mut();
+                s.initial_liquidity = v;
+                resolve_final_price(&ctx_clone, &mut s);
+            }
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    // Changing the slider mapping moves the thumbs, never the prices
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "slider-center-price", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            {
+                let mut s = state_clone.borrow_mut();
+                s.center_price = s.oriented_price(v);
+            }
+            let s = state_clone.borrow();
+            sync_slider_positions(&ctx_clone, &s);
+            refresh(&ctx_clone, &s);
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "slider-decades", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().decades = v;
+            let s = state_clone.borrow();
+            sync_slider_positions(&ctx_clone, &s);
+            refresh(&ctx_clone, &s);
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "initial-price", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            {
+                let mut s = state_clone.borrow_mut();
+                s.initial_price = s.snap_to_tick(s.oriented_price(v));
+                resolve_final_price(&ctx_clone, &mut s);
+            }
+            let s = state_clone.borrow();
+            let slider_val = s.slider_for_price(s.initial_price);
+            set_input_value(&ctx_clone, "initial-price-slider", &slider_val.to_string());
+            refresh(&ctx_clone, &s);
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "initial-price-slider", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed {
+            let price = {
+                let s = state_clone.borrow();
+                let previous = s.slider_for_price(s.initial_price);
+                if !slider_delta_exceeds_min(previous, v, s.slider_jitter_threshold()) {
+                    return;
+                }
+                s.snap_to_tick(s.price_for_slider(v))
+            };
+            {
+                let mut s = state_clone.borrow_mut();
+                s.initial_price = price;
+                resolve_final_price(&ctx_clone, &mut s);
+            }
+            let s = state_clone.borrow();
+            set_input_value(
+                &ctx_clone,
+                "initial-price",
+                &s.display(s.oriented_price(price)),
+            );
+            refresh(&ctx_clone, &s);
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "fee-percent", move |value| {
+        // Invalid input is reported and the last valid fee kept
+        let parsed = parse_user_percent(&value);
+        let checked = parsed
+            .ok_or_else(|| "Fee % must be a number".to_string())
+            .and_then(validate_fee);
+        match checked {
+            Ok(v) => {
+                set_field_error(&ctx_clone, "fee-percent", None);
+                {
+                    let mut s = state_clone.borrow_mut();
+                    s.fee_percent = v;
+                    resolve_final_price(&ctx_clone, &mut s);
+                }
+                refresh(&ctx_clone, &state_clone.borrow());
+            }
+            Err(message) => set_field_error(&ctx_clone, "fee-percent", Some(&message)),
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "alt-fee-percent", move |value| {
+        if let Some(v) = parse_user_percent(&value).and_then(|v| validate_fee(v).ok()) {
+            state_clone.borrow_mut().alt_fee_percent = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "protocol-fee-percent", move |value| {
+        let parsed = parse_user_percent(&value);
+        if let Some(v) = parsed
+            && (0.0..=100.0).contains(&v)
+        {
+            toggle_protocol_fee(&mut state_clone.borrow_mut(), v / 100.0);
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "final-liquidity", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().final_liquidity = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "final-price", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            {
+                let mut s = state_clone.borrow_mut();
+                s.final_price = s.snap_to_tick(s.oriented_price(v));
+            }
+            backfill_trade_amount(&ctx_clone, &mut state_clone.borrow_mut());
+            let s = state_clone.borrow();
+            let slider_val = s.slider_for_price(s.final_price);
+            set_input_value(&ctx_clone, "final-price-slider", &slider_val.to_string());
+            refresh(&ctx_clone, &s);
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "final-price-slider", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed {
+            let price = {
+                let s = state_clone.borrow();
+                let previous = s.slider_for_price(s.final_price);
+                if !slider_delta_exceeds_min(previous, v, s.slider_jitter_threshold()) {
+                    return;
+                }
+                s.snap_to_tick(s.price_for_slider(v))
+            };
+            state_clone.borrow_mut().final_price = price;
+            backfill_trade_amount(&ctx_clone, &mut state_clone.borrow_mut());
+            let s = state_clone.borrow();
+            set_input_value(
+                &ctx_clone,
+                "final-price",
+                &s.display(s.oriented_price(price)),
+            );
+            update_execution_price(&ctx_clone, &s);
+            refresh(&ctx_clone, &s);
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "breakeven-fee-apr", move |value| {
+        let parsed = parse_user_percent(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().fee_apr_percent = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "breakeven-il", move |value| {
+        let parsed = parse_user_percent(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().il_percent = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "holder-horizon-days", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().horizon_days = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "collateral-base", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().collateral_base = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "debt-quote", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().debt_quote = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "external-price", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            let price = state_clone.borrow().oriented_price(v);
+            state_clone.borrow_mut().external_price = price;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "oracle-weight-slider", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed {
+            state_clone.borrow_mut().oracle_weight = v.clamp(0.0, 1.0);
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(
+        &ctx,
+        &mut listeners,
+        "halflife-close-percent",
+        move |value| {
+            let parsed = parse_user_percent(&value);
+            if let Some(v) = parsed
+                && (0.0..=100.0).contains(&v)
+            {
+                state_clone.borrow_mut().arb_close_percent = v;
+                refresh(&ctx_clone, &state_clone.borrow());
+            }
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "revenue-base-volume", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().base_volume = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "revenue-elasticity", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().volume_elasticity = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "rebate-volume", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().cumulative_volume = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "rebase-base-factor", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().base_rebase_factor = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "rebase-quote-factor", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().quote_rebase_factor = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "implied-trade-size", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().implied_trade_size = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(
+        &ctx,
+        &mut listeners,
+        "implied-impact-percent",
+        move |value| {
+            let parsed = parse_user_percent(&value);
+            if let Some(v) = parsed
+                && (0.0..=100.0).contains(&v)
+            {
+                state_clone.borrow_mut().implied_impact_percent = v;
+                refresh(&ctx_clone, &state_clone.borrow());
+            }
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "quotes-bid", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().quoted_bid = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "quotes-ask", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().quoted_ask = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "skew-inventory", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && (-1.0..=1.0).contains(&v)
+        {
+            state_clone.borrow_mut().inventory_fraction = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "skew-factor", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().skew_factor = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(
+        &ctx,
+        &mut listeners,
+        "impact-warn-threshold",
+        move |value| {
+            let parsed = parse_user_number(&value);
+            if let Some(v) = parsed
+                && v.is_finite()
+                && v >= 0.0
+            {
+                state_clone.borrow_mut().warn_threshold = v;
+                refresh(&ctx_clone, &state_clone.borrow());
+            }
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "slippage-tolerance", move |value| {
+        let parsed = parse_user_percent(&value);
+        if let Some(v) = parsed
+            && (0.0..=100.0).contains(&v)
+        {
+            state_clone.borrow_mut().slippage_tolerance_percent = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "sim-seed", move |value| {
+        if let Some(v) = parse_user_count(&value) {
+            state_clone.borrow_mut().sim_seed = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "sim-steps", move |value| {
+        if let Some(v) = parse_user_count(&value)
+            && v <= MAX_SIMULATION_STEPS as u64
+        {
+            state_clone.borrow_mut().sim_steps = v as usize;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "max-impact-percent", move |value| {
+        let parsed = parse_user_percent(&value);
+        if let Some(v) = parsed
+            && (0.0..=100.0).contains(&v)
+        {
+            state_clone.borrow_mut().max_impact_percent = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "route-liquidity", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().route_liquidity = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "route-price", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().route_price = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "route-amount", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().route_amount = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "lp-daily-volume", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+        {
+            state_clone.borrow_mut().daily_volume = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "rebate-tiers", move |value| {
+        if let Some(tiers) = parse_rebate_tiers(&value) {
+            state_clone.borrow_mut().rebate_tiers = tiers;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "range-lower", move |value| {
+        let (_, upper) = state_clone.borrow().displayed_range();
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v >= 0.0
+            && v < upper
+        {
+            state_clone.borrow_mut().set_displayed_range(v, upper);
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "range-upper", move |value| {
+        let (lower, _) = state_clone.borrow().displayed_range();
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v > lower
+        {
+            state_clone.borrow_mut().set_displayed_range(lower, v);
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "display-precision", move |value| {
+        if let Some(v) = parse_user_count(&value)
+            && v <= MAX_DISPLAY_PRECISION as u64
+        {
+            state_clone.borrow_mut().display_precision = v as usize;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_checkbox_listener(&ctx, &mut listeners, "display-grouped", move |checked| {
+        state_clone.borrow_mut().grouped = checked;
+        let s = state_clone.borrow();
+        sync_price_inputs(&ctx_clone, &s);
+        refresh(&ctx_clone, &s);
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_radio_listener(
+        &ctx,
+        &mut listeners,
+        "display-mode",
+        DisplayMode::OPTIONS,
+        move |value| {
+            if let Some(mode) = DisplayMode::parse(&value) {
+                state_clone.borrow_mut().display_mode = mode;
+                refresh(&ctx_clone, &state_clone.borrow());
+            }
+        },
+    );
+
+    // Entering tick mode, or changing the spacing in it, snaps both prices
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_checkbox_listener(&ctx, &mut listeners, "tick-mode", move |checked| {
+        {
+            let mut s = state_clone.borrow_mut();
+            s.tick_mode = checked;
+            s.snap_prices_to_ticks();
+        }
+        let s = state_clone.borrow();
+        sync_price_inputs(&ctx_clone, &s);
+        refresh(&ctx_clone, &s);
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "tick-spacing", move |value| {
+        if let Some(v) = parse_user_count(&value)
+            && (1..=u64::from(MAX_TICK_SPACING)).contains(&v)
+        {
+            {
+                let mut s = state_clone.borrow_mut();
+                s.tick_spacing = v as u32;
+                s.snap_prices_to_ticks();
+            }
+            let s = state_clone.borrow();
+            sync_price_inputs(&ctx_clone, &s);
+            refresh(&ctx_clone, &s);
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_radio_listener(
+        &ctx,
+        &mut listeners,
+        "fee-mode",
+        FeeMode::OPTIONS,
+        move |value| {
+            if let Some(mode) = FeeMode::parse(&value) {
+                state_clone.borrow_mut().fee_mode = mode;
+                backfill_trade_amount(&ctx_clone, &mut state_clone.borrow_mut());
+                refresh(&ctx_clone, &state_clone.borrow());
+            }
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_select_listener(&ctx, &mut listeners, "amm-model", move |value| {
+        if let Some(model) = AmmModel::parse(&value) {
+            state_clone.borrow_mut().model = model;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    // Switching modes keeps the final price and back-fills the amount
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_select_listener(&ctx, &mut listeners, "entry-mode", move |value| {
+        if let Some(mode) = EntryMode::parse(&value) {
+            state_clone.borrow_mut().entry_mode = mode;
+            backfill_trade_amount(&ctx_clone, &mut state_clone.borrow_mut());
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    // In an amount mode the amount drives the final price and its slider
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "trade-amount", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            {
+                let mut s = state_clone.borrow_mut();
+                s.trade_amount = v;
+                resolve_final_price(&ctx_clone, &mut s);
+            }
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_input_listener(&ctx, &mut listeners, "amplification", move |value| {
+        let parsed = parse_user_number(&value);
+        if let Some(v) = parsed
+            && v.is_finite()
+            && v > 0.0
+        {
+            state_clone.borrow_mut().amplification = v;
+            refresh(&ctx_clone, &state_clone.borrow());
+        }
+    });
+
+    // Weights sum to 1, so editing one sets the other
+    for (id, other_id) in [
+        ("weight-base", "weight-quote"),
+        ("weight-quote", "weight-base"),
+    ] {
+        let ctx_clone = ctx.clone();
+        let state_clone = Rc::clone(&state);
+        attach_input_listener(&ctx, &mut listeners, id, move |value| {
+            let parsed = parse_user_number(&value);
+            if let Some(v) = parsed
+                && v > 0.0
+                && v < 1.0
+            {
+                {
+                    let mut s = state_clone.borrow_mut();
+                    if id == "weight-base" {
+                        s.weight_base = v;
+                        s.weight_quote = 1.0 - v;
+                    } else {
+                        s.weight_quote = v;
+                        s.weight_base = 1.0 - v;
+                    }
+                }
+                let s = state_clone.borrow();
+                set_input_value(&ctx_clone, other_id, &s.display(1.0 - v));
+                refresh(&ctx_clone, &s);
+            }
+        });
+    }
+
+    // Up/Down arrows nudge numeric inputs, ten times as far with Shift
+    for id in NUDGE_INPUTS {
+        let ctx_clone = ctx.clone();
+        let state_clone = Rc::clone(&state);
+        attach_keydown_listener(&ctx, &mut listeners, id, move |event| {
+            let direction = match event.key().as_str() {
+                "ArrowUp" => 1,
+                "ArrowDown" => -1,
+                _ => return,
+            };
+            event.prevent_default();
+            nudge_input(&ctx_clone, &state_clone, id, direction, event.shift_key());
+        });
+    }
+
+    // Inverting only changes how prices are shown; the pool state is untouched,
+    // so toggling twice restores the original display exactly
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_click_listener(&ctx, &mut listeners, "invert-toggle", move || {
+        state_clone.borrow_mut().inverted ^= true;
+        backfill_trade_amount(&ctx_clone, &mut state_clone.borrow_mut());
+        let s = state_clone.borrow();
+        sync_price_inputs(&ctx_clone, &s);
+        refresh(&ctx_clone, &s);
+    });
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_click_listener(&ctx, &mut listeners, "theme-toggle", move || {
+        let theme = state_clone.borrow().theme.next();
+        state_clone.borrow_mut().theme = theme;
+        refresh(&ctx_clone, &state_clone.borrow());
+    });
+
+    // Rows stack into one column while the window is narrow
+    if let Some(window) = web_sys::window() {
+        let ctx_clone = ctx.clone();
+        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            apply_layout(&ctx_clone);
+        }) as Box<dyn Fn(_)>);
+        add_listener(&mut listeners, &window, "resize", "window", closure);
+    }
+    apply_layout(&ctx);
+
+    // Auto follows changes of the system color scheme live
+    if let Some(query) = dark_scheme_query() {
+        let ctx_clone = ctx.clone();
+        let state_clone = Rc::clone(&state);
+        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            apply_theme(&ctx_clone, state_clone.borrow().theme);
+        }) as Box<dyn Fn(_)>);
+        add_listener(&mut listeners, &query, "change", DARK_SCHEME_QUERY, closure);
+    }
+
+    // Executing the trade makes the final price and liquidity the new initial ones
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_click_listener(&ctx, &mut listeners, "execute-trade", move || {
+        {
+            let mut s = state_clone.borrow_mut();
+            if let Ok(outcome) = model_outcome(&s) {
+                accrue_fees(&mut s, &outcome.trade);
+            }
+            s.session.cumulative_log_drift += log_drift(s.initial_price, s.final_price);
+            s.initial_price = s.final_price;
+            s.initial_liquidity = s.final_liquidity;
+        }
+        let s = state_clone.borrow();
+        set_input_value(
+            &ctx_clone,
+            "initial-liquidity",
+            &s.display(s.initial_liquidity),
+        );
+        let slider_val = s.slider_for_price(s.initial_price);
+        let displayed = s.oriented_price(s.initial_price);
+        set_input_value(&ctx_clone, "initial-price", &s.display(displayed));
+        set_input_value(&ctx_clone, "initial-price-slider", &slider_val.to_string());
+        refresh(&ctx_clone, &s);
+    });
+
+    for (id, redo) in [("undo", false), ("redo", true)] {
+        let ctx_clone = ctx.clone();
+        let state_clone = Rc::clone(&state);
+        attach_click_listener(&ctx, &mut listeners, id, move || {
+            step_history(&ctx_clone, &state_clone, redo);
+        });
+    }
+
+    // Ctrl+Z undoes and Ctrl+Shift+Z redoes anywhere in the calculator
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    let closure = Closure::wrap(Box::new(move |event: web_sys::Event| {
+        let Ok(event) = event.dyn_into::<web_sys::KeyboardEvent>() else {
+            return;
+        };
+        if (event.ctrl_key() || event.meta_key()) && event.key().eq_ignore_ascii_case("z") {
+            event.prevent_default();
+            step_history(&ctx_clone, &state_clone, event.shift_key());
+        }
+    }) as Box<dyn Fn(_)>);
+    add_listener(&mut listeners, &container, "keydown", "history", closure);
+
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_click_listener(&ctx, &mut listeners, "reset-defaults", move || {
+        reset_state(&ctx_clone, &state_clone);
+    });
+
+    let state_clone = Rc::clone(&state);
+    attach_click_listener(&ctx, &mut listeners, "download-csv", move || {
+        download_sweep_csv(&state_clone.borrow());
+    });
+
+    // One rejection handler serves every copy; the click listener owns it, so
+    // destroy_ui releases it with the listener
+    let ctx_clone = ctx.clone();
+    let on_copy_rejected: Closure<dyn FnMut(JsValue)> =
+        Closure::new(move |reason: JsValue| copy_failed(&ctx_clone, &reason));
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_click_listener(&ctx, &mut listeners, "copy-results", move || {
+        copy_results(&ctx_clone, &state_clone.borrow(), &on_copy_rejected);
+    });
+
+    // Replays a seeded random walk from the initial pool and shows its fees
+    let ctx_clone = ctx.clone();
+    let state_clone = Rc::clone(&state);
+    attach_click_listener(&ctx, &mut listeners, "simulate-fees", move || {
+        {
+            let mut s = state_clone.borrow_mut();
+            if let Ok(start) = CpmmState::new(s.initial_liquidity, s.initial_price) {
+                let moves = random_walk_moves(s.sim_seed, s.sim_steps, SIMULATION_STEP_VOL);
+                let (base_fees, quote_fees) = simulate_fees(start, &moves, s.fee_percent / 100.0);
+                s.session.sim_base_fees = base_fees;
+                s.session.sim_quote_fees = quote_fees;
+            }
+        }
+        refresh(&ctx_clone, &state_clone.borrow());
+    });
+
+    log_info("CPMM Calculator: UI initialized successfully");
+    Ok(Instance {
+        container,
+        ctx,
+        state,
+        listeners,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_all_matches_display_values() {
+        let state = AppState::default();
+        let (display, micros) = compute_all(&state).unwrap();
+        assert_eq!(display, compute_display_values(&state).unwrap());
+        // Timing is only measured at the verbose log level
+        assert_eq!(micros, 0.0);
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn parse(json: &str) -> JsValue {
+        js_sys::JSON::parse(json).unwrap()
+    }
+
+    /// Drops the query string a test wrote, host parameters included.
+    fn clear_page_query() {
+        let window = web_sys::window().unwrap();
+        let path = window.location().pathname().unwrap();
+        window
+            .history()
+            .unwrap()
+            .replace_state_with_url(&JsValue::NULL, "", Some(&path))
+            .unwrap();
+    }
+
+    fn field(root: &Element, prefix: &str, id: &str) -> HtmlInputElement {
+        root.query_selector(&format!("[id=\"{}-{}\"]", prefix, id))
+            .unwrap()
+            .unwrap()
+            .dyn_into()
+            .unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_validate_config_valid() {
+        let errors = js_sys::Array::from(&validate_config(parse(
+            r#"{"initialLiquidity": 500, "initialPrice": 2, "feePercent": 1}"#,
+        )));
+        assert_eq!(errors.length(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_validate_config_invalid() {
+        let errors = js_sys::Array::from(&validate_config(parse(
+            r#"{"initialLiquidity": -1, "feePercent": 100, "decades": 0}"#,
+        )));
+        assert_eq!(errors.length(), 3);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_build_info_has_version() {
+        let info = build_info();
+        let version = js_sys::Reflect::get(&info, &"version".into())
+            .unwrap()
+            .as_string()
+            .unwrap();
+        assert!(!version.is_empty());
+        assert!(js_sys::Array::is_array(
+            &js_sys::Reflect::get(&info, &"features".into()).unwrap()
+        ));
+    }
+
+    fn get_f64(object: &JsValue, key: &str) -> f64 {
+        js_sys::Reflect::get(object, &key.into())
+            .unwrap()
+            .as_f64()
+            .unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_compute_trade_buy_base() {
+        // L=1000, P: 1.0 -> 1.21 buys ~90.91 base for 100 quote
+        let result = compute_trade(1000.0, 1.0, 1.21, 0.3);
+        assert!((get_f64(&result, "baseWalletDelta") - 1000.0 / 11.0).abs() < 1e-9);
+        assert!((get_f64(&result, "quoteWalletDelta") + 100.0).abs() < 1e-9);
+        assert!(get_f64(&result, "quoteFeeCollected") > 0.0);
+        assert_eq!(get_f64(&result, "baseFeeCollected"), 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_compute_trade_invalid_input() {
+        let result = compute_trade(0.0, 1.0, 1.21, 0.3);
+        let error = js_sys::Reflect::get(&result, &"error".into()).unwrap();
+        assert!(error.as_string().is_some());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_trade_to_marginal_price_export() {
+        let result = js_trade_to_marginal_price(1000.0, 1.0, 1.21, 0.3);
+        assert!((get_f64(&result, "priceDelta") - 0.21).abs() < 1e-9);
+        let direct = compute_trade(1000.0, 1.0, 1.21, 0.3);
+        for key in [
+            "baseWalletDelta",
+            "quoteWalletDeltaNet",
+            "quoteFeeCollected",
+        ] {
+            assert_eq!(get_f64(&result, key), get_f64(&direct, key));
+        }
+
+        for invalid in [
+            js_trade_to_marginal_price(1000.0, 1.0, -1.0, 0.3),
+            js_trade_to_marginal_price(1000.0, 1.0, 1.21, 100.0),
+        ] {
+            let error = js_sys::Reflect::get(&invalid, &"error".into()).unwrap();
+            assert!(error.as_string().is_some());
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_attach_input_listener_missing_id_is_noop() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let ctx = UiContext {
+            document: document.clone(),
+            root: document.create_element("div").unwrap(),
+            prefix: "cpmm-listener-test".to_string(),
+            on_change: Rc::default(),
+            debouncer: Rc::new(Debouncer::new(DEFAULT_DEBOUNCE_MS)),
+            history: Rc::new(RefCell::new(History::new(
+                AppState::default(),
+                HISTORY_DEPTH,
+            ))),
+        };
+        let mut listeners = Vec::new();
+        attach_input_listener(&ctx, &mut listeners, "no-such-input", |_| {
+            panic!("listener must not be attached");
+        });
+        assert!(listeners.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_dropped_listener_leaves_color_scheme_query() {
+        // The media query list outlives the calculator, so a theme listener
+        // must be unregistered when its instance is destroyed
+        let Some(query) = dark_scheme_query() else {
+            return;
+        };
+        let calls = Rc::new(Cell::new(0));
+        let calls_clone = Rc::clone(&calls);
+        let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            calls_clone.set(calls_clone.get() + 1);
+        }) as Box<dyn Fn(_)>);
+        let mut listeners = Vec::new();
+        add_listener(&mut listeners, &query, "change", DARK_SCHEME_QUERY, closure);
+
+        let change = || web_sys::Event::new("change").unwrap();
+        query.dispatch_event(&change()).unwrap();
+        assert_eq!(calls.get(), 1);
+
+        drop(listeners);
+        query.dispatch_event(&change()).unwrap();
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_destroy_ui_without_inject_is_noop() {
+        destroy_ui("cpmm-never-injected");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_inject_ui_into_detached_element() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let container = target.first_element_child().unwrap();
+        assert_eq!(container.class_name(), "cpmm-calculator");
+
+        // Computed fields are filled even though the target is not in the page
+        let reserves = field(&target, &key, "initial-base-reserves");
+        assert!(!reserves.value().is_empty());
+
+        destroy_ui(&key);
+        assert_eq!(target.child_element_count(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_inject_before_parentless_anchor_fails_cleanly() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let anchor = document.create_element("div").unwrap();
+        let key = "cpmm-parentless-anchor";
+
+        assert!(inject_before(&document, &anchor, key, false).is_err());
+        assert!(INSTANCES.with(|instances| !instances.borrow().contains_key(key)));
+        assert!(
+            document
+                .get_element_by_id(&format!("{}-initial-price", key))
+                .is_none()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_inject_before_parentless_anchor_falls_back_to_body() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let anchor = document.create_element("div").unwrap();
+        let key = "cpmm-parentless-fallback";
+
+        inject_before(&document, &anchor, key, true).unwrap();
+        let body = document.body().unwrap();
+        let container = body.last_element_child().unwrap();
+        assert!(container.class_list().contains("cpmm-calculator"));
+        assert!(
+            document
+                .get_element_by_id(&format!("{}-initial-price", key))
+                .is_some()
+        );
+
+        destroy_ui(key);
+        assert!(
+            document
+                .get_element_by_id(&format!("{}-initial-price", key))
+                .is_none()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_set_prices_move_field_and_slider() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+
+        let initial_slider = field(&target, &key, "initial-price-slider").value();
+        let final_slider = field(&target, &key, "final-price-slider").value();
+
+        assert!(set_initial_price(&key, 2.0));
+        assert!(set_final_price(&key, 3.0));
+        assert_eq!(
+            field(&target, &key, "initial-price").value(),
+            format_number(2.0, 6)
+        );
+        assert_eq!(
+            field(&target, &key, "final-price").value(),
+            format_number(3.0, 6)
+        );
+        assert_ne!(
+            field(&target, &key, "initial-price-slider").value(),
+            initial_slider
+        );
+        assert_ne!(
+            field(&target, &key, "final-price-slider").value(),
+            final_slider
+        );
+
+        // Invalid prices and unknown prefixes change nothing
+        assert!(!set_initial_price(&key, 0.0));
+        assert!(!set_final_price(&key, f64::NAN));
+        assert!(!set_final_price("cpmm-never-injected", 2.0));
+        assert_eq!(
+            field(&target, &key, "final-price").value(),
+            format_number(3.0, 6)
+        );
+
+        destroy_ui(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_instances_are_independent() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let first = document.create_element("div").unwrap();
+        let second = document.create_element("div").unwrap();
+        let first_key = inject_ui_into_element(first.clone()).unwrap();
+        let second_key = inject_ui_into_element(second.clone()).unwrap();
+        assert_ne!(first_key, second_key);
+        set_debounce_ms(&first_key, 0);
+
+        let second_before = field(&second, &second_key, "initial-base-reserves").value();
+
+        let liquidity = field(&first, &first_key, "initial-liquidity");
+        liquidity.set_value("4000");
+        liquidity
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+
+        let first_after = field(&first, &first_key, "initial-base-reserves").value();
+        assert_eq!(first_after, format_number(4000.0, 6));
+        assert_eq!(
+            field(&second, &second_key, "initial-base-reserves").value(),
+            second_before
+        );
+
+        destroy_ui(&first_key);
+        destroy_ui(&second_key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_invert_toggle_twice_restores_display() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+
+        let ids = [
+            "initial-price",
+            "initial-price-slider",
+            "final-price",
+            "final-price-slider",
+            "initial-base-reserves",
+            "final-quote-reserves",
+            "delta-price",
+            "delta-base-net",
+            "delta-execution-price",
+            "delta-price-impact",
+            "delta-price-change",
+            "delta-log-return",
+            "delta-log-drift",
+            "external-price",
+            "blended-price",
+            "range-lower",
+            "range-upper",
+        ];
+        let values = || -> Vec<String> {
+            ids.iter()
+                .map(|id| field(&target, &key, id).value())
+                .collect()
+        };
+        let toggle: web_sys::HtmlElement = target
+            .query_selector(&format!("[id=\"{}-invert-toggle\"]", key))
+            .unwrap()
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+
+        let original = values();
+        toggle.click();
+        assert_eq!(
+            field(&target, &key, "initial-price").value(),
+            format_number(invert_price(1.0), 6)
+        );
+        assert_eq!(
+            field(&target, &key, "final-price").value(),
+            format_number(invert_price(1.1), 6)
+        );
+        toggle.click();
+        assert_eq!(values(), original);
+
+        destroy_ui(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_state_restored_after_reinject() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        target.set_id("cpmm-persist-test");
+        clear_saved_state("cpmm-persist-test");
+
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let liquidity = field(&target, &key, "initial-liquidity");
+        liquidity.set_value("2500");
+        liquidity
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        destroy_ui(&key);
+
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let restored = field(&target, &key, "initial-liquidity").value();
+        assert_eq!(restored, format_number(2500.0, 6));
+        destroy_ui(&key);
+
+        clear_saved_state("cpmm-persist-test");
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let reset = field(&target, &key, "initial-liquidity").value();
+        assert_eq!(
+            reset,
+            format_number(AppState::default().initial_liquidity, 6)
+        );
+        destroy_ui(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_reset_ui_restores_defaults() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+
+        let default_slider = field(&target, &key, "final-price-slider").value();
+        let final_price = field(&target, &key, "final-price");
+        final_price.set_value("3");
+        final_price
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        let liquidity = field(&target, &key, "initial-liquidity");
+        liquidity.set_value("4000");
+        liquidity
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        assert_ne!(
+            field(&target, &key, "final-price-slider").value(),
+            default_slider
+        );
+
+        reset_ui(&key);
+
+        let state = INSTANCES.with(|instances| instances.borrow()[&key].state.borrow().clone());
+        assert_eq!(state, AppState::default());
+        assert_eq!(
+            field(&target, &key, "initial-liquidity").value(),
+            format_number(AppState::default().initial_liquidity, 6)
+        );
+        assert_eq!(
+            field(&target, &key, "final-price-slider").value(),
+            default_slider
+        );
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_slider_settings_move_thumbs_not_prices() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+
+        let final_price = field(&target, &key, "final-price");
+        final_price.set_value("10");
+        final_price
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        let slider_value = || -> f64 {
+            field(&target, &key, "final-price-slider")
+                .value()
+                .parse()
+                .unwrap()
+        };
+        let before = slider_value();
+
+        let decades = field(&target, &key, "slider-decades");
+        decades.set_value("6");
+        decades
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        let widened = slider_value();
+        assert!(widened < before && widened > 0.5);
+        // The step shrinks with the wider range
+        assert_eq!(
+            field(&target, &key, "final-price-slider").step(),
+            slider_step_for_decades(6.0).to_string()
+        );
+
+        // Non-positive decades are rejected
+        decades.set_value("0");
+        decades
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        assert_eq!(slider_value(), widened);
+
+        // Centering on the price puts its thumb in the middle
+        let center = field(&target, &key, "slider-center-price");
+        center.set_value("10");
+        center
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        assert!((slider_value() - 0.5).abs() < 1e-9);
+        let state = INSTANCES.with(|instances| instances.borrow()[&key].state.borrow().clone());
+        assert_eq!(state.final_price, 10.0);
+        assert_eq!(state.decades, 6.0);
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_import_json_applies_or_rejects_whole_scenario() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let current =
+            || INSTANCES.with(|instances| instances.borrow()[&key].state.borrow().clone());
+
+        let before = current();
+        assert!(!import_json(
+            &key,
+            r#"{"state": {"finalPrice": 3, "feePercent": 150}}"#
+        ));
+        assert_eq!(current(), before);
+
+        let scenario = AppState {
+            initial_liquidity: 4000.0,
+            final_price: 3.0,
+            ..AppState::default()
+        };
+        assert!(import_json(&key, &scenario_json(&scenario)));
+        assert_eq!(current(), scenario);
+        assert_eq!(
+            field(&target, &key, "initial-liquidity").value(),
+            format_number(4000.0, 6)
+        );
+        assert_eq!(
+            field(&target, &key, "final-price-slider").value(),
+            scenario.slider_for_price(3.0).to_string()
+        );
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_set_on_change_receives_updates() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+
+        let received: Rc<RefCell<Vec<JsValue>>> = Rc::default();
+        let received_clone = Rc::clone(&received);
+        let callback = Closure::<dyn Fn(JsValue)>::new(move |scenario: JsValue| {
+            received_clone.borrow_mut().push(scenario);
+        });
+        set_on_change(&key, Some(callback.as_ref().clone().unchecked_into()));
+        // Fires once right away for the current values
+        assert_eq!(received.borrow().len(), 1);
+        set_debounce_ms(&key, 0);
+
+        let final_price = field(&target, &key, "final-price");
+        final_price.set_value("2");
+        final_price
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        let last = received.borrow().last().cloned().unwrap();
+        let get = |object: &JsValue, key: &str| js_sys::Reflect::get(object, &key.into()).unwrap();
+        let state = get(&last, "state");
+        assert_eq!(get(&state, "finalPrice").as_f64(), Some(2.0));
+        let trade = get(&last, "trade");
+        assert!(get(&trade, "baseWalletDelta").as_f64().unwrap() > 0.0);
+        assert!(get(&trade, "quoteFeeCollected").as_f64().unwrap() > 0.0);
+
+        // Removing the callback stops the updates
+        let count = received.borrow().len();
+        set_on_change(&key, None);
+        final_price.set_value("3");
+        final_price
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        assert_eq!(received.borrow().len(), count);
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_invalid_fee_shows_error_and_keeps_state() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let fee_percent =
+            || INSTANCES.with(|instances| instances.borrow()[&key].state.borrow().fee_percent);
+        let error = || {
+            target
+                .query_selector(&format!("#{}-fee-percent-error", key))
+                .unwrap()
+                .unwrap()
+                .text_content()
+                .unwrap_or_default()
+        };
+        let fee = field(&target, &key, "fee-percent");
+        let type_fee = |value: &str| {
+            fee.set_value(value);
+            fee.dispatch_event(&web_sys::Event::new("input").unwrap())
+                .unwrap();
+        };
+
+        type_fee("0.5");
+        assert_eq!(fee_percent(), 0.5);
+        type_fee("100");
+        assert_eq!(fee_percent(), 0.5);
+        assert!(fee.class_list().contains("cpmm-input-error"));
+        assert!(!error().is_empty());
+        type_fee("-1");
+        assert_eq!(fee_percent(), 0.5);
+        type_fee("1");
+        assert_eq!(fee_percent(), 1.0);
+        assert!(!fee.class_list().contains("cpmm-input-error"));
+        assert!(error().is_empty());
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_trade_amount_sets_final_price() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let mode: web_sys::HtmlSelectElement = target
+            .query_selector(&format!("#{}-entry-mode", key))
+            .unwrap()
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+        let final_price = field(&target, &key, "final-price");
+        let before = final_price.value();
+
+        mode.set_value("quoteIn");
+        mode.dispatch_event(&web_sys::Event::new("change").unwrap())
+            .unwrap();
+        // Switching modes back-fills the amount without moving the price
+        assert_eq!(final_price.value(), before);
+
+        let amount = field(&target, &key, "trade-amount");
+        amount.set_value("50");
+        amount
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        let price: f64 = final_price.value().parse().unwrap();
+        let expected = CpmmState::new(1000.0, 1.0)
+            .unwrap()
+            .swap_exact_quote_in(50.0 / 1.003, 0.0)
+            .1
+            .price;
+        assert!((price - expected).abs() < 1e-5);
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_initial_price_change_resolves_amount_mode_price() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let mode: web_sys::HtmlSelectElement = target
+            .query_selector(&format!("#{}-entry-mode", key))
+            .unwrap()
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+        mode.set_value("quoteIn");
+        mode.dispatch_event(&web_sys::Event::new("change").unwrap())
+            .unwrap();
+        let amount = field(&target, &key, "trade-amount");
+        amount.set_value("50");
+        amount
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+
+        let initial_price = field(&target, &key, "initial-price");
+        initial_price.set_value("2");
+        initial_price
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        // The amount stays put and the final price follows the new pool
+        assert_eq!(amount.value(), "50");
+        let price: f64 = field(&target, &key, "final-price").value().parse().unwrap();
+        let expected = CpmmState::new(1000.0, 2.0)
+            .unwrap()
+            .swap_exact_quote_in(50.0 / 1.003, 0.0)
+            .1
+            .price;
+        assert!((price - expected).abs() < 1e-5);
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_calculator_handle() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let wrapper = document.create_element("div").unwrap();
+        let anchor = document.create_element("div").unwrap();
+        anchor.set_id("handle-anchor");
+        wrapper.append_child(&anchor).unwrap();
+        document.body().unwrap().append_child(&wrapper).unwrap();
+
+        let calculator = CpmmCalculator::new("handle-anchor").unwrap();
+        assert_eq!(calculator.prefix(), "handle-anchor");
+        assert!(calculator.set_initial_price(1.05));
+        assert!(!calculator.set_initial_price(-1.0));
+        assert!(calculator.set_fee(1.0));
+        assert!(!calculator.set_fee(100.0));
+
+        // The result is the trade from 1.05 to the default final price 1.1
+        let result = calculator.get_result();
+        let price_delta = js_sys::Reflect::get(&result, &"priceDelta".into())
+            .unwrap()
+            .as_f64()
+            .unwrap();
+        assert!((price_delta - 0.05).abs() < 1e-9);
+        assert_eq!(
+            field(&wrapper, "handle-anchor", "initial-price").value(),
+            "1.050000"
+        );
+
+        calculator.destroy();
+        assert!(
+            document
+                .get_element_by_id("handle-anchor-initial-price")
+                .is_none()
+        );
+        wrapper.remove();
+        clear_saved_state("handle-anchor");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_fee_mode_radio_moves_fee_to_output() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let state = || INSTANCES.with(|instances| instances.borrow()[&key].state.borrow().clone());
+        assert_eq!(state().fee_mode, FeeMode::OnInput);
+
+        let on_output = field(&target, &key, "fee-mode-onOutput");
+        on_output.set_checked(true);
+        on_output
+            .dispatch_event(&web_sys::Event::new("change").unwrap())
+            .unwrap();
+        assert_eq!(state().fee_mode, FeeMode::OnOutput);
+        // The default trade buys base, so the fee is now taken in base
+        let trade = model_outcome(&state()).unwrap().trade;
+        assert!(trade.base_fee_collected > 0.0);
+        assert_eq!(trade.quote_fee_collected, 0.0);
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_rapid_typing_collapses_into_one_recompute() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+
+        let received: Rc<RefCell<Vec<JsValue>>> = Rc::default();
+        let received_clone = Rc::clone(&received);
+        let callback = Closure::<dyn Fn(JsValue)>::new(move |scenario: JsValue| {
+            received_clone.borrow_mut().push(scenario);
+        });
+        set_on_change(&key, Some(callback.as_ref().clone().unchecked_into()));
+        assert_eq!(received.borrow().len(), 1);
+
+        let final_price = field(&target, &key, "final-price");
+        for value in ["2", "3", "4"] {
+            final_price.set_value(value);
+            final_price
+                .dispatch_event(&web_sys::Event::new("input").unwrap())
+                .unwrap();
+        }
+        // The state follows every keystroke but the recompute waits
+        let ctx = INSTANCES.with(|instances| instances.borrow()[&key].ctx.clone());
+        assert_eq!(received.borrow().len(), 1);
+
+        ctx.debouncer.flush();
+        assert_eq!(received.borrow().len(), 2);
+        let last = received.borrow().last().cloned().unwrap();
+        let state = js_sys::Reflect::get(&last, &"state".into()).unwrap();
+        let final_price_value = js_sys::Reflect::get(&state, &"finalPrice".into()).unwrap();
+        assert_eq!(final_price_value.as_f64(), Some(4.0));
+        ctx.debouncer.flush();
+        assert_eq!(received.borrow().len(), 2);
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_js_slider_center() {
+        let price = js_slider_to_price(0.5, 10.0, 2.0).unwrap();
+        assert!((price - 10.0).abs() < 1e-10);
+        assert_eq!(js_price_to_slider(10.0, 10.0, 2.0).unwrap(), 0.5);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_js_slider_price_conversion_roundtrip() {
+        for price in [0.001, 0.1, 1.0, 10.0, 100.0, 1000.0] {
+            let slider = js_price_to_slider(price, 1.0, 3.0).unwrap();
+            let recovered = js_slider_to_price(slider, 1.0, 3.0).unwrap();
+            assert!((price - recovered).abs() / price < 0.001);
+        }
+        // Prices beyond the range clamp to the slider's ends
+        assert_eq!(js_price_to_slider(1e6, 1.0, 3.0).unwrap(), 1.0);
+        assert_eq!(js_price_to_slider(1e-6, 1.0, 3.0).unwrap(), 0.0);
+        assert!(js_slider_to_price(0.5, 1.0, 0.0).is_err());
+        assert!(js_price_to_slider(1.0, 1.0, -1.0).is_err());
+        assert!(js_price_to_slider(1.0, 0.0, 3.0).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_undo_redo_buttons_step_through_edits() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        set_debounce_ms(&key, 0);
+        let final_price =
+            || INSTANCES.with(|instances| instances.borrow()[&key].state.borrow().final_price);
+        let button = |id: &str| -> web_sys::HtmlElement {
+            target
+                .query_selector(&format!("#{}-{}", key, id))
+                .unwrap()
+                .unwrap()
+                .dyn_into()
+                .unwrap()
+        };
+        let input = field(&target, &key, "final-price");
+        let type_price = |value: &str| {
+            input.set_value(value);
+            input
+                .dispatch_event(&web_sys::Event::new("input").unwrap())
+                .unwrap();
+        };
+        let before = final_price();
+        assert!(button("undo").has_attribute("disabled"));
+
+        type_price("2");
+        type_price("3");
+        button("undo").click();
+        assert_eq!(final_price(), 2.0);
+        assert_eq!(input.value().parse::<f64>().unwrap(), 2.0);
+        button("undo").click();
+        assert_eq!(final_price(), before);
+        button("redo").click();
+        assert_eq!(final_price(), 2.0);
+
+        // A new edit drops the redo step
+        type_price("4");
+        assert!(button("redo").has_attribute("disabled"));
+        button("redo").click();
+        assert_eq!(final_price(), 4.0);
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_slider_drag_updates_execution_price_immediately() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let target = document.create_element("div").unwrap();
+        let key = inject_ui_into_element(target.clone()).unwrap();
+        let execution_price = field(&target, &key, "delta-execution-price");
+        let before = execution_price.value();
+
+        // The recompute is still pending, but the execution price follows the drag
+        let slider = field(&target, &key, "final-price-slider");
+        slider.set_value("0.7");
+        slider
+            .dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+        assert_ne!(execution_price.value(), before);
+        assert_ne!(execution_price.value(), UNDEFINED_DISPLAY);
+        assert!(
+            execution_price
+                .class_list()
+                .contains("cpmm-execution-price")
+        );
+
+        destroy_ui(&key);
+        clear_saved_state(&key);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_input_change_updates_page_query() {
+        let window = web_sys::window().unwrap();
+        let path = window.location().pathname().unwrap();
+        window
+            .history()
+            .unwrap()
+            .replace_state_with_url(&JsValue::NULL, "", Some(&format!("{}?page=7", path)))
+            .unwrap();
+        let document = window.document().unwrap();
+        let first = document.create_element("div").unwrap();
+        let second = document.create_element("div").unwrap();
+        let first_key = inject_ui_into_element(first.clone()).unwrap();
+        let second_key = inject_ui_into_element(second.clone()).unwrap();
+        set_debounce_ms(&first_key, 0);
+
+        let fee = field(&first, &first_key, "fee-percent");
+        fee.set_value("1");
+        fee.dispatch_event(&web_sys::Event::new("input").unwrap())
+            .unwrap();
+
+        // The host page's parameter survives and each calculator has its own keys
+        let params = web_sys::UrlSearchParams::new_with_str(&page_query().unwrap()).unwrap();
+        assert_eq!(params.get("page").as_deref(), Some("7"));
+        assert_eq!(
+            params.get(&query_key(&first_key, "fee")).as_deref(),
+            Some("1")
+        );
+        assert_ne!(
+            params.get(&query_key(&second_key, "fee")).as_deref(),
+            Some("1")
+        );
+        destroy_ui(&first_key);
+        destroy_ui(&second_key);
+        clear_page_query();
+    }
+}